@@ -0,0 +1,43 @@
+use sea_orm::{entity::prelude::*, DeleteMany};
+use serde::{Deserialize, Serialize};
+
+/// Data reported by a running measurement, stored as it is acquired. Once the measurement finishes its data is in the
+/// measurements table and the chunks are removed.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "chunks")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+
+    pub measurement: i32,
+
+    /// Order of the chunk within the measurement
+    pub sequence: i32,
+
+    pub data: Json,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+impl RelationTrait for Relation {
+
+    fn def(&self) -> RelationDef {
+
+        panic!("No RelationDef")
+
+    }
+
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+
+    pub fn delete_by_measurement(measurement: i32) -> DeleteMany<Entity> {
+
+        Self::delete_many().filter(Column::Measurement.eq(measurement))
+
+    }
+
+}
@@ -0,0 +1,43 @@
+use sea_orm::{entity::prelude::*, DeleteMany};
+use serde::{Deserialize, Serialize};
+
+/// A measurement waiting in the queue. The measurement itself, with its parameters, lives in the measurements table.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "jobs")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+
+    pub measurement: i32,
+
+    /// Place in the queue, lower runs first
+    pub position: i32,
+
+    /// The job is not run before this time, measurements that continue later are scheduled this way
+    pub not_before: Option<DateTimeLocal>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+impl RelationTrait for Relation {
+
+    fn def(&self) -> RelationDef {
+
+        panic!("No RelationDef")
+
+    }
+
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+
+    pub fn delete_by_id(id: i32) -> DeleteMany<Entity> {
+
+        Self::delete_many().filter(Column::Id.eq(id))
+
+    }
+
+}
@@ -1,3 +1,5 @@
 pub use sea_orm;
 
+pub mod chunk;
+pub mod job;
 pub mod measurement;
\ No newline at end of file
@@ -20,6 +20,9 @@ pub struct Model {
 
     pub data: Option<Json>,
 
+    /// Why the measurement failed, including the WGFMU library error and warning text
+    pub error: Option<String>,
+
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -45,6 +48,12 @@ pub enum Status {
     Done,
     #[sea_orm(string_value = "E")]
     Error,
+    #[sea_orm(string_value = "Q")]
+    Queued,
+    #[sea_orm(string_value = "C")]
+    Cancelled,
+    #[sea_orm(string_value = "A")]
+    Aborted,
 }
 
 #[derive(Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
@@ -58,6 +67,18 @@ pub enum Category {
     Stdp,
     #[sea_orm(string_value = "STC")]
     StdpCollection,
+    #[sea_orm(string_value = "IV")]
+    IvSweep,
+    #[sea_orm(string_value = "PIV")]
+    PulsedIv,
+    #[sea_orm(string_value = "EN")]
+    Endurance,
+    #[sea_orm(string_value = "RT")]
+    Retention,
+    #[sea_orm(string_value = "PPF")]
+    Ppf,
+    #[sea_orm(string_value = "EPSC")]
+    Epsc,
 }
 
 impl Entity {
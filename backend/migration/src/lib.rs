@@ -1,11 +1,21 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220921_000001_create_measurements_table;
+mod m20261018_000001_create_jobs_table;
+mod m20261018_000002_create_chunks_table;
+mod m20261018_000003_add_measurement_error;
+mod m20261018_000004_add_job_not_before;
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220921_000001_create_measurements_table::Migration)]
+        vec![
+            Box::new(m20220921_000001_create_measurements_table::Migration),
+            Box::new(m20261018_000001_create_jobs_table::Migration),
+            Box::new(m20261018_000002_create_chunks_table::Migration),
+            Box::new(m20261018_000003_add_measurement_error::Migration),
+            Box::new(m20261018_000004_add_job_not_before::Migration),
+        ]
     }
 }
@@ -0,0 +1,36 @@
+use entity::{sea_orm::{EntityTrait, Schema, DbBackend}, job};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000001_create_jobs_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the Job table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(get_seaorm_create_stmt(job::Entity)).await
+    }
+
+    // Define how to rollback this migration: Drop the Job table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(get_seaorm_drop_stmt(job::Entity)).await
+    }
+}
+
+fn get_seaorm_create_stmt<E: EntityTrait>(e: E) -> TableCreateStatement {
+    let schema = Schema::new(DbBackend::Sqlite);
+    schema
+        .create_table_from_entity(e)
+        .if_not_exists()
+        .to_owned()
+
+}
+
+fn get_seaorm_drop_stmt<E: EntityTrait>(e: E) -> TableDropStatement {
+    Table::drop().table(e).if_exists().to_owned()
+}
@@ -0,0 +1,36 @@
+use entity::{sea_orm::{EntityTrait, Schema, DbBackend}, chunk};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000002_create_chunks_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the Chunk table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(get_seaorm_create_stmt(chunk::Entity)).await
+    }
+
+    // Define how to rollback this migration: Drop the Chunk table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(get_seaorm_drop_stmt(chunk::Entity)).await
+    }
+}
+
+fn get_seaorm_create_stmt<E: EntityTrait>(e: E) -> TableCreateStatement {
+    let schema = Schema::new(DbBackend::Sqlite);
+    schema
+        .create_table_from_entity(e)
+        .if_not_exists()
+        .to_owned()
+
+}
+
+fn get_seaorm_drop_stmt<E: EntityTrait>(e: E) -> TableDropStatement {
+    Table::drop().table(e).if_exists().to_owned()
+}
@@ -0,0 +1,42 @@
+use entity::measurement;
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000003_add_measurement_error" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the error column to the Measurement table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The measurements table is created from the entity, new databases already have the column
+        if manager.has_column("measurements", "error").await? {
+            return Ok(());
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(measurement::Entity)
+                    .add_column(ColumnDef::new(measurement::Column::Error).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the error column.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(measurement::Entity)
+                    .drop_column(measurement::Column::Error)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,42 @@
+use entity::job;
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000004_add_job_not_before" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the not_before column to the Job table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The jobs table is created from the entity, new databases already have the column
+        if manager.has_column("jobs", "not_before").await? {
+            return Ok(());
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(job::Entity)
+                    .add_column(ColumnDef::new(job::Column::NotBefore).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the not_before column.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(job::Entity)
+                    .drop_column(job::Column::NotBefore)
+                    .to_owned(),
+            )
+            .await
+    }
+}
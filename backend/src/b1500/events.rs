@@ -0,0 +1,63 @@
+use entity::measurement::Status;
+use sea_orm::JsonValue;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::instrument::Progress;
+
+// Measurement events, everything that happens to a measurement while it is queued or running is published here so
+// clients can follow it live. Events are not stored, a subscriber only sees what is published after it subscribed.
+
+/// Events kept for subscribers that fall behind, older ones are dropped
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MeasurementEvent {
+    /// The status of the measurement changed
+    Status(Status),
+    Progress(Progress),
+    /// Points measured since the previous chunk, or a finished part of the measurement
+    Data(JsonValue),
+}
+
+impl MeasurementEvent {
+    /// Whether no more events follow this one
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            MeasurementEvent::Status(Status::Done | Status::Error | Status::Cancelled | Status::Aborted)
+        )
+    }
+
+    /// Formats the event as a server-sent event, named after its kind
+    pub fn to_sse(&self) -> String {
+        let (name, data) = match self {
+            MeasurementEvent::Status(status) => ("status", serde_json::to_string(status)),
+            MeasurementEvent::Progress(progress) => ("progress", serde_json::to_string(progress)),
+            MeasurementEvent::Data(data) => ("data", serde_json::to_string(data)),
+        };
+
+        format!("event: {}\ndata: {}\n\n", name, data.unwrap_or_default())
+    }
+}
+
+pub struct Events {
+    sender: broadcast::Sender<(i32, MeasurementEvent)>,
+}
+
+impl Events {
+    pub fn new() -> Events {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Events { sender }
+    }
+
+    pub fn publish(&self, measurement: i32, event: MeasurementEvent) {
+        // Nobody listening is not an error
+        let _ = self.sender.send((measurement, event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(i32, MeasurementEvent)> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,567 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+use sea_orm::prelude::DateTimeLocal;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use super::events::{Events, MeasurementEvent};
+use super::wgfmu::driver::{
+    ChannelData, ChannelStatus, ForceVoltageRange, MeasureCurrentRange, MeasureEventMode, MeasureMode,
+    MeasureVoltageRange, Measurement, OperationMode, Res, Status, Step, WarningLevel,
+};
+use super::wgfmu::{Error, WgfmuDriver};
+
+// Instrument manager, it owns the WGFMU driver and hands out exclusive leases to whoever wants to use it. A lease is a
+// driver itself, so measurements just take a `&mut impl WgfmuDriver` and never know about the manager. The manager
+// keeps track of the session, that way the web layer can report the instrument state without touching the driver.
+// Aborting only raises a flag, the lease checks it before every call that starts or prepares work on the sequencer,
+// stops the sequencer and fails the call with `Error::Aborted`. Calls that retrieve data or clean up are never refused.
+// Waiting for the sequencer polls its status instead of blocking in the driver, so the flag is seen while it runs.
+// The lease also keeps the progress of its measurement, from the steps it reports and the sequencer status it reads,
+// and publishes it together with the data reported while measuring.
+
+pub type Driver = Box<dyn WgfmuDriver + Send>;
+
+/// Time between two reads of the sequencer status while waiting for it to finish
+const WAIT_POLL_PERIOD: Duration = Duration::from_millis(200);
+
+/// Session state, as seen from the calls that succeeded
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionState {
+    /// Instrument address of the open session, if any
+    pub session: Option<String>,
+    pub initialized: bool,
+    pub connected: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Holder {
+    /// Who holds the lease, e.g. "pulse measurement"
+    pub name: String,
+    /// Id of the measurement being performed, if any
+    pub measurement: Option<i32>,
+    pub since: DateTimeLocal,
+    pub progress: Progress,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    /// Step being run, for measurements made of several trains or executions
+    pub step: Option<Step>,
+    /// Last sequencer status read while waiting for the current execution
+    pub sequencer: Option<ChannelStatus>,
+    /// Measure events of the current execution, (completed, total)
+    pub events: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Occupation {
+    Idle,
+    Busy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstrumentStatus {
+    pub status: Occupation,
+    pub holder: Option<Holder>,
+    #[serde(flatten)]
+    pub state: SessionState,
+}
+
+pub struct InstrumentManager {
+    driver: Mutex<Driver>,
+    state: Mutex<SessionState>,
+    holder: Mutex<Option<Holder>>,
+    released: Condvar,
+    abort: AtomicBool,
+    events: Events,
+}
+
+impl InstrumentManager {
+    pub fn new(driver: Driver) -> Arc<InstrumentManager> {
+        Arc::new(InstrumentManager {
+            driver: Mutex::new(driver),
+            state: Mutex::new(SessionState::default()),
+            holder: Mutex::new(None),
+            released: Condvar::new(),
+            abort: AtomicBool::new(false),
+            events: Events::new(),
+        })
+    }
+
+    /// Waits until the instrument is free and leases it to `holder`. The lease is given back when dropped.
+    pub fn lease(self: &Arc<Self>, holder: &str) -> Result<Lease, Error> {
+        let mut current = self.holder.lock()?;
+        while current.is_some() {
+            current = self.released.wait(current)?;
+        }
+
+        Ok(self.grant(&mut current, holder))
+    }
+
+    /// Leases the instrument to `holder` if nobody else holds it.
+    pub fn try_lease(self: &Arc<Self>, holder: &str) -> Result<Option<Lease>, Error> {
+        let mut current = self.holder.lock()?;
+        if current.is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.grant(&mut current, holder)))
+    }
+
+    fn grant(self: &Arc<Self>, current: &mut Option<Holder>, holder: &str) -> Lease {
+        info!("Instrument leased to {}", holder);
+        *current = Some(Holder {
+            name: holder.to_string(),
+            measurement: None,
+            since: chrono::Local::now(),
+            progress: Progress::default(),
+        });
+        self.abort.store(false, Ordering::SeqCst);
+
+        Lease {
+            manager: Arc::clone(self),
+            sink: None,
+        }
+    }
+
+    /// Asks the lease used for `measurement` to abort it. Returns false when that measurement is not running.
+    pub fn abort(&self, measurement: i32) -> Result<bool, Error> {
+        let holder = self.holder.lock()?;
+        match holder.as_ref().and_then(|holder| holder.measurement) {
+            Some(id) if id == measurement => {
+                info!("Aborting measurement {}", measurement);
+                self.abort.store(true, Ordering::SeqCst);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Events of the measurements, including the ones run through the leases.
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// Progress of `measurement`, None when it is not running.
+    pub fn progress(&self, measurement: i32) -> Result<Option<Progress>, Error> {
+        Ok(self
+            .holder
+            .lock()?
+            .as_ref()
+            .filter(|holder| holder.measurement == Some(measurement))
+            .map(|holder| holder.progress.clone()))
+    }
+
+    pub fn status(&self) -> Result<InstrumentStatus, Error> {
+        let holder = self.holder.lock()?.clone();
+        Ok(InstrumentStatus {
+            status: match holder {
+                Some(_) => Occupation::Busy,
+                None => Occupation::Idle,
+            },
+            holder,
+            state: self.state.lock()?.clone(),
+        })
+    }
+}
+
+/// Exclusive access to the instrument
+pub struct Lease {
+    manager: Arc<InstrumentManager>,
+    /// Receives the data reported by the measurement, so it can be stored as it is acquired
+    sink: Option<mpsc::Sender<serde_json::Value>>,
+}
+
+impl Lease {
+    /// Sends every piece of data reported from now on to `sink`. Reporting blocks while the sink is full, so the
+    /// lease must not be used from an async context.
+    pub fn set_sink(&mut self, sink: mpsc::Sender<serde_json::Value>) {
+        self.sink = Some(sink);
+    }
+
+    /// Records the measurement the lease is used for, so it shows up in the instrument status.
+    pub fn set_measurement(&self, id: i32) {
+        if let Ok(mut holder) = self.manager.holder.lock() {
+            if let Some(holder) = holder.as_mut() {
+                holder.measurement = Some(id);
+            }
+        }
+    }
+
+    fn update_progress(&self, update: impl FnOnce(&mut Progress)) {
+        if let Ok(mut holder) = self.manager.holder.lock() {
+            if let Some(holder) = holder.as_mut() {
+                update(&mut holder.progress);
+                if let Some(id) = holder.measurement {
+                    self.manager
+                        .events
+                        .publish(id, MeasurementEvent::Progress(holder.progress.clone()));
+                }
+            }
+        }
+    }
+
+    /// Stops the sequencer and fails with `Error::Aborted` if an abort was requested.
+    fn checkpoint(&mut self) -> Res {
+        if !self.manager.abort.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        if let Err(err) = self.manager.driver.lock()?.abort() {
+            warn!("Could not stop the sequencer {:?}", err);
+        }
+        Result::Err(Error::Aborted)
+    }
+
+    /// Performs a call on the driver, `update` records its effect on the session when it succeeds.
+    fn call<T>(
+        &mut self,
+        call: impl FnOnce(&mut Driver) -> Result<T, Error>,
+        update: impl FnOnce(&mut SessionState),
+    ) -> Result<T, Error> {
+        let result = call(&mut *self.manager.driver.lock()?)?;
+        update(&mut *self.manager.state.lock()?);
+        Ok(result)
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        // The holder mutex is never poisoned, nothing can panic while it is locked
+        if let Ok(mut holder) = self.manager.holder.lock() {
+            if let Some(holder) = holder.take() {
+                info!("Instrument released by {}", holder.name);
+            }
+        }
+        self.manager.released.notify_one();
+    }
+}
+
+impl WgfmuDriver for Lease {
+    fn open_session(&mut self, instrument: &str) -> Res {
+        self.call(
+            |d| d.open_session(instrument),
+            |s| s.session = Some(instrument.to_string()),
+        )
+    }
+
+    fn close_session(&mut self) -> Res {
+        self.call(|d| d.close_session(), |s| *s = SessionState::default())
+    }
+
+    fn clear(&mut self) -> Res {
+        self.call(|d| d.clear(), |_| ())
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        self.call(|d| d.get_channel_ids(), |_| ())
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        self.call(|d| d.get_error(), |_| ())
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        self.call(|d| d.get_error_summary(), |_| ())
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        self.call(|d| d.get_warning_summary(), |_| ())
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        self.call(|d| d.set_warning_level(level), |_| ())
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        self.call(|d| d.treat_warnings_as_errors(level), |_| ())
+    }
+
+    fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.create_pattern(pattern, init_v), |_| ())
+    }
+
+    fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.add_vector(pattern, d_time, voltage), |_| ())
+    }
+
+    fn add_vectors(&mut self, pattern: &str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.add_vectors(pattern, d_time, voltage), |_| ())
+    }
+
+    fn set_measure_event(
+        &mut self,
+        pattern: &str,
+        event: &str,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    ) -> Res {
+        self.checkpoint()?;
+        self.call(
+            |d| {
+                d.set_measure_event(
+                    pattern,
+                    event,
+                    time,
+                    points,
+                    interval,
+                    average,
+                    measure_event_mode,
+                )
+            },
+            |_| (),
+        )
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_range_event(pattern, event, time, range), |_| ())
+    }
+
+    fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.add_sequence(chan_id, pattern, count), |_| ())
+    }
+
+    fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.add_sequences(chan_id, pattern, count), |_| ())
+    }
+
+    fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_vector(pattern, time, voltage), |_| ())
+    }
+
+    fn initialize(&mut self) -> Res {
+        self.checkpoint()?;
+
+        // Initializing resets every channel, which also disconnects them
+        self.call(
+            |d| d.initialize(),
+            |s| {
+                s.initialized = true;
+                s.connected.clear();
+            },
+        )
+    }
+
+    fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_operation_mode(chan_id, operation_mode), |_| ())
+    }
+
+    fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_measure_mode(chan_id, mode), |_| ())
+    }
+
+    fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
+        self.call(|d| d.get_measure_mode(chan_id), |_| ())
+    }
+
+    fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
+        self.call(|d| d.get_operation_mode(chan_id), |_| ())
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_force_voltage_range(chan_id, range), |_| ())
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_measure_voltage_range(chan_id, range), |_| ())
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_measure_current_range(chan_id, range), |_| ())
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_force_delay(chan_id, delay), |_| ())
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.set_measure_delay(chan_id, delay), |_| ())
+    }
+
+    fn connect(&mut self, chan_id: usize) -> Res {
+        self.checkpoint()?;
+        self.call(
+            |d| d.connect(chan_id),
+            |s| {
+                if !s.connected.contains(&chan_id) {
+                    s.connected.push(chan_id);
+                }
+            },
+        )
+    }
+
+    fn execute(&mut self) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.execute(), |_| ())?;
+        self.update_progress(|p| {
+            p.sequencer = None;
+            p.events = None;
+        });
+        Ok(())
+    }
+
+    fn wait_until_completed(&mut self) -> Res {
+        // The driver blocks until the sequencer is done, the connected channels are polled instead so an abort
+        // requested meanwhile stops it right away
+        loop {
+            self.checkpoint()?;
+
+            let connected = self.manager.state.lock()?.connected.clone();
+            let mut running = false;
+            for chan_id in connected {
+                let status = self.call(|d| d.get_channel_status(chan_id), |_| ())?;
+                running |= matches!(status.status, Status::Running | Status::RunningIllegal);
+            }
+            if !running {
+                break;
+            }
+
+            std::thread::sleep(WAIT_POLL_PERIOD);
+        }
+
+        self.call(|d| d.wait_until_completed(), |_| ())
+    }
+
+    fn abort(&mut self) -> Res {
+        self.call(|d| d.abort(), |_| ())
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        self.call(|d| d.abort_channel(chan_id), |_| ())
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        // Measurements poll the status while the sequencer runs, so an abort is seen right away
+        self.checkpoint()?;
+        let status = self.call(|d| d.get_channel_status(chan_id), |_| ())?;
+        self.update_progress(|p| p.sequencer = Some(status));
+        Ok(status)
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        self.call(|d| d.get_measure_values(chan_id, current), |_| ())
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        let size = self.call(|d| d.get_completed_measure_event_size(chan_id), |_| ())?;
+        self.update_progress(|p| p.events = Some(size));
+        Ok(size)
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        self.call(|d| d.get_completed_measure_values(chan_id, offset), |_| ())
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        self.call(|d| d.get_channel_data(chan_id), |_| ())
+    }
+
+    fn do_self_calibration(&mut self) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.do_self_calibration(), |_| ())
+    }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        self.checkpoint()?;
+        self.call(|d| d.dc_force_voltage(chan_id, voltage), |_| ())
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        self.call(|d| d.dc_measure_value(chan_id), |_| ())
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        self.call(|d| d.dc_measure_averaged_value(chan_id, points, interval), |_| ())
+    }
+
+    fn report_step(&mut self, step: Step) {
+        self.update_progress(|p| p.step = Some(step));
+    }
+
+    fn report_data(&mut self, data: serde_json::Value) {
+        let measurement = self
+            .manager
+            .holder
+            .lock()
+            .ok()
+            .and_then(|holder| holder.as_ref().and_then(|holder| holder.measurement));
+        if let Some(id) = measurement {
+            self.manager
+                .events
+                .publish(id, MeasurementEvent::Data(data.clone()));
+        }
+
+        if let Some(sink) = &self.sink {
+            if sink.blocking_send(data).is_err() {
+                warn!("The reported data could not be stored, its receiver is gone");
+                self.sink = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+    use crate::b1500::CHANNEL1;
+
+    #[test]
+    fn abort_stops_a_running_sequencer() {
+        let manager = InstrumentManager::new(Box::new(TestWgfmu::new(MemristorParams::default()).unwrap()));
+
+        // A sequence long enough that it only ends early when it is stopped
+        let measuring = Arc::clone(&manager);
+        let run = thread::spawn(move || {
+            let mut lease = measuring.lease("long sequence").unwrap();
+            lease.set_measurement(1);
+            lease.create_pattern("p", 0.0)?;
+            lease.add_vector("p", 60.0, 0.0)?;
+            lease.add_sequence(CHANNEL1, "p", 1)?;
+            lease.initialize()?;
+            lease.connect(CHANNEL1)?;
+            lease.execute()?;
+            lease.wait_until_completed()
+        });
+
+        let status = || manager.driver.lock().unwrap().get_channel_status(CHANNEL1).unwrap();
+        while status().status != Status::Running {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(manager.abort(1).unwrap());
+
+        assert_eq!(run.join().unwrap(), Err(Error::Aborted));
+        let stopped = status();
+        assert_eq!(stopped.status, Status::AbortCompleted);
+        assert!(stopped.elapsed_time < stopped.total_time);
+    }
+}
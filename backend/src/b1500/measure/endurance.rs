@@ -0,0 +1,467 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::b1500::types::{Channels, CurrentRanges, Ranges, VoltageWaveForm, VoltageWaveFormPoint};
+use crate::b1500::utils::add_waveform;
+use crate::b1500::wgfmu::driver::{MeasureEventMode, Measurement, Step};
+use crate::b1500::wgfmu::{self, WgfmuDriver};
+
+use super::{
+    utils::add_range_events, utils::get_measurements, utils::round_10ns, utils::setup_fastiv,
+    utils::wait_until_completed_quietly, Error,
+};
+
+/// Rise and fall time of the pulses
+const EDGE_TIME: f64 = 1e-8;
+
+/// Vectors a pattern can hold, page 1-14, table 1-6 of the B1530A user guide
+const MAX_VECTORS: usize = 2048;
+
+/// Highest amplitude, the whole sequence has to fit the +-5 V force range
+const MAX_AMPLITUDE: f64 = 5.0;
+
+/// Endurance test, the device is SET and RESET over and over and read every `read_every` cycles.
+///
+/// Notes:
+///   _
+///  | |    _          _      ... v_set, v_read
+/// _| |___| |__   ___| |___
+///             | |
+///             |_|           ... v_reset
+///  SET   read RESET  read
+///  |<---- read cycle ---->|
+///
+/// Every pulse and read is followed by `gap` seconds at 0 V, the read cycle reads the LRS after the SET pulse and the
+/// HRS after the RESET one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Endurance {
+    /// Number of SET/RESET cycles
+    pub cycles: usize,
+    /// Amplitude of the SET pulses. (Volts)
+    pub v_set: f64,
+    /// Width of the SET pulses. (seconds)
+    pub set_width: f64,
+    /// Amplitude of the RESET pulses, negative. (Volts)
+    pub v_reset: f64,
+    /// Width of the RESET pulses. (seconds)
+    pub reset_width: f64,
+    /// Amplitude of the reads. (Volts)
+    pub v_read: f64,
+    /// Width of the reads. (seconds)
+    pub read_width: f64,
+    /// Time at 0 V after every pulse and read. (seconds)
+    #[serde(default)]
+    pub gap: f64,
+    /// The resistances are read after every `read_every` cycles
+    #[serde(default = "every_cycle")]
+    pub read_every: usize,
+    pub avg_time: f64,
+    /// Smallest HRS/LRS ratio, the test stops once the memory window closes below it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_window: Option<f64>,
+    /// Cycles run by the sequencer at a time, the memory window is checked after each run
+    #[serde(default = "default_batch")]
+    pub batch: usize,
+    /// Current ranges to switch between during the pulses and the reads, the current is measured at a single range
+    /// otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_ranges: Option<CurrentRanges>,
+}
+
+fn every_cycle() -> usize {
+    1
+}
+
+fn default_batch() -> usize {
+    10_000
+}
+
+/// Resistances read after a number of cycles
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EnduranceCycle {
+    /// SET/RESET cycles run when the device was read
+    pub cycle: usize,
+    /// Resistance read after the RESET pulse. (Ohms)
+    pub hrs: Option<f64>,
+    /// Resistance read after the SET pulse. (Ohms)
+    pub lrs: Option<f64>,
+}
+
+impl EnduranceCycle {
+    /// HRS/LRS ratio, None when either could not be read
+    pub fn window(&self) -> Option<f64> {
+        match (self.hrs, self.lrs) {
+            (Some(hrs), Some(lrs)) if lrs > 0.0 => Some(hrs / lrs),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EnduranceMeasurement {
+    pub cycles: Vec<EnduranceCycle>,
+    /// First cycle whose memory window closed below `min_window`, the test stopped after its batch
+    pub collapsed_at: Option<usize>,
+}
+
+/// Force waveform of a pattern, along with the times it reads at and the (start, end) times of its SET and RESET
+/// pulses
+struct Layout {
+    waveform: VoltageWaveForm,
+    reads: Vec<f64>,
+    pulses: Vec<(f64, f64)>,
+    time: f64,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Layout {
+            waveform: vec![],
+            reads: vec![],
+            pulses: vec![],
+            time: 0.0,
+        }
+    }
+
+    /// Adds a pulse and the gap after it, returns when the pulse starts
+    fn push_pulse(&mut self, amplitude: f64, width: f64, gap: f64) -> f64 {
+        let start = self.time;
+        let points = [(EDGE_TIME, amplitude), (round_10ns(width), amplitude), (EDGE_TIME, 0.0), (round_10ns(gap), 0.0)];
+
+        for (dtime, voltage) in points {
+            if dtime > 0.0 {
+                self.waveform.push(VoltageWaveFormPoint { dtime, voltage });
+                self.time += dtime;
+            }
+        }
+
+        start
+    }
+
+    fn push_cycle(&mut self, endurance: &Endurance, read: bool) {
+        let start = self.push_pulse(endurance.v_set, endurance.set_width, 0.0);
+        self.pulses.push((start, self.time));
+        self.push_gap(endurance.gap);
+        if read {
+            self.push_read(endurance);
+        }
+
+        let start = self.push_pulse(endurance.v_reset, endurance.reset_width, 0.0);
+        self.pulses.push((start, self.time));
+        self.push_gap(endurance.gap);
+        if read {
+            self.push_read(endurance);
+        }
+    }
+
+    fn push_read(&mut self, endurance: &Endurance) {
+        let start = self.push_pulse(endurance.v_read, endurance.read_width, endurance.gap);
+        let (offset, _) = endurance.read_window();
+        self.reads.push(round_10ns(start + EDGE_TIME + offset));
+    }
+
+    fn push_gap(&mut self, gap: f64) {
+        let gap = round_10ns(gap);
+        if gap > 0.0 {
+            self.waveform.push(VoltageWaveFormPoint { dtime: gap, voltage: 0.0 });
+            self.time += gap;
+        }
+    }
+}
+
+impl Endurance {
+    /// Whether the test can be run
+    pub fn is_valid(&self) -> bool {
+        let amplitudes = [self.v_set, self.v_reset, self.v_read];
+
+        self.cycles >= 1
+            && self.read_every >= 1
+            && self.batch >= 1
+            && self.v_set > 0.0
+            && self.v_reset < 0.0
+            && amplitudes.iter().all(|v| v.abs() <= MAX_AMPLITUDE)
+            && [self.set_width, self.reset_width, self.read_width].iter().all(|&w| w >= 1e-8)
+            && self.gap >= 0.0
+            && self.avg_time >= 0.0
+            && self.min_window.is_none_or(|window| window > 0.0)
+    }
+
+    /// Offset from the start of the read plateau and averaging time of the reads, centered on the plateau
+    fn read_window(&self) -> (f64, f64) {
+        let average = round_10ns(self.avg_time.min(self.read_width));
+        (round_10ns((self.read_width - average) / 2.0), average)
+    }
+
+    /// Layout of a pattern with `cycles` cycles that are not read, followed by a read cycle if `read`
+    fn layout(&self, cycles: usize, read: bool) -> Layout {
+        let mut layout = Layout::new();
+        for _ in 0..cycles {
+            layout.push_cycle(self, false);
+        }
+        if read {
+            layout.push_cycle(self, true);
+        }
+
+        layout
+    }
+
+    /// Cycles run by every sequencer execution, a whole number of read blocks
+    fn batch_cycles(&self) -> usize {
+        (self.batch / self.read_every).max(1) * self.read_every
+    }
+}
+
+/// Creates `name` on the force channel from `layout`, and `{name}_v2`, held at 0 V, on the ground one. Both measure at
+/// the reads of the layout.
+fn add_layout<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    endurance: &Endurance,
+    layout: &Layout,
+    name: &str,
+) -> Result<(), Error> {
+    let (_, average) = endurance.read_window();
+    let ground = format!("{}_v2", name);
+
+    wgfmu.create_pattern(name, 0.0)?;
+    add_waveform(wgfmu, &layout.waveform, name)?;
+
+    wgfmu.create_pattern(ground.as_str(), 0.0)?;
+    wgfmu.add_vector(ground.as_str(), layout.time, 0.0)?;
+
+    if let Some(ranges) = endurance.current_ranges {
+        add_range_events(wgfmu, ground.as_str(), ranges, &layout.pulses)?;
+    }
+
+    for (idx, &time) in layout.reads.iter().enumerate() {
+        let event = format!("read_{}", idx);
+        for pattern in [name, ground.as_str()] {
+            wgfmu.set_measure_event(
+                pattern,
+                event.as_str(),
+                time,
+                1,
+                1e-8,
+                average,
+                MeasureEventMode::MeasureEventDataAveraged,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets up the sequences of `cycles` cycles, read every `read_every` of them.
+///
+/// Due to the 2048 maximum vectors limitation of the b1530A a million cycles do not fit a pattern, instead a block of
+/// `read_every` cycles ending with a read is laid out once and repeated. When even the block does not fit, its cycles
+/// are repeated on their own before the read cycle.
+fn add_cycles<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    endurance: &Endurance,
+    channels: Channels,
+    cycles: usize,
+) -> Result<(), Error> {
+    let bare = endurance.read_every - 1;
+    let blocks = cycles / endurance.read_every;
+    let remainder = cycles % endurance.read_every;
+
+    let cycle = endurance.layout(1, false);
+    add_layout(wgfmu, endurance, &cycle, "endurance_cycle")?;
+
+    let block = endurance.layout(bare, true);
+    let mut sequence: Vec<(&str, usize)> = vec![];
+    if block.waveform.len() <= MAX_VECTORS {
+        add_layout(wgfmu, endurance, &block, "endurance_block")?;
+        sequence.push(("endurance_block", blocks));
+    } else {
+        add_layout(wgfmu, endurance, &endurance.layout(0, true), "endurance_read")?;
+        for _ in 0..blocks {
+            sequence.push(("endurance_cycle", bare));
+            sequence.push(("endurance_read", 1));
+        }
+    }
+    sequence.push(("endurance_cycle", remainder));
+
+    for (pattern, count) in sequence.into_iter().filter(|&(_, count)| count > 0) {
+        wgfmu.add_sequence(channels.force, pattern, count)?;
+        wgfmu.add_sequence(channels.ground, format!("{}_v2", pattern).as_str(), count)?;
+    }
+
+    Ok(())
+}
+
+/// Resistances of the reads, they come in pairs, the LRS after the SET pulse and the HRS after the RESET one
+fn read_cycles(measurement: &[Measurement], first_cycle: usize, read_every: usize) -> Vec<EnduranceCycle> {
+    let resistance = |point: &Measurement| match point.current {
+        Some(current) if current != 0.0 => Some((point.voltage / current).abs()),
+        _ => None,
+    };
+
+    measurement
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(idx, reads)| EnduranceCycle {
+            cycle: first_cycle + (idx + 1) * read_every,
+            lrs: resistance(&reads[0]),
+            hrs: resistance(&reads[1]),
+        })
+        .collect()
+}
+
+/// When the test is aborted, the cycles read so far are kept as its partial data
+fn keep_completed(error: Error, cycles: &[EnduranceCycle]) -> Error {
+    match error {
+        Error::Aborted(_) => Error::Aborted(
+            serde_json::to_value(EnduranceMeasurement {
+                cycles: cycles.to_vec(),
+                collapsed_at: None,
+            })
+            .unwrap_or_default(),
+        ),
+        error => error,
+    }
+}
+
+pub fn measure_endurance_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    endurance: Endurance,
+) -> Result<EnduranceMeasurement, Error> {
+    if !endurance.is_valid() {
+        return Result::Err(Error::WgfmuError(wgfmu::Error::BadArguments));
+    }
+
+    let batch_cycles = endurance.batch_cycles();
+    let n_batches = endurance.cycles.div_ceil(batch_cycles);
+
+    info!(
+        "Measuring the endurance over {} cycles, {} V / {} V pulses read every {} cycles",
+        endurance.cycles, endurance.v_set, endurance.v_reset, endurance.read_every
+    );
+
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+
+    let mut cycles = vec![];
+    let mut collapsed_at = None;
+    for idx in 0..n_batches {
+        let first_cycle = idx * batch_cycles;
+        let batch = batch_cycles.min(endurance.cycles - first_cycle);
+
+        let mut run_batch = || -> Result<Vec<EnduranceCycle>, Error> {
+            wgfmu.report_step(Step {
+                name: "batch".to_string(),
+                index: idx,
+                count: n_batches,
+            });
+
+            wgfmu.clear()?;
+            add_cycles(wgfmu, &endurance, channels, batch)?;
+
+            wgfmu.initialize()?;
+            setup_fastiv(wgfmu, channels, ranges)?;
+            wgfmu.execute()?;
+            // Only the cycles read from the batch are reported, not its raw samples
+            wait_until_completed_quietly(wgfmu, channels)?;
+
+            let measurement = get_measurements(wgfmu, channels)?;
+            let read = read_cycles(&measurement, first_cycle, endurance.read_every);
+
+            wgfmu.report_data(serde_json::to_value(&read).unwrap_or_default());
+            Ok(read)
+        };
+
+        let read = run_batch().map_err(|err| keep_completed(err, &cycles))?;
+
+        if let Some(min_window) = endurance.min_window {
+            collapsed_at = read
+                .iter()
+                .find(|cycle| cycle.window().is_some_and(|window| window < min_window))
+                .map(|cycle| cycle.cycle);
+        }
+        cycles.extend(read);
+
+        if let Some(cycle) = collapsed_at {
+            info!("The memory window closed after {} cycles", cycle);
+            break;
+        }
+    }
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(EnduranceMeasurement { cycles, collapsed_at })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::b1500::instrument::InstrumentManager;
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+
+    fn endurance(cycles: usize, read_every: usize) -> Endurance {
+        serde_json::from_value(serde_json::json!({
+            "cycles": cycles,
+            "vSet": 1.5,
+            "setWidth": 1e-6,
+            "vReset": -1.5,
+            "resetWidth": 1e-6,
+            "vRead": 0.1,
+            "readWidth": 1e-6,
+            "gap": 1e-6,
+            "readEvery": read_every,
+            "avgTime": 1e-7,
+            "batch": cycles,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reports_only_cycles() {
+        let manager = InstrumentManager::new(Box::new(TestWgfmu::new(MemristorParams::default()).unwrap()));
+        let mut lease = manager.lease("Endurance").unwrap();
+        let (sink, mut reported) = mpsc::channel(64);
+        lease.set_sink(sink);
+
+        // A single batch long enough for the sequencer to be polled while it runs, raw samples would be streamed
+        let measurement =
+            measure_endurance_fastiv(&mut lease, None, Default::default(), Default::default(), endurance(150_000, 100))
+                .unwrap();
+        drop(lease);
+
+        let mut cycles = vec![];
+        while let Ok(chunk) = reported.try_recv() {
+            cycles.extend(serde_json::from_value::<Vec<EnduranceCycle>>(chunk).unwrap());
+        }
+        assert_eq!(cycles.len(), 1500);
+        assert_eq!(cycles, measurement.cycles);
+    }
+
+    #[test]
+    fn repeats_cycles_when_the_block_does_not_fit() {
+        let endurance = endurance(2500, 1000);
+        assert!(endurance.layout(endurance.read_every - 1, true).waveform.len() > MAX_VECTORS);
+
+        let mut wgfmu = TestWgfmu::new(MemristorParams::default()).unwrap();
+        let measurement =
+            measure_endurance_fastiv(&mut wgfmu, None, Default::default(), Default::default(), endurance).unwrap();
+
+        // Two read blocks, the 500 cycles left are not read
+        let read = measurement.cycles.iter().map(|cycle| cycle.cycle).collect::<Vec<usize>>();
+        assert_eq!(read, vec![1000, 2000]);
+        assert!(measurement
+            .cycles
+            .iter()
+            .all(|cycle| cycle.hrs.is_some() && cycle.lrs.is_some()));
+    }
+}
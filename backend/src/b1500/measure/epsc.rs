@@ -0,0 +1,359 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::b1500::types::{Channels, Ranges, VoltageWaveForm, VoltageWaveFormPoint};
+use crate::b1500::utils::add_waveform;
+use crate::b1500::wgfmu::driver::{MeasureEventMode, Measurement};
+use crate::b1500::wgfmu::{self, WgfmuDriver};
+
+use super::{utils::get_measurements, utils::round_10ns, utils::setup_fastiv, utils::wait_until_completed, Error};
+
+/// Rise and fall time of the spike
+const EDGE_TIME: f64 = 1e-8;
+
+/// The decay is fitted while it stays above this fraction of the peak, below it the trace is mostly noise
+const FIT_THRESHOLD: f64 = 0.05;
+
+/// Excitatory postsynaptic current, the device is held at the read bias, a presynaptic spike is applied and the
+/// current is sampled while it decays back.
+///
+/// Notes:
+///                 ___                      amplitude
+///                |   |
+///   _____________|   |___________________  v_read
+///  |<-baseline->|<->|<----read_time---->|
+///                width
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Epsc {
+    /// Amplitude of the spike. (Volts)
+    pub amplitude: f64,
+    /// Width of the spike. (seconds)
+    pub width: f64,
+    /// Bias the current is read at. (Volts)
+    pub v_read: f64,
+    /// Time the current is sampled at the read bias before the spike, the baseline is taken from the end of the trace
+    /// when it is 0. (seconds)
+    #[serde(default)]
+    pub baseline: f64,
+    /// Time the decay is sampled for after the spike. (seconds)
+    pub read_time: f64,
+    /// Samples of the decay
+    pub n_points: usize,
+    pub avg_time: f64,
+}
+
+/// Parameters extracted from the decay, the peak and the charge are relative to the baseline current
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EpscParameters {
+    /// Current the device draws at rest. (Amperes)
+    pub baseline_current: f64,
+    /// (Amperes)
+    pub peak_current: f64,
+    /// Time of the peak after the spike. (seconds)
+    pub peak_time: f64,
+    /// Time constant of an exponential fit of the decay, None when the current does not decay. (seconds)
+    pub tau: Option<f64>,
+    /// Charge of the current over the trace. (Coulombs)
+    pub charge: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EpscMeasurement {
+    /// The baseline samples followed by the decay
+    pub iv: Vec<Measurement>,
+    pub parameters: EpscParameters,
+}
+
+impl Epsc {
+    /// Whether the measurement can be run
+    pub fn is_valid(&self) -> bool {
+        self.amplitude.abs() <= 10.0
+            && self.v_read.abs() <= 10.0
+            && self.width >= 1e-8
+            && self.baseline >= 0.0
+            && self.n_points >= 2
+            && self.avg_time >= 0.0
+            && self.read_time / self.n_points as f64 >= 1e-8
+    }
+
+    /// Interval and averaging time of the samples
+    fn sampling(&self) -> (f64, f64) {
+        let interval = round_10ns(self.read_time / self.n_points as f64);
+        (interval, self.avg_time.min(interval))
+    }
+
+    /// Samples of the baseline, at the same interval as the decay
+    fn baseline_points(&self) -> usize {
+        let (interval, _) = self.sampling();
+        if round_10ns(self.baseline) < interval {
+            return 0;
+        }
+        (f64::floor(round_10ns(self.baseline) / interval) as usize).min(self.n_points)
+    }
+
+    /// Force waveform, along with the times the baseline and the decay start at
+    fn waveform(&self) -> (VoltageWaveForm, f64, f64) {
+        let mut waveform: VoltageWaveForm = vec![];
+        let mut time = 0.0;
+        let mut push = |waveform: &mut VoltageWaveForm, dtime: f64, voltage: f64| {
+            if dtime > 0.0 {
+                waveform.push(VoltageWaveFormPoint { dtime, voltage });
+                time += dtime;
+            }
+            time
+        };
+
+        let baseline = push(&mut waveform, EDGE_TIME, self.v_read);
+        push(&mut waveform, round_10ns(self.baseline), self.v_read);
+        push(&mut waveform, EDGE_TIME, self.amplitude);
+        push(&mut waveform, round_10ns(self.width), self.amplitude);
+        let decay = push(&mut waveform, EDGE_TIME, self.v_read);
+        push(&mut waveform, round_10ns(self.read_time), self.v_read);
+        push(&mut waveform, EDGE_TIME, 0.0);
+
+        (waveform, baseline, decay)
+    }
+}
+
+/// Time constant of `current` = A exp(-t / tau), from a least squares fit of its logarithm. `trace` holds the (time,
+/// current) samples from the peak on, only the ones before the current first drops under `FIT_THRESHOLD` of the peak
+/// are used.
+fn fit_decay(trace: &[(f64, f64)]) -> Option<f64> {
+    let (_, peak) = *trace.first()?;
+    let sign = f64::signum(peak);
+    let points = trace
+        .iter()
+        .take_while(|&&(_, current)| sign * current > FIT_THRESHOLD * peak.abs())
+        .map(|&(t, current)| (t, f64::ln(sign * current)))
+        .collect::<Vec<(f64, f64)>>();
+    if points.len() < 3 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|&(t, _)| t).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let covariance = points.iter().map(|&(t, y)| (t - mean_t) * (y - mean_y)).sum::<f64>();
+    let variance = points.iter().map(|&(t, _)| (t - mean_t).powi(2)).sum::<f64>();
+    if variance == 0.0 {
+        return None;
+    }
+
+    let slope = covariance / variance;
+    if slope < 0.0 {
+        Some(-1.0 / slope)
+    } else {
+        None
+    }
+}
+
+/// Extracts the parameters of the decay from its samples, relative to `baseline` samples taken at rest, or to the end
+/// of the decay when there are none
+fn analyze(baseline: &[Measurement], decay: &[Measurement]) -> EpscParameters {
+    let current = |sample: &Measurement| sample.current.unwrap_or_default();
+    let mean = |samples: &[Measurement]| samples.iter().map(current).sum::<f64>() / samples.len().max(1) as f64;
+
+    let baseline_current = if baseline.is_empty() {
+        // The last tenth of the trace
+        mean(&decay[decay.len() - (decay.len() / 10).max(1).min(decay.len())..])
+    } else {
+        mean(baseline)
+    };
+
+    let start = decay.first().map_or(0.0, |sample| sample.time);
+    let trace = decay
+        .iter()
+        .map(|sample| (sample.time - start, current(sample) - baseline_current))
+        .collect::<Vec<(f64, f64)>>();
+
+    let peak = trace
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.1.abs().total_cmp(&b.1.abs()))
+        .map(|(idx, _)| idx)
+        .unwrap_or_default();
+    let (peak_time, peak_current) = trace.get(peak).copied().unwrap_or_default();
+
+    let charge = trace
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0) * (pair[0].1 + pair[1].1) / 2.0)
+        .sum::<f64>();
+
+    EpscParameters {
+        baseline_current,
+        peak_current,
+        peak_time,
+        tau: trace.get(peak..).and_then(fit_decay),
+        charge,
+    }
+}
+
+pub fn measure_epsc_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    epsc: Epsc,
+) -> Result<EpscMeasurement, Error> {
+    if !epsc.is_valid() {
+        return Result::Err(Error::WgfmuError(wgfmu::Error::BadArguments));
+    }
+
+    let (interval, avg_time) = epsc.sampling();
+    let baseline_points = epsc.baseline_points();
+    let (waveform, baseline_start, decay_start) = epsc.waveform();
+    let total_time = waveform.iter().map(|point| point.dtime).sum::<f64>();
+
+    info!(
+        "Measuring the EPSC of a {} V, {} s spike read at {} V",
+        epsc.amplitude, epsc.width, epsc.v_read
+    );
+
+    wgfmu.clear()?;
+
+    wgfmu.create_pattern("epsc", 0.0)?;
+    add_waveform(wgfmu, &waveform, "epsc")?;
+    wgfmu.create_pattern("epsc_v2", 0.0)?;
+    wgfmu.add_vector("epsc_v2", total_time, 0.0)?;
+
+    for pattern in ["epsc", "epsc_v2"] {
+        if baseline_points > 0 {
+            wgfmu.set_measure_event(
+                pattern,
+                "baseline",
+                baseline_start,
+                baseline_points as i32,
+                interval,
+                avg_time,
+                MeasureEventMode::MeasureEventDataAveraged,
+            )?;
+        }
+        wgfmu.set_measure_event(
+            pattern,
+            "decay",
+            round_10ns(decay_start),
+            epsc.n_points as i32,
+            interval,
+            avg_time,
+            MeasureEventMode::MeasureEventDataAveraged,
+        )?;
+    }
+
+    wgfmu.add_sequence(channels.force, "epsc", 1)?;
+    wgfmu.add_sequence(channels.ground, "epsc_v2", 1)?;
+
+    info!("Initializing WGFMU");
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+    wgfmu.initialize()?;
+
+    setup_fastiv(wgfmu, channels, ranges)?;
+    wgfmu.execute()?;
+
+    info!("Performing measurements");
+    wait_until_completed(wgfmu, channels)?;
+
+    info!("Retrieving data...");
+
+    let measurement = get_measurements(wgfmu, channels)?;
+    let (baseline, decay) = measurement.split_at(baseline_points.min(measurement.len()));
+    let parameters = analyze(baseline, decay);
+
+    info!(
+        "EPSC peak {} A after {} s, tau {:?} s, charge {} C",
+        parameters.peak_current, parameters.peak_time, parameters.tau, parameters.charge
+    );
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(EpscMeasurement {
+        iv: measurement,
+        parameters,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of `current` every 100 ns, from `start` on
+    fn samples(start: f64, n_points: usize, current: impl Fn(f64) -> f64) -> Vec<Measurement> {
+        (0..n_points)
+            .map(|idx| {
+                let time = idx as f64 * 1e-7;
+                Measurement {
+                    voltage: 0.1,
+                    current: Some(current(time)),
+                    time: start + time,
+                }
+            })
+            .collect()
+    }
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() <= tolerance * expected.abs(),
+            "{} is not within {} of {}",
+            value,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn exponential_decay() {
+        let (a, tau, b) = (1e-5, 1e-5, 1e-7);
+        let baseline = samples(0.0, 100, |_| b);
+        let decay = samples(2e-5, 1000, |t| a * f64::exp(-t / tau) + b);
+
+        let parameters = analyze(&baseline, &decay);
+        assert_close(parameters.baseline_current, b, 1e-9);
+        assert_close(parameters.peak_current, a, 1e-9);
+        assert_eq!(parameters.peak_time, 0.0);
+        assert_close(parameters.tau.unwrap(), tau, 1e-6);
+        // The integral of the decay over the 99.9 us it was sampled for
+        assert_close(parameters.charge, a * tau * (1.0 - f64::exp(-999e-7 / tau)), 1e-3);
+    }
+
+    #[test]
+    fn negative_decay_without_baseline() {
+        let (a, tau, b) = (-1e-5, 1e-5, -1e-7);
+        let decay = samples(0.0, 1000, |t| a * f64::exp(-t / tau) + b);
+
+        // The baseline is read from the end of the trace, where the decay is almost over
+        let parameters = analyze(&[], &decay);
+        assert_close(parameters.baseline_current, b, 1e-2);
+        assert_close(parameters.peak_current, a, 1e-3);
+        assert_close(parameters.tau.unwrap(), tau, 1e-2);
+    }
+
+    #[test]
+    fn no_decay() {
+        let parameters = analyze(&[], &[]);
+        assert_eq!(parameters.tau, None);
+        assert_eq!((parameters.peak_current, parameters.charge), (0.0, 0.0));
+
+        // Flat at the baseline
+        let flat = samples(0.0, 100, |_| 1e-7);
+        assert_eq!(analyze(&flat, &flat).tau, None);
+
+        // Growing, the peak is the last sample
+        let growing = samples(0.0, 100, |t| 1e-7 + t * 1e-2);
+        let parameters = analyze(&samples(0.0, 10, |_| 1e-7), &growing);
+        assert_close(parameters.peak_time, 99e-7, 1e-9);
+        assert_eq!(parameters.tau, None);
+
+        // Above the baseline but not decaying
+        let parameters = analyze(&samples(0.0, 10, |_| 1e-7), &samples(0.0, 100, |_| 1e-6));
+        assert_eq!(parameters.tau, None);
+
+        assert_eq!(fit_decay(&[]), None);
+        assert_eq!(fit_decay(&[(0.0, 1e-6), (1e-7, 5e-7)]), None);
+    }
+}
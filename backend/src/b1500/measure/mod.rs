@@ -1,16 +1,27 @@
 use std::{sync::PoisonError};
 
+use sea_orm::prelude::DateTimeLocal;
+
 use std::fmt::Display;
 
 use super::{wgfmu};
 
+pub mod endurance;
+pub mod epsc;
+pub mod ppf;
 pub mod pulsed;
+pub mod pulsed_iv;
+pub mod retention;
 pub mod stdp;
+pub mod sweep;
 pub mod utils;
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "WGFMU measurement error {}", self.to_string())
+        match self {
+            Error::WgfmuError(error) => write!(f, "{}", error),
+            _ => write!(f, "WGFMU measurement error {:?}", self),
+        }
     }
 }
 
@@ -19,11 +30,18 @@ pub enum Error {
     WgfmuMutexLockError,
     WgfmuError(wgfmu::driver::Error),
     UtilsError(super::utils::Error),
+    /// The measurement was aborted, holds whatever data was measured before that (or null)
+    Aborted(serde_json::Value),
 }
 
 impl From<wgfmu::driver::Error> for Error {
     fn from(error: wgfmu::driver::Error) -> Self {
-        Error::WgfmuError(error)
+        match error {
+            wgfmu::driver::Error::Aborted => Error::Aborted(serde_json::Value::Null),
+            // A call panicked while holding the driver
+            wgfmu::driver::Error::MutexUnlockError => Error::WgfmuMutexLockError,
+            error => Error::WgfmuError(error),
+        }
     }
 }
 
@@ -37,4 +55,11 @@ impl<T> From<PoisonError<T>> for Error {
     fn from(_: PoisonError<T>) -> Error {
         Error::WgfmuMutexLockError
     }
-}
\ No newline at end of file
+}
+/// Outcome of a measurement that runs in several sittings, releasing the instrument in between
+#[derive(Debug)]
+pub enum Continuation<T> {
+    /// The measurement goes on at that time, from the data it reported so far
+    Later(DateTimeLocal),
+    Done(T),
+}
@@ -0,0 +1,300 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::b1500::types::{Channels, Ranges, VoltageWaveForm, VoltageWaveFormPoint};
+use crate::b1500::utils::add_waveform;
+use crate::b1500::wgfmu::driver::{MeasureEventMode, Measurement, Step};
+use crate::b1500::wgfmu::{self, WgfmuDriver};
+
+use super::{
+    utils::get_measurements, utils::round_10ns, utils::setup_fastiv, utils::wait_until_completed_reporting, Error,
+};
+
+/// Rise and fall time of the pulses
+const EDGE_TIME: f64 = 1e-8;
+
+/// Paired-pulse facilitation, a pair of identical pulses is applied for every interval and the current peaks compared.
+///
+/// Notes:
+///          ___        ___           ___            ___
+///         |   |      |   |         |   |          |   |  amplitude
+///  _______|   |______|   |_________|   |__________|   |
+///  |<---->|<->|<---->|             |<->|<-------->|
+///    rest width interval           width  interval
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Ppf {
+    /// (Volts)
+    pub amplitude: f64,
+    /// Width of both pulses. (seconds)
+    pub width: f64,
+    /// Times at 0 V between the end of the first pulse and the start of the second one. (seconds)
+    pub intervals: Vec<f64>,
+    /// Time at 0 V before every pair, so the device relaxes from the previous one. (seconds)
+    #[serde(default)]
+    pub rest: f64,
+    /// Samples measured on every pulse
+    pub n_points: usize,
+    pub avg_time: f64,
+}
+
+/// Facilitation of a pulse pair
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PpfPoint {
+    /// (seconds)
+    pub interval: f64,
+    /// Current peak of the first pulse. (Amperes)
+    pub a1: f64,
+    /// Current peak of the second pulse. (Amperes)
+    pub a2: f64,
+    /// Facilitation index A2/A1, None when the first pulse drew no current
+    pub index: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PpfMeasurement {
+    pub iv: Vec<Measurement>,
+    pub curve: Vec<PpfPoint>,
+}
+
+impl Ppf {
+    /// Whether the measurement can be run
+    pub fn is_valid(&self) -> bool {
+        self.amplitude != 0.0
+            && self.amplitude.abs() <= 10.0
+            && !self.intervals.is_empty()
+            && self.intervals.iter().all(|&interval| interval >= 1e-8)
+            && self.rest >= 0.0
+            && self.n_points >= 1
+            && self.avg_time >= 0.0
+            && self.width / self.n_points as f64 >= 1e-8
+    }
+
+    /// Start within the pulse, interval and averaging time of the samples of a pulse
+    fn sampling(&self) -> (f64, f64, f64) {
+        let interval = round_10ns(self.width / self.n_points as f64);
+        (EDGE_TIME, interval, self.avg_time.min(interval))
+    }
+
+    /// Force waveform of the pair with `interval` between the pulses, and the times the pulses start at
+    fn waveform(&self, interval: f64) -> (VoltageWaveForm, [f64; 2]) {
+        let mut waveform: VoltageWaveForm = vec![];
+        let mut time = 0.0;
+        let mut push = |waveform: &mut VoltageWaveForm, dtime: f64, voltage: f64| {
+            if dtime > 0.0 {
+                waveform.push(VoltageWaveFormPoint { dtime, voltage });
+                time += dtime;
+            }
+            time
+        };
+
+        let first = push(&mut waveform, round_10ns(self.rest), 0.0);
+        push(&mut waveform, EDGE_TIME, self.amplitude);
+        push(&mut waveform, round_10ns(self.width), self.amplitude);
+        push(&mut waveform, EDGE_TIME, 0.0);
+        let second = push(&mut waveform, round_10ns(interval), 0.0);
+        push(&mut waveform, EDGE_TIME, self.amplitude);
+        push(&mut waveform, round_10ns(self.width), self.amplitude);
+        push(&mut waveform, EDGE_TIME, 0.0);
+
+        (waveform, [first, second])
+    }
+}
+
+/// Highest current, in absolute value, measured on a pulse
+fn peak(samples: &[Measurement]) -> f64 {
+    samples
+        .iter()
+        .filter_map(|sample| sample.current)
+        .map(f64::abs)
+        .fold(0.0, f64::max)
+}
+
+/// Facilitation of every pair, from the `n_points` samples of the first pulse followed by those of the second one. The
+/// curve is left empty when the samples do not add up, pairing them would mix the pulses up.
+fn curve(ppf: &Ppf, measurement: &[Measurement]) -> Vec<PpfPoint> {
+    let expected = 2 * ppf.n_points * ppf.intervals.len();
+    if measurement.len() != expected {
+        warn!(
+            "{} samples were measured instead of {}, the facilitation can not be computed",
+            measurement.len(),
+            expected
+        );
+        return vec![];
+    }
+
+    measurement
+        .chunks(2 * ppf.n_points)
+        .zip(ppf.intervals.iter())
+        .map(|(pair, &interval)| {
+            let (first, second) = pair.split_at(ppf.n_points);
+            let (a1, a2) = (peak(first), peak(second));
+            PpfPoint {
+                interval,
+                a1,
+                a2,
+                index: if a1 > 0.0 { Some(a2 / a1) } else { None },
+            }
+        })
+        .collect()
+}
+
+pub fn measure_ppf_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    ppf: Ppf,
+) -> Result<PpfMeasurement, Error> {
+    if !ppf.is_valid() {
+        return Result::Err(Error::WgfmuError(wgfmu::Error::BadArguments));
+    }
+
+    let (start, interval, avg_time) = ppf.sampling();
+
+    info!(
+        "Measuring the paired-pulse facilitation of {} V, {} s pulses at {} intervals",
+        ppf.amplitude,
+        ppf.width,
+        ppf.intervals.len()
+    );
+
+    wgfmu.clear()?;
+
+    // One pattern per pulse pair, both pulses are sampled
+    let mut pair_ends: Vec<f64> = vec![];
+    for (idx, &pair_interval) in ppf.intervals.iter().enumerate() {
+        let (waveform, pulses) = ppf.waveform(pair_interval);
+        let total_time = waveform.iter().map(|point| point.dtime).sum::<f64>();
+        pair_ends.push(pair_ends.last().copied().unwrap_or_default() + total_time);
+
+        let force = format!("ppf_{}", idx);
+        let ground = format!("ppf_{}_v2", idx);
+        wgfmu.create_pattern(force.as_str(), 0.0)?;
+        add_waveform(wgfmu, &waveform, force.as_str())?;
+        wgfmu.create_pattern(ground.as_str(), 0.0)?;
+        wgfmu.add_vector(ground.as_str(), total_time, 0.0)?;
+
+        for (n, pulse_start) in pulses.into_iter().enumerate() {
+            for pattern in [force.as_str(), ground.as_str()] {
+                wgfmu.set_measure_event(
+                    pattern,
+                    format!("pulse_{}", n + 1).as_str(),
+                    round_10ns(pulse_start + start),
+                    ppf.n_points as i32,
+                    interval,
+                    avg_time,
+                    MeasureEventMode::MeasureEventDataAveraged,
+                )?;
+            }
+        }
+
+        wgfmu.add_sequence(channels.force, force.as_str(), 1)?;
+        wgfmu.add_sequence(channels.ground, ground.as_str(), 1)?;
+    }
+    let pairs_time = pair_ends.last().copied().unwrap_or_default();
+
+    info!("Initializing WGFMU");
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+    wgfmu.initialize()?;
+
+    setup_fastiv(wgfmu, channels, ranges)?;
+    wgfmu.execute()?;
+
+    let mut current_pair = None;
+
+    info!("Performing measurements");
+    wait_until_completed_reporting(wgfmu, channels, |wgfmu, status| {
+        if status.total_time > 0.0 && pairs_time > 0.0 {
+            let t = status.elapsed_time / status.total_time * pairs_time;
+            let index = pair_ends.partition_point(|&end| end <= t).min(pair_ends.len() - 1);
+            if current_pair != Some(index) {
+                current_pair = Some(index);
+                wgfmu.report_step(Step {
+                    name: "pulse pair".to_string(),
+                    index,
+                    count: pair_ends.len(),
+                });
+            }
+        }
+    })?;
+
+    info!("Retrieving data...");
+
+    let measurement = get_measurements(wgfmu, channels)?;
+
+    let curve = curve(&ppf, &measurement);
+
+    for point in curve.iter() {
+        info!("PPF at {} s: {:?}", point.interval, point.index);
+    }
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(PpfMeasurement { iv: measurement, curve })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ppf(intervals: Vec<f64>, n_points: usize) -> Ppf {
+        Ppf {
+            amplitude: 1.0,
+            width: 1e-6,
+            intervals,
+            rest: 0.0,
+            n_points,
+            avg_time: 1e-8,
+        }
+    }
+
+    fn samples(currents: &[f64]) -> Vec<Measurement> {
+        currents
+            .iter()
+            .enumerate()
+            .map(|(idx, &current)| Measurement {
+                voltage: 1.0,
+                current: Some(current),
+                time: idx as f64 * 1e-7,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn curve_of_the_pairs() {
+        let ppf = ppf(vec![1e-6, 2e-6], 2);
+        let measurement = samples(&[1e-6, -2e-6, 3e-6, 1e-6, 0.0, 0.0, 1e-6, 0.0]);
+
+        assert_eq!(
+            curve(&ppf, &measurement),
+            vec![
+                PpfPoint {
+                    interval: 1e-6,
+                    a1: 2e-6,
+                    a2: 3e-6,
+                    index: Some(1.5),
+                },
+                PpfPoint {
+                    interval: 2e-6,
+                    a1: 0.0,
+                    a2: 1e-6,
+                    index: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_curve_when_samples_are_missing() {
+        let ppf = ppf(vec![1e-6, 2e-6], 2);
+        assert!(curve(&ppf, &samples(&[1e-6, 2e-6, 3e-6, 4e-6, 1e-6, 2e-6, 3e-6])).is_empty());
+        assert!(curve(&ppf, &[]).is_empty());
+    }
+}
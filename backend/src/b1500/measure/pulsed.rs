@@ -1,15 +1,15 @@
-use std::sync::{Arc, MutexGuard};
-
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 
-use crate::b1500::types::{GaussianNoise, Noise, VoltageWaveForm, VoltageWaveFormPoint};
+use crate::b1500::types::{Channels, CurrentRanges, GaussianNoise, Ranges, Noise, VoltageWaveForm, VoltageWaveFormPoint};
 use crate::b1500::utils::{add_noisy_waveform, add_waveform};
-use crate::b1500::wgfmu::driver::{MeasureEventMode, MeasureMode, Measurement, OperationMode};
+use crate::b1500::wgfmu::driver::{MeasureEventMode, Measurement, Step};
 use crate::b1500::wgfmu::WgfmuDriver;
-use crate::b1500::{CHANNEL1, CHANNEL2, WGFMU};
 
-use super::{utils::round_10ns, Error};
+use super::{
+    utils::add_range_events, utils::get_measurements, utils::round_10ns, utils::setup_fastiv, utils::wait_until_completed,
+    utils::wait_until_completed_reporting, Error,
+};
 
 fn init_pulsed_voltage_waveform(
     v_high: f64,
@@ -63,11 +63,15 @@ pub struct PulseTrain {
     /// Low voltage of the pulses, see notes above. (Volts)
     pub v_low: f64,
     /// Initial waiting delay, in seconds. (seconds)
-    pub delay: f64
+    pub delay: f64,
+    /// Current ranges to switch between during the pulses and the low phases, the current is measured at a single
+    /// range otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_ranges: Option<CurrentRanges>,
 }
 
-fn wgfmu_add_pulse_train<T: WgfmuDriver>(
-    wgfmu: &mut MutexGuard<T>,
+fn wgfmu_add_pulse_train<T: WgfmuDriver + ?Sized>(
+    wgfmu: &mut T,
     pulse_train: PulseTrain,
     n_points_high: usize,
     n_points_low: usize,
@@ -75,6 +79,7 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
     noise_std: f64,
     avg_time: &mut f64,
     pattern: &str,
+    channels: Channels,
 ) -> Result<(), Error> {
     // Sampling measurements High
     let totaltime_high = round_10ns(pulse_train.cycle_time * pulse_train.duty_cycle + 1e-8);
@@ -126,7 +131,7 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
     let mut unique_finish_time = 0.0;
 
     {
-        // CHANNEL2
+        // Force channel
         // Initializing the "v1" pattern at 0, this is for SMU1
         wgfmu.create_pattern(pattern, 0.0)?;
 
@@ -142,7 +147,7 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
             let delay_pattern = format!("{}_delay", pattern);
             wgfmu.create_pattern(delay_pattern.as_str(), 0.0)?;
             add_waveform(wgfmu, &wait_wf, delay_pattern.as_str())?;
-            wgfmu.add_sequence(CHANNEL2, delay_pattern.as_str(), 1)?;
+            wgfmu.add_sequence(channels.force, delay_pattern.as_str(), 1)?;
             // eventEndTime = time + interval * (points - 1) + average
             let points = 80.0;
             let interval = (pulse_train.delay - *avg_time) / (points - 1.0);
@@ -159,7 +164,7 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
 
         if !noise {
             add_waveform(wgfmu, &waveform, pattern)?;
-            wgfmu.add_sequence(CHANNEL2, pattern, pulse_train.n_pulses)?;
+            wgfmu.add_sequence(channels.force, pattern, pulse_train.n_pulses)?;
         } else {
             waveform = waveform.repeat(unique_pulses);
 
@@ -176,7 +181,7 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
                     sigma: noise_std,
                 }),
             )?;
-            wgfmu.add_sequence(CHANNEL2, pattern, n_rep)?;
+            wgfmu.add_sequence(channels.force, pattern, n_rep)?;
         }
 
         // Add the created waveform n_pulses times
@@ -204,7 +209,7 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
             // let pattern_margin = format!("{}_margin", pattern);
             // wgfmu.create_pattern(pattern_margin.as_str(), 0.0)?;
             // wgfmu.add_vector(pattern_margin.as_str(), pulse_train.delay, 0.0)?;
-            // wgfmu.add_sequence(CHANNEL2, pattern_margin.as_str(), 1)?;
+            // wgfmu.add_sequence(channels.force, pattern_margin.as_str(), 1)?;
         } else {
             // let total_measure_time = measure_totaltime_high + measure_totaltime_low;
 
@@ -244,13 +249,18 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
 
     {
         let v2 = format!("{}_v2", pattern);
+        // Each pulse ends after its falling edge
+        let pulse_end = round_10ns(totaltime_high + 1e-8);
 
         if pulse_train.delay != 0.0 {
             let wait_wf: VoltageWaveForm = vec![VoltageWaveFormPoint { voltage: 0.0, dtime: pulse_train.delay * 1.001 }];
             let delay_pattern = format!("{}_delay", v2);
             wgfmu.create_pattern(delay_pattern.as_str(), 0.0)?;
             add_waveform(wgfmu, &wait_wf, delay_pattern.as_str())?;
-            wgfmu.add_sequence(CHANNEL1, delay_pattern.as_str(), 1)?;
+            wgfmu.add_sequence(channels.ground, delay_pattern.as_str(), 1)?;
+            if let Some(ranges) = pulse_train.current_ranges {
+                add_range_events(wgfmu, delay_pattern.as_str(), ranges, &[])?;
+            }
             // eventEndTime = time + interval * (points - 1) + average
             let points = 80.0;
             let interval = (pulse_train.delay - *avg_time) / (points - 1.0);
@@ -273,7 +283,11 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
             // End at 0
             wgfmu.set_vector(v2.as_str(), total_time, 0.0)?;
 
-            wgfmu.add_sequence(CHANNEL1, v2.as_str(), pulse_train.n_pulses)?;
+            wgfmu.add_sequence(channels.ground, v2.as_str(), pulse_train.n_pulses)?;
+
+            if let Some(ranges) = pulse_train.current_ranges {
+                add_range_events(wgfmu, v2.as_str(), ranges, &[(0.0, pulse_end)])?;
+            }
 
             wgfmu.set_measure_event(
                 v2.as_str(),
@@ -302,7 +316,14 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
             // End at 0
             wgfmu.set_vector(v2.as_str(), total_time * (pulse_train.n_pulses as f64), 0.0)?;
 
-            wgfmu.add_sequence(CHANNEL1, v2.as_str(), 1)?;
+            wgfmu.add_sequence(channels.ground, v2.as_str(), 1)?;
+
+            if let Some(ranges) = pulse_train.current_ranges {
+                let pulses = (0..pulse_train.n_pulses)
+                    .map(|k| (k as f64 * total_time, k as f64 * total_time + pulse_end))
+                    .collect::<Vec<(f64, f64)>>();
+                add_range_events(wgfmu, v2.as_str(), ranges, &pulses)?;
+            }
 
             let total_points =
                 (n_rep * (unique_pulses * (n_points_low as usize + n_points_high as usize))) as i32;
@@ -323,8 +344,11 @@ fn wgfmu_add_pulse_train<T: WgfmuDriver>(
     Ok(())
 }
 
-pub fn measure_pulse_fastiv(
+pub fn measure_pulse_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
     instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
     pulse_train: PulseTrain,
     n_points_high: usize,
     n_points_low: usize,
@@ -347,13 +371,11 @@ pub fn measure_pulse_fastiv(
         cycle_time * 1e9
     );
 
-    let wgfmu = Arc::clone(&WGFMU);
-    let mut wgfmu = wgfmu.lock()?;
 
     wgfmu.clear()?;
 
     wgfmu_add_pulse_train(
-        &mut wgfmu,
+        wgfmu,
         pulse_train,
         n_points_high,
         n_points_low,
@@ -361,6 +383,7 @@ pub fn measure_pulse_fastiv(
         noise_std,
         &mut avg_time,
         "v1",
+        channels,
     )?;
 
     info!("Initializing WGFMU");
@@ -369,20 +392,15 @@ pub fn measure_pulse_fastiv(
     }
     wgfmu.initialize()?;
 
-    wgfmu.set_operation_mode(CHANNEL2, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_operation_mode(CHANNEL1, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_measure_mode(CHANNEL2, MeasureMode::MeasureModeVoltage)?;
-    wgfmu.set_measure_mode(CHANNEL1, MeasureMode::MeasureModeCurrent)?;
-    wgfmu.connect(CHANNEL2)?;
-    wgfmu.connect(CHANNEL1)?;
+    setup_fastiv(wgfmu, channels, ranges)?;
     wgfmu.execute()?;
 
     info!("Performing measurements");
-    wgfmu.wait_until_completed()?;
+    wait_until_completed(wgfmu, channels)?;
 
     info!("Retrieving data...");
 
-    let measurement = wgfmu.get_measure_values(CHANNEL2)?;
+    let measurement = get_measurements(wgfmu, channels)?;
 
     info!("Measured event len {}", measurement.len());
 
@@ -392,8 +410,11 @@ pub fn measure_pulse_fastiv(
     Ok(measurement)
 }
 
-pub fn measure_pulse_collection_fastiv(
+pub fn measure_pulse_collection_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
     instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
     pulse_train_collection: PulseTrainCollection,
     n_points_high: usize,
     n_points_low: usize,
@@ -401,8 +422,6 @@ pub fn measure_pulse_collection_fastiv(
     noise: bool,
     noise_std: f64,
 ) -> Result<Vec<Measurement>, Error> {
-    let wgfmu = Arc::clone(&WGFMU);
-    let mut wgfmu = wgfmu.lock()?;
 
     wgfmu.clear()?;
 
@@ -411,7 +430,7 @@ pub fn measure_pulse_collection_fastiv(
     for (idx, pulse_train) in pulse_train_collection.iter().enumerate() {
         let pattern = format!("v{}", idx);
         
-        wgfmu_add_pulse_train(&mut wgfmu, pulse_train.clone(), n_points_high, n_points_low, noise, noise_std, &mut avg_time, pattern.as_str())?;
+        wgfmu_add_pulse_train(wgfmu, pulse_train.clone(), n_points_high, n_points_low, noise, noise_std, &mut avg_time, pattern.as_str(), channels)?;
     }
 
     info!("Initializing WGFMU");
@@ -420,20 +439,40 @@ pub fn measure_pulse_collection_fastiv(
     }
     wgfmu.initialize()?;
 
-    wgfmu.set_operation_mode(CHANNEL2, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_operation_mode(CHANNEL1, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_measure_mode(CHANNEL2, MeasureMode::MeasureModeVoltage)?;
-    wgfmu.set_measure_mode(CHANNEL1, MeasureMode::MeasureModeCurrent)?;
-    wgfmu.connect(CHANNEL2)?;
-    wgfmu.connect(CHANNEL1)?;
+    setup_fastiv(wgfmu, channels, ranges)?;
     wgfmu.execute()?;
 
+    // Trains run one after the other, the one running is found from the elapsed time of the sequencer
+    let train_ends = pulse_train_collection
+        .iter()
+        .scan(0.0, |end, train| {
+            *end += train.delay + train.cycle_time * train.n_pulses as f64;
+            Some(*end)
+        })
+        .collect::<Vec<f64>>();
+    let trains_time = train_ends.last().copied().unwrap_or_default();
+
+    let mut current_train = None;
+
     info!("Performing measurements");
-    wgfmu.wait_until_completed()?;
+    wait_until_completed_reporting(wgfmu, channels, |wgfmu, status| {
+        if status.total_time > 0.0 && trains_time > 0.0 {
+            let t = status.elapsed_time / status.total_time * trains_time;
+            let index = train_ends.partition_point(|&end| end <= t).min(train_ends.len() - 1);
+            if current_train != Some(index) {
+                current_train = Some(index);
+                wgfmu.report_step(Step {
+                    name: "train".to_string(),
+                    index,
+                    count: train_ends.len(),
+                });
+            }
+        }
+    })?;
 
     info!("Retrieving data...");
 
-    let measurement = wgfmu.get_measure_values(CHANNEL2)?;
+    let measurement = get_measurements(wgfmu, channels)?;
 
     info!("Measured event len {}", measurement.len());
 
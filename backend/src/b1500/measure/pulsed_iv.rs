@@ -0,0 +1,515 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::b1500::types::{Channels, Ranges, VoltageWaveForm, VoltageWaveFormPoint};
+use crate::b1500::utils::add_waveform;
+use crate::b1500::wgfmu::driver::{MeasureEventMode, Measurement, Step};
+use crate::b1500::wgfmu::{self, WgfmuDriver};
+
+use super::{
+    utils::get_measurements, utils::round_10ns, utils::setup_fastiv, utils::wait_until_completed_reporting, Error,
+};
+
+/// Rise and fall time of the rectangular pulses
+const EDGE_TIME: f64 = 1e-8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PulseShape {
+    /// Ramps up to the amplitude and back down, each half takes half the pulse width
+    Triangular,
+    /// Holds the amplitude for the pulse width
+    Rectangular,
+}
+
+/// How SET and RESET events are found, comparing every pulse of a ramp with the previous one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "method", rename_all = "camelCase")]
+pub enum SwitchDetection {
+    /// The peak current grows (SET) or drops (RESET) by at least `threshold`. (Amperes)
+    CurrentJump { threshold: f64 },
+    /// The conductance at the peak of the pulse grows (SET) or drops (RESET) by at least `ratio` times
+    ConductanceRatio { ratio: f64 },
+}
+
+/// Pulsed I-V sweep, a staircase of pulses returning to 0 V between them.
+///
+/// Notes:
+///                 /\
+///            /\  /  \  /\                        v_max
+///  ___/\____/  \/    \/  \/\___    ___    ____
+///                              \/\/   \/\/    \/ v_min
+///  |<-- positive branch, SET -->|<-- negative branch, RESET -->|
+///
+/// Every branch ramps the amplitude up by `step` and back down, positive pulses are expected to SET the device and
+/// negative ones to RESET it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PulsedIvSweep {
+    /// Highest amplitude of the positive branch. (Volts)
+    pub v_max: f64,
+    /// Lowest amplitude of the negative branch. (Volts)
+    pub v_min: f64,
+    /// Amplitude difference between two consecutive pulses. (Volts)
+    pub step: f64,
+    pub shape: PulseShape,
+    /// Duration of every pulse. (seconds)
+    pub pulse_width: f64,
+    /// Time at 0 V after every pulse. (seconds)
+    pub pulse_gap: f64,
+    /// Number of times the sweep is repeated
+    pub cycles: usize,
+    /// Samples measured on every pulse
+    pub n_points: usize,
+    pub avg_time: f64,
+    pub detection: SwitchDetection,
+}
+
+/// Switching parameters of a cycle of the sweep, resistances are read at the smallest pulses after the switching
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PulsedIvCycle {
+    /// Amplitude of the pulse that set the device, None if it did not switch. (Volts)
+    pub v_set: Option<f64>,
+    /// Amplitude of the pulse that reset the device, None if it did not switch. (Volts)
+    pub v_reset: Option<f64>,
+    /// Resistance at the end of the negative branch. (Ohms)
+    pub hrs: Option<f64>,
+    /// Resistance at the end of the positive branch. (Ohms)
+    pub lrs: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PulsedIvMeasurement {
+    pub iv: Vec<Measurement>,
+    pub cycles: Vec<PulsedIvCycle>,
+}
+
+/// Sample of a pulse with the highest voltage, in absolute value
+#[derive(Debug, Clone, Copy)]
+struct Peak {
+    voltage: f64,
+    current: f64,
+}
+
+impl Peak {
+    fn conductance(&self) -> Option<f64> {
+        if self.voltage == 0.0 {
+            return None;
+        }
+        Some((self.current / self.voltage).abs())
+    }
+
+    fn resistance(&self) -> Option<f64> {
+        self.conductance().filter(|&g| g > 0.0).map(|g| 1.0 / g)
+    }
+}
+
+impl SwitchDetection {
+    /// Whether a switch can be told from noise, the threshold has to be positive and the ratio above 1
+    fn is_valid(&self) -> bool {
+        match *self {
+            SwitchDetection::CurrentJump { threshold } => threshold > 0.0,
+            SwitchDetection::ConductanceRatio { ratio } => ratio > 1.0,
+        }
+    }
+
+    /// Whether the device set between the `previous` and the `next` pulses
+    fn is_set(&self, previous: &Peak, next: &Peak) -> bool {
+        match *self {
+            SwitchDetection::CurrentJump { threshold } => next.current.abs() - previous.current.abs() >= threshold,
+            SwitchDetection::ConductanceRatio { ratio } => match (previous.conductance(), next.conductance()) {
+                (Some(previous), Some(next)) => next >= previous * ratio,
+                _ => false,
+            },
+        }
+    }
+
+    /// Whether the device reset between the `previous` and the `next` pulses
+    fn is_reset(&self, previous: &Peak, next: &Peak) -> bool {
+        match *self {
+            SwitchDetection::CurrentJump { threshold } => previous.current.abs() - next.current.abs() >= threshold,
+            SwitchDetection::ConductanceRatio { ratio } => match (previous.conductance(), next.conductance()) {
+                (Some(previous), Some(next)) => previous >= next * ratio,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl PulsedIvSweep {
+    /// Amplitudes of a ramp up to `limit` and back down, without the 0 V ends
+    fn ramp(&self, limit: f64) -> Vec<f64> {
+        let n_steps = f64::round(limit.abs() / self.step).max(1.0) as usize;
+        let up = (1..n_steps)
+            .map(|k| f64::signum(limit) * self.step * k as f64)
+            .chain(std::iter::once(limit))
+            .collect::<Vec<f64>>();
+
+        up.iter().chain(up.iter().rev().skip(1)).cloned().collect()
+    }
+
+    /// Amplitudes of the positive and the negative branches of a cycle
+    fn branches(&self) -> (Vec<f64>, Vec<f64>) {
+        (self.ramp(self.v_max), self.ramp(self.v_min))
+    }
+
+    /// Start, interval and averaging time of the samples of a pulse
+    fn sampling(&self) -> (f64, f64, f64) {
+        let start = match self.shape {
+            PulseShape::Triangular => 0.0,
+            PulseShape::Rectangular => EDGE_TIME,
+        };
+        let interval = round_10ns(self.pulse_width / self.n_points as f64);
+
+        (start, interval, self.avg_time.min(interval))
+    }
+
+    /// Whether the sweep can be measured with the force voltage range of `ranges`
+    pub fn is_valid(&self, ranges: &Ranges) -> bool {
+        // Auto ranging goes up to the limits of the instrument
+        let (low, high) = ranges
+            .force_voltage
+            .and_then(|range| range.limits())
+            .unwrap_or((-10.0, 10.0));
+
+        self.v_max > 0.0
+            && self.v_max <= high
+            && self.v_min < 0.0
+            && self.v_min >= low
+            && self.step > 0.0
+            && self.cycles >= 1
+            && self.n_points >= 1
+            && self.pulse_gap >= 0.0
+            && self.avg_time >= 0.0
+            && self.detection.is_valid()
+            && self.pulse_width / self.n_points as f64 >= 1e-8
+            // Each half of a triangular pulse takes at least 10 ns
+            && (self.shape != PulseShape::Triangular || self.pulse_width >= 2e-8)
+    }
+
+    fn waveform(&self, amplitude: f64) -> VoltageWaveForm {
+        let mut waveform = match self.shape {
+            PulseShape::Triangular => vec![
+                VoltageWaveFormPoint {
+                    dtime: round_10ns(self.pulse_width / 2.0),
+                    voltage: amplitude,
+                },
+                VoltageWaveFormPoint {
+                    dtime: round_10ns(self.pulse_width / 2.0),
+                    voltage: 0.0,
+                },
+            ],
+            PulseShape::Rectangular => vec![
+                VoltageWaveFormPoint {
+                    dtime: EDGE_TIME,
+                    voltage: amplitude,
+                },
+                VoltageWaveFormPoint {
+                    dtime: round_10ns(self.pulse_width),
+                    voltage: amplitude,
+                },
+                VoltageWaveFormPoint {
+                    dtime: EDGE_TIME,
+                    voltage: 0.0,
+                },
+            ],
+        };
+
+        if self.pulse_gap > 0.0 {
+            waveform.push(VoltageWaveFormPoint {
+                dtime: round_10ns(self.pulse_gap),
+                voltage: 0.0,
+            });
+        }
+
+        waveform
+    }
+}
+
+/// Finds the switching parameters of a cycle from the peaks of its pulses
+fn analyze_cycle(
+    detection: &SwitchDetection,
+    positive: &[Peak],
+    negative: &[Peak],
+    positive_up: usize,
+    negative_up: usize,
+) -> PulsedIvCycle {
+    // Only the ramps up are looked at, the current naturally drops while the amplitude goes back down
+    let v_set = positive[..positive_up.min(positive.len())]
+        .windows(2)
+        .find(|pair| detection.is_set(&pair[0], &pair[1]))
+        .map(|pair| pair[1].voltage);
+    let v_reset = negative[..negative_up.min(negative.len())]
+        .windows(2)
+        .find(|pair| detection.is_reset(&pair[0], &pair[1]))
+        .map(|pair| pair[1].voltage);
+
+    PulsedIvCycle {
+        v_set,
+        v_reset,
+        hrs: negative.last().and_then(Peak::resistance),
+        lrs: positive.last().and_then(Peak::resistance),
+    }
+}
+
+pub fn measure_pulsed_iv_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    sweep: PulsedIvSweep,
+) -> Result<PulsedIvMeasurement, Error> {
+    if !sweep.is_valid(&ranges) {
+        return Result::Err(Error::WgfmuError(wgfmu::Error::BadArguments));
+    }
+
+    let (positive, negative) = sweep.branches();
+    let amplitudes = positive.iter().chain(negative.iter()).cloned().collect::<Vec<f64>>();
+    let (start, interval, avg_time) = sweep.sampling();
+
+    info!(
+        "Measuring a pulsed I-V sweep from {} V to {} V, {} cycles of {} pulses",
+        sweep.v_min,
+        sweep.v_max,
+        sweep.cycles,
+        amplitudes.len()
+    );
+
+    wgfmu.clear()?;
+
+    // One pattern per pulse of a cycle, they are sequenced again for every cycle
+    for (idx, &amplitude) in amplitudes.iter().enumerate() {
+        let force = format!("piv_{}", idx);
+        let waveform = sweep.waveform(amplitude);
+        let pulse_time = waveform.iter().map(|point| point.dtime).sum::<f64>();
+        wgfmu.create_pattern(force.as_str(), 0.0)?;
+        add_waveform(wgfmu, &waveform, force.as_str())?;
+        wgfmu.set_measure_event(
+            force.as_str(),
+            "pulse",
+            start,
+            sweep.n_points as i32,
+            interval,
+            avg_time,
+            MeasureEventMode::MeasureEventDataAveraged,
+        )?;
+
+        let ground = format!("piv_{}_v2", idx);
+        wgfmu.create_pattern(ground.as_str(), 0.0)?;
+        wgfmu.add_vector(ground.as_str(), pulse_time, 0.0)?;
+        wgfmu.set_measure_event(
+            ground.as_str(),
+            "pulse_current",
+            start,
+            sweep.n_points as i32,
+            interval,
+            avg_time,
+            MeasureEventMode::MeasureEventDataAveraged,
+        )?;
+    }
+
+    for _ in 0..sweep.cycles {
+        for idx in 0..amplitudes.len() {
+            wgfmu.add_sequence(channels.force, format!("piv_{}", idx).as_str(), 1)?;
+            wgfmu.add_sequence(channels.ground, format!("piv_{}_v2", idx).as_str(), 1)?;
+        }
+    }
+
+    info!("Initializing WGFMU");
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+    wgfmu.initialize()?;
+
+    setup_fastiv(wgfmu, channels, ranges)?;
+    wgfmu.execute()?;
+
+    let mut current_cycle = None;
+
+    info!("Performing measurements");
+    wait_until_completed_reporting(wgfmu, channels, |wgfmu, status| {
+        if status.total_time > 0.0 {
+            let index = ((status.elapsed_time / status.total_time * sweep.cycles as f64) as usize).min(sweep.cycles - 1);
+            if current_cycle != Some(index) {
+                current_cycle = Some(index);
+                wgfmu.report_step(Step {
+                    name: "cycle".to_string(),
+                    index,
+                    count: sweep.cycles,
+                });
+            }
+        }
+    })?;
+
+    info!("Retrieving data...");
+
+    let measurement = get_measurements(wgfmu, channels)?;
+
+    let peaks = measurement
+        .chunks(sweep.n_points)
+        .map(|pulse| {
+            pulse
+                .iter()
+                .max_by(|a, b| a.voltage.abs().total_cmp(&b.voltage.abs()))
+                .map(|sample| Peak {
+                    voltage: sample.voltage,
+                    current: sample.current.unwrap_or_default(),
+                })
+        })
+        .collect::<Option<Vec<Peak>>>()
+        .unwrap_or_default();
+
+    let positive_up = positive.len().div_ceil(2);
+    let negative_up = negative.len().div_ceil(2);
+    let cycles = peaks
+        .chunks(amplitudes.len())
+        .map(|cycle| {
+            let (pos, neg) = cycle.split_at(positive.len().min(cycle.len()));
+            analyze_cycle(&sweep.detection, pos, neg, positive_up, negative_up)
+        })
+        .collect::<Vec<PulsedIvCycle>>();
+
+    for (idx, cycle) in cycles.iter().enumerate() {
+        info!(
+            "Cycle {}: V_set {:?} V, V_reset {:?} V, HRS {:?} Ohm, LRS {:?} Ohm",
+            idx, cycle.v_set, cycle.v_reset, cycle.hrs, cycle.lrs
+        );
+    }
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(PulsedIvMeasurement {
+        iv: measurement,
+        cycles,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b1500::wgfmu::driver::ForceVoltageRange;
+
+    fn sweep(detection: SwitchDetection) -> PulsedIvSweep {
+        PulsedIvSweep {
+            v_max: 1.0,
+            v_min: -1.0,
+            step: 0.5,
+            shape: PulseShape::Rectangular,
+            pulse_width: 1e-6,
+            pulse_gap: 1e-6,
+            cycles: 1,
+            n_points: 10,
+            avg_time: 1e-8,
+            detection,
+        }
+    }
+
+    fn peaks(peaks: &[(f64, f64)]) -> Vec<Peak> {
+        peaks
+            .iter()
+            .map(|&(voltage, current)| Peak { voltage, current })
+            .collect()
+    }
+
+    #[test]
+    fn ramp() {
+        let jump = SwitchDetection::CurrentJump { threshold: 1e-4 };
+        assert_eq!(sweep(jump).ramp(1.0), vec![0.5, 1.0, 0.5]);
+        assert_eq!(sweep(jump).ramp(-1.0), vec![-0.5, -1.0, -0.5]);
+        // The last step is shortened to end on the limit
+        let ramp = PulsedIvSweep { step: 0.3, ..sweep(jump) }.ramp(1.0);
+        assert_eq!(ramp.len(), 5);
+        for (amplitude, expected) in ramp.iter().zip([0.3, 0.6, 1.0, 0.6, 0.3]) {
+            assert!((amplitude - expected).abs() < 1e-9, "{:?}", ramp);
+        }
+        // A step beyond the limit is a single pulse at the limit
+        assert_eq!(PulsedIvSweep { step: 2.0, ..sweep(jump) }.ramp(1.0), vec![1.0]);
+    }
+
+    #[test]
+    fn analyze_current_jump() {
+        let detection = SwitchDetection::CurrentJump { threshold: 1e-4 };
+        let positive = peaks(&[(0.5, 1e-5), (1.0, 5e-4), (0.5, 2.5e-4)]);
+        let negative = peaks(&[(-0.5, -2.5e-4), (-1.0, -1e-5), (-0.5, -5e-6)]);
+
+        let cycle = analyze_cycle(&detection, &positive, &negative, 2, 2);
+        assert_eq!(cycle.v_set, Some(1.0));
+        assert_eq!(cycle.v_reset, Some(-1.0));
+        assert!((cycle.lrs.unwrap() - 2e3).abs() < 1e-6);
+        assert!((cycle.hrs.unwrap() - 1e5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn analyze_conductance_ratio() {
+        let detection = SwitchDetection::ConductanceRatio { ratio: 10.0 };
+        // The conductance only grows 5 times on the positive branch
+        let positive = peaks(&[(0.5, 1e-5), (1.0, 1e-4), (0.5, 5e-5)]);
+        let negative = peaks(&[(-0.5, -5e-4), (-1.0, -1e-5), (-0.5, -5e-6)]);
+
+        let cycle = analyze_cycle(&detection, &positive, &negative, 2, 2);
+        assert_eq!(cycle.v_set, None);
+        assert_eq!(cycle.v_reset, Some(-1.0));
+    }
+
+    #[test]
+    fn analyze_only_ramps_up() {
+        let detection = SwitchDetection::CurrentJump { threshold: 1e-4 };
+        // The jump happens while the amplitude goes back down
+        let positive = peaks(&[(0.5, 1e-5), (1.0, 1e-5), (0.5, 1e-3)]);
+
+        let cycle = analyze_cycle(&detection, &positive, &[], 2, 2);
+        assert_eq!(cycle.v_set, None);
+        assert!((cycle.lrs.unwrap() - 500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn analyze_without_peaks() {
+        let detection = SwitchDetection::CurrentJump { threshold: 1e-4 };
+        assert_eq!(analyze_cycle(&detection, &[], &[], 2, 2), PulsedIvCycle::default());
+
+        // No resistance is read at 0 V or without current
+        let positive = peaks(&[(0.5, 1e-5), (0.0, 1e-5)]);
+        let negative = peaks(&[(-0.5, -1e-5), (-0.5, 0.0)]);
+        let cycle = analyze_cycle(&detection, &positive, &negative, 5, 5);
+        assert_eq!((cycle.lrs, cycle.hrs), (None, None));
+    }
+
+    #[test]
+    fn is_valid() {
+        let ranges = Ranges::default();
+        assert!(sweep(SwitchDetection::CurrentJump { threshold: 1e-4 }).is_valid(&ranges));
+        assert!(sweep(SwitchDetection::ConductanceRatio { ratio: 2.0 }).is_valid(&ranges));
+
+        assert!(!sweep(SwitchDetection::CurrentJump { threshold: 0.0 }).is_valid(&ranges));
+        assert!(!sweep(SwitchDetection::CurrentJump { threshold: -1e-4 }).is_valid(&ranges));
+        assert!(!sweep(SwitchDetection::ConductanceRatio { ratio: 1.0 }).is_valid(&ranges));
+        assert!(!sweep(SwitchDetection::ConductanceRatio { ratio: 0.5 }).is_valid(&ranges));
+
+        let jump = SwitchDetection::CurrentJump { threshold: 1e-4 };
+        assert!(!PulsedIvSweep { v_max: 0.0, ..sweep(jump) }.is_valid(&ranges));
+        assert!(!PulsedIvSweep { v_min: 0.5, ..sweep(jump) }.is_valid(&ranges));
+        assert!(!PulsedIvSweep { step: 0.0, ..sweep(jump) }.is_valid(&ranges));
+        assert!(!PulsedIvSweep { cycles: 0, ..sweep(jump) }.is_valid(&ranges));
+        // 10 points on a 50 ns pulse are closer than 10 ns
+        assert!(!PulsedIvSweep { pulse_width: 5e-8, ..sweep(jump) }.is_valid(&ranges));
+        assert!(!PulsedIvSweep {
+            shape: PulseShape::Triangular,
+            pulse_width: 1e-8,
+            n_points: 1,
+            ..sweep(jump)
+        }
+        .is_valid(&ranges));
+
+        // The amplitudes have to fit in the force range
+        let ranges = Ranges {
+            force_voltage: Some(ForceVoltageRange::Range3V),
+            ..Default::default()
+        };
+        assert!(sweep(jump).is_valid(&ranges));
+        assert!(!PulsedIvSweep { v_max: 5.0, ..sweep(jump) }.is_valid(&ranges));
+    }
+}
@@ -0,0 +1,309 @@
+use std::time::Duration;
+
+use log::info;
+use sea_orm::prelude::DateTimeLocal;
+use serde::{Deserialize, Serialize};
+
+use crate::b1500::types::{Channels, Ranges, VoltageWaveForm, VoltageWaveFormPoint};
+use crate::b1500::utils::add_waveform;
+use crate::b1500::wgfmu::{self, WgfmuDriver};
+
+use super::{
+    utils::hold, utils::measure_conductance_fastiv, utils::round_10ns, utils::setup_fastiv, utils::wait_until_completed,
+    Continuation, Error,
+};
+
+/// Reads closer than this to the previous one are waited for holding the instrument, it is released otherwise
+const HOLD_TIME: f64 = 10.0;
+
+/// Most reads per decade, a read takes the instrument for a while and closer ones would just follow each other
+const MAX_READS_PER_DECADE: usize = 100;
+
+/// Pulses that program the state whose retention is measured, `count` pulses of `amplitude` and `width` followed by
+/// `gap` seconds at 0 V each
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramPulse {
+    /// (Volts)
+    pub amplitude: f64,
+    /// (seconds)
+    pub width: f64,
+    #[serde(default = "one_pulse")]
+    pub count: usize,
+    /// (seconds)
+    #[serde(default)]
+    pub gap: f64,
+}
+
+fn one_pulse() -> usize {
+    1
+}
+
+/// Retention test, the device is programmed and its conductance read right away and then at logarithmically spaced
+/// times, `reads_per_decade` reads every decade from `first_read` to `last_read` seconds after programming.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Retention {
+    /// Programs the device before the first read, the state it is in is measured otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<ProgramPulse>,
+    /// (seconds)
+    #[serde(default = "one_second")]
+    pub first_read: f64,
+    /// (seconds)
+    pub last_read: f64,
+    #[serde(default = "one_read")]
+    pub reads_per_decade: usize,
+}
+
+fn one_second() -> f64 {
+    1.0
+}
+
+fn one_read() -> usize {
+    1
+}
+
+/// Conductance read at `time` seconds after the device was programmed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionRead {
+    /// (seconds)
+    pub time: f64,
+    /// When the read started
+    pub date: DateTimeLocal,
+    /// (Siemens)
+    pub conductance: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionMeasurement {
+    pub programmed_at: DateTimeLocal,
+    pub reads: Vec<RetentionRead>,
+}
+
+impl Retention {
+    /// Whether the test can be run
+    pub fn is_valid(&self) -> bool {
+        let program_ok = self.program.as_ref().is_none_or(|program| {
+            program.count >= 1 && program.width >= 1e-8 && program.gap >= 0.0 && program.amplitude.abs() <= 10.0
+        });
+
+        program_ok
+            && self.first_read > 0.0
+            && self.last_read >= self.first_read
+            && self.reads_per_decade >= 1
+            && self.reads_per_decade <= MAX_READS_PER_DECADE
+    }
+
+    /// Times of the reads after the first one, in seconds after programming
+    pub fn read_times(&self) -> Vec<f64> {
+        (0..)
+            .map(|k| self.first_read * f64::powf(10.0, k as f64 / self.reads_per_decade as f64))
+            .take_while(|&t| t <= self.last_read * (1.0 + 1e-9))
+            .collect()
+    }
+
+    /// First read time, in seconds after programming, still ahead `elapsed` seconds after programming
+    fn next_read(&self, elapsed: f64) -> Option<f64> {
+        self.read_times().into_iter().find(|&t| t > elapsed)
+    }
+}
+
+fn program_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    channels: Channels,
+    ranges: Ranges,
+    program: &ProgramPulse,
+) -> Result<(), Error> {
+    let mut waveform: VoltageWaveForm = vec![
+        VoltageWaveFormPoint {
+            dtime: 1e-8,
+            voltage: program.amplitude,
+        },
+        VoltageWaveFormPoint {
+            dtime: round_10ns(program.width),
+            voltage: program.amplitude,
+        },
+        VoltageWaveFormPoint {
+            dtime: 1e-8,
+            voltage: 0.0,
+        },
+    ];
+    if program.gap > 0.0 {
+        waveform.push(VoltageWaveFormPoint {
+            dtime: round_10ns(program.gap),
+            voltage: 0.0,
+        });
+    }
+    let total_time = waveform.iter().map(|point| point.dtime).sum::<f64>();
+
+    wgfmu.clear()?;
+
+    wgfmu.create_pattern("program", 0.0)?;
+    add_waveform(wgfmu, &waveform, "program")?;
+    wgfmu.add_sequence(channels.force, "program", program.count)?;
+
+    wgfmu.create_pattern("program_v2", 0.0)?;
+    wgfmu.add_vector("program_v2", total_time, 0.0)?;
+    wgfmu.add_sequence(channels.ground, "program_v2", program.count)?;
+
+    wgfmu.initialize()?;
+    setup_fastiv(wgfmu, channels, ranges)?;
+    wgfmu.execute()?;
+    wait_until_completed(wgfmu, channels)?;
+
+    Ok(())
+}
+
+fn seconds(duration: chrono::Duration) -> f64 {
+    duration.num_microseconds().unwrap_or(i64::MAX) as f64 * 1e-6
+}
+
+/// Runs the retention test from where `reads` left it, programming the device first if nothing was read yet. Every
+/// read is reported as it is taken. Reads that come shortly after are taken right away, when the next one is further
+/// ahead the test returns when it is due, so the instrument can be released meanwhile.
+pub fn measure_retention_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    retention: &Retention,
+    reads: &[RetentionRead],
+) -> Result<Continuation<RetentionMeasurement>, Error> {
+    if !retention.is_valid() {
+        return Result::Err(Error::WgfmuError(wgfmu::Error::BadArguments));
+    }
+
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+
+    let mut reads = reads.to_vec();
+    let programmed_at = match reads.first() {
+        Some(first) => first.date - chrono::Duration::microseconds((first.time * 1e6) as i64),
+        None => {
+            if let Some(program) = &retention.program {
+                info!("Programming the device with {} V pulses", program.amplitude);
+                program_fastiv(wgfmu, channels, ranges, program)?;
+            }
+            chrono::Local::now()
+        }
+    };
+
+    let next = loop {
+        let date = chrono::Local::now();
+        let conductance = measure_conductance_fastiv(wgfmu, None, channels, ranges)?;
+        let read = RetentionRead {
+            time: seconds(date - programmed_at),
+            date,
+            conductance,
+        };
+        info!("Retention read at {:.1} s, {} S", read.time, read.conductance);
+
+        wgfmu.report_data(serde_json::to_value(read).unwrap_or_default());
+        reads.push(read);
+
+        let elapsed = seconds(chrono::Local::now() - programmed_at);
+        match retention.next_read(elapsed) {
+            Some(next) if next - elapsed < HOLD_TIME => {
+                hold(wgfmu, channels, Duration::from_secs_f64(next - elapsed))?;
+            }
+            next => break next,
+        }
+    };
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(match next {
+        Some(next) => Continuation::Later(programmed_at + chrono::Duration::microseconds((next * 1e6) as i64)),
+        None => Continuation::Done(RetentionMeasurement { programmed_at, reads }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Instant;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::b1500::instrument::InstrumentManager;
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+
+    fn retention(first_read: f64, last_read: f64, reads_per_decade: usize) -> Retention {
+        Retention {
+            program: None,
+            first_read,
+            last_read,
+            reads_per_decade,
+        }
+    }
+
+    fn assert_times(times: &[f64], expected: &[f64]) {
+        assert_eq!(times.len(), expected.len(), "{:?}", times);
+        for (time, expected) in times.iter().zip(expected) {
+            assert!((time - expected).abs() < 1e-6 * expected, "{:?}", times);
+        }
+    }
+
+    #[test]
+    fn read_times() {
+        assert_times(&retention(1.0, 100.0, 1).read_times(), &[1.0, 10.0, 100.0]);
+        // Evenly spaced on a log scale
+        let sqrt10 = f64::sqrt(10.0);
+        assert_times(
+            &retention(1.0, 100.0, 2).read_times(),
+            &[1.0, sqrt10, 10.0, 10.0 * sqrt10, 100.0],
+        );
+        assert_times(&retention(2.0, 50.0, 1).read_times(), &[2.0, 20.0]);
+        assert_times(&retention(5.0, 5.0, 3).read_times(), &[5.0]);
+    }
+
+    #[test]
+    fn next_read() {
+        let retention = retention(1.0, 100.0, 1);
+        assert_eq!(retention.next_read(0.0), Some(1.0));
+        assert_eq!(retention.next_read(1.0), Some(10.0));
+        assert_eq!(retention.next_read(50.0), Some(100.0));
+        assert_eq!(retention.next_read(100.0), None);
+    }
+
+    #[test]
+    fn is_valid() {
+        assert!(retention(1.0, 100.0, MAX_READS_PER_DECADE).is_valid());
+        assert!(!retention(1.0, 100.0, MAX_READS_PER_DECADE + 1).is_valid());
+        assert!(!retention(1.0, 100.0, 0).is_valid());
+        assert!(!retention(0.0, 100.0, 1).is_valid());
+        assert!(!retention(10.0, 1.0, 1).is_valid());
+    }
+
+    #[test]
+    fn abort_while_holding() {
+        let manager = InstrumentManager::new(Box::new(TestWgfmu::new(MemristorParams::default()).unwrap()));
+        let (sink, mut reported) = mpsc::channel(64);
+
+        // The read right away is followed by a 9 s wait for the next one, holding the instrument
+        let measuring = Arc::clone(&manager);
+        let run = thread::spawn(move || {
+            let mut lease = measuring.lease("Retention").unwrap();
+            lease.set_measurement(1);
+            lease.set_sink(sink);
+            let retention = retention(9.0, 9.0, 1);
+            measure_retention_fastiv(&mut lease, None, Default::default(), Default::default(), &retention, &[])
+        });
+
+        reported.blocking_recv().unwrap();
+        let aborted = Instant::now();
+        assert!(manager.abort(1).unwrap());
+
+        assert!(matches!(run.join().unwrap(), Err(Error::Aborted(_))));
+        assert!(aborted.elapsed() < Duration::from_secs(5));
+    }
+}
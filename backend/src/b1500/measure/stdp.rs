@@ -1,11 +1,9 @@
-use std::sync::Arc;
-
 use log::info;
 use serde::{Serialize, Deserialize};
 
-use crate::b1500::{wgfmu::{driver::{Measurement, MeasureEventMode, OperationMode, MeasureMode}, WgfmuDriver}, WGFMU, types::{VoltageWaveForm, VoltageWaveFormPoint, Noise, GaussianNoise}, utils::{add_waveform, add_noisy_waveform}, CHANNEL2, CHANNEL1};
+use crate::b1500::{wgfmu::{driver::{Measurement, MeasureEventMode, Step}, WgfmuDriver}, types::{Channels, CurrentRanges, Ranges, VoltageWaveForm, VoltageWaveFormPoint, Noise, GaussianNoise}, utils::{add_waveform, add_noisy_waveform}};
 
-use super::{Error, utils::round_10ns, utils::measure_conductance_fastiv};
+use super::{Error, utils::add_range_events, utils::round_10ns, utils::measure_conductance_fastiv, utils::wait_until_completed, utils::wait_until_completed_quietly, utils::setup_fastiv, utils::get_measurements};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum StdpType {
@@ -19,8 +17,14 @@ pub struct StdpMeasurement {
     conductance: f64, // (S)
 }
 
-pub fn measure_stdp_fastiv(
+/// STDP measurement at `delay`, followed by a conductance read. The measured points are reported as they arrive when
+/// `stream` is set, collections leave it off and report every finished STDP measurement instead.
+pub fn measure_stdp_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
     instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    current_ranges: Option<CurrentRanges>,
     delay: f64,
     amplitude: f64,
     pulse_duration: f64,
@@ -30,6 +34,7 @@ pub fn measure_stdp_fastiv(
     stdp_type: StdpType,
     noise: bool,
     noise_std: f64,
+    stream: bool,
 ) -> Result<StdpMeasurement, Error> {
     info!(
         "Measuring STDP at {} V Amplitude and {} ns delay",
@@ -39,8 +44,6 @@ pub fn measure_stdp_fastiv(
 
     let measurement;
     {
-        let wgfmu = Arc::clone(&WGFMU);
-        let mut wgfmu = wgfmu.lock()?;
 
         {
             wgfmu.clear()?;
@@ -71,7 +74,7 @@ pub fn measure_stdp_fastiv(
 
                 let mut waveform: VoltageWaveForm = vec![];
 
-                // CHANNEL2
+                // Force channel
                 // Initializing the "v1" pattern at 0, this is for SMU1
                 wgfmu.create_pattern("v1", 0.0)?;
 
@@ -152,10 +155,10 @@ pub fn measure_stdp_fastiv(
                 });
 
                 if !noise {
-                    add_waveform(&mut wgfmu, &waveform, "v1")?;
+                    add_waveform(wgfmu, &waveform, "v1")?;
                 } else {
                     add_noisy_waveform(
-                        &mut wgfmu,
+                        wgfmu,
                         &waveform,
                         points as usize,
                         "v1",
@@ -167,7 +170,7 @@ pub fn measure_stdp_fastiv(
                 }
 
                 // Add the created waveform ONE time
-                wgfmu.add_sequence(CHANNEL2, "v1", 1)?;
+                wgfmu.add_sequence(channels.force, "v1", 1)?;
 
                 wgfmu.set_measure_event(
                     "v1",
@@ -182,7 +185,7 @@ pub fn measure_stdp_fastiv(
                 // Sampling margin
                 wgfmu.create_pattern("v1_margin", 0.0)?;
                 wgfmu.add_vector("v1_margin", cycle_time / 4.0, 0.0)?;
-                wgfmu.add_sequence(CHANNEL2, "v1_margin", 1)?;
+                wgfmu.add_sequence(channels.force, "v1_margin", 1)?;
             }
 
             {
@@ -192,7 +195,18 @@ pub fn measure_stdp_fastiv(
                 wgfmu.create_pattern("v2", 0.0)?;
                 // End at 0
                 wgfmu.add_vector("v2", total_time, 0.0)?;
-                wgfmu.add_sequence(CHANNEL1, "v2", 1)?;
+                wgfmu.add_sequence(channels.ground, "v2", 1)?;
+
+                if let Some(current_ranges) = current_ranges {
+                    // The pulses start after the first wait and end once the force channel is back at 0
+                    let pulse_start = round_10ns(1e-8 + wait_time);
+                    let pulse_end = if delay != 0.0 {
+                        round_10ns(pulse_start + pulse_duration + delay + 2e-8)
+                    } else {
+                        round_10ns(pulse_start + pulse_duration / 2.0)
+                    };
+                    add_range_events(wgfmu, "v2", current_ranges, &[(pulse_start, pulse_end)])?;
+                }
                 wgfmu.set_measure_event(
                     "v2",
                     "event",
@@ -209,21 +223,20 @@ pub fn measure_stdp_fastiv(
             }
             wgfmu.initialize()?;
 
-            wgfmu.set_operation_mode(CHANNEL2, OperationMode::OperationModeFastIV)?;
-            wgfmu.set_operation_mode(CHANNEL1, OperationMode::OperationModeFastIV)?;
-            wgfmu.set_measure_mode(CHANNEL2, MeasureMode::MeasureModeVoltage)?;
-            wgfmu.set_measure_mode(CHANNEL1, MeasureMode::MeasureModeCurrent)?;
-            wgfmu.connect(CHANNEL2)?;
-            wgfmu.connect(CHANNEL1)?;
+            setup_fastiv(wgfmu, channels, ranges)?;
             wgfmu.execute()?;
 
             info!("Performing measurements");
-            wgfmu.wait_until_completed()?;
+            if stream {
+                wait_until_completed(wgfmu, channels)?;
+            } else {
+                wait_until_completed_quietly(wgfmu, channels)?;
+            }
 
             info!("Retrieving data...");
         }
 
-        measurement = wgfmu.get_measure_values(CHANNEL2)?;
+        measurement = get_measurements(wgfmu, channels)?;
 
         info!("Stdp measurement length: {}", measurement.len());
 
@@ -234,7 +247,7 @@ pub fn measure_stdp_fastiv(
 
     std::thread::sleep(std::time::Duration::from_millis(1000)); // Litle wait before conductance measurement
 
-    let conductance = measure_conductance_fastiv(instrument)?;
+    let conductance = measure_conductance_fastiv(wgfmu, instrument, channels, ranges)?;
 
     Ok(StdpMeasurement {
         iv: measurement,
@@ -271,8 +284,26 @@ pub enum StdpCollectionMeasMode {
 
 const MAX_FORCE_CONDUCTANCE_TRIES: usize = 3;
 
-pub fn measure_stdp_collection_fastiv(
+/// When the collection is aborted, the STDP measurements completed so far are kept as its partial data
+fn keep_completed(error: Error, base_conductance: f64, collection: &Vec<StdpMeasurementWrapper>) -> Error {
+    match error {
+        Error::Aborted(_) => Error::Aborted(
+            serde_json::to_value(StdpCollectionMeasurement {
+                base_conductance,
+                collection: collection.clone(),
+            })
+            .unwrap_or_default(),
+        ),
+        error => error,
+    }
+}
+
+pub fn measure_stdp_collection_fastiv<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
     instrument: &str,
+    channels: Channels,
+    ranges: Ranges,
+    current_ranges: Option<CurrentRanges>,
     delay_points: usize,
     amplitude: f64,
     wait_time: f64,
@@ -289,15 +320,11 @@ pub fn measure_stdp_collection_fastiv(
         delay_points
     );
 
-    {
-        let wgfmu = Arc::clone(&WGFMU);
-        let mut wgfmu = wgfmu.lock()?;
-        wgfmu.open_session(instrument)?;
-    }
+    wgfmu.open_session(instrument)?;
 
     let max_delay = pulse_duration / 2.0 * 0.9;
 
-    let base_conductance = measure_conductance_fastiv(None)?;
+    let base_conductance = measure_conductance_fastiv(wgfmu, None, channels, ranges)?;
 
     info!("-------------------------------");
     info!(
@@ -319,11 +346,21 @@ pub fn measure_stdp_collection_fastiv(
     delays.pop();
     delays.reverse();
 
-    for delay in delays {
-        let get_meas = || -> Result<StdpMeasurementWrapper, Error> {
-            Ok(StdpMeasurementWrapper {
+    let n_delays = delays.len();
+    for (idx, delay) in delays.into_iter().enumerate() {
+        let mut get_meas = || -> Result<StdpMeasurementWrapper, Error> {
+            wgfmu.report_step(Step {
+                name: "delay point".to_string(),
+                index: idx,
+                count: n_delays,
+            });
+            let meas = StdpMeasurementWrapper {
                 stdp_measurement: measure_stdp_fastiv(
+                    &mut *wgfmu,
                     None,
+                    channels,
+                    ranges,
+                    current_ranges,
                     delay,
                     amplitude,
                     pulse_duration,
@@ -333,15 +370,20 @@ pub fn measure_stdp_collection_fastiv(
                     stdp_type,
                     noise,
                     noise_std,
+                    false,
                 )?,
                 delay: match stdp_type {
                     StdpType::Depression => -delay,
                     StdpType::Potenciation => delay,
                 },
-            })
+            };
+
+            // Every finished delay point is published right away, its points were not reported while measuring
+            wgfmu.report_data(serde_json::to_value(&meas).unwrap_or_default());
+            Ok(meas)
         };
 
-        let mut meas = get_meas()?;
+        let mut meas = get_meas().map_err(|err| keep_completed(err, base_conductance, &collection))?;
         match meas_mode {
             StdpCollectionMeasMode::SequentialMeasurement => {
                 collection.push(meas);
@@ -415,7 +457,7 @@ pub fn measure_stdp_collection_fastiv(
                 while ntries < MAX_FORCE_CONDUCTANCE_TRIES
                     && !conductance_ok(meas.stdp_measurement.conductance)
                 {
-                    meas = get_meas()?;
+                    meas = get_meas().map_err(|err| keep_completed(err, base_conductance, &collection))?;
                     // if goodness_test(best_meas.stdp_measurement.conductance, meas.stdp_measurement.conductance) > 0.0 {
                     //     best_meas = meas.clone();
                     // }
@@ -435,14 +477,54 @@ pub fn measure_stdp_collection_fastiv(
         }
     }
 
-    {
-        let wgfmu = Arc::clone(&WGFMU);
-        let mut wgfmu = wgfmu.lock()?;
-        wgfmu.close_session()?;
-    }
+    wgfmu.close_session()?;
 
     Ok(StdpCollectionMeasurement {
         base_conductance,
         collection,
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::b1500::instrument::InstrumentManager;
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+
+    #[test]
+    fn collection_reports_every_point_once() {
+        let manager = InstrumentManager::new(Box::new(TestWgfmu::new(MemristorParams::default()).unwrap()));
+        let mut lease = manager.lease("STDP collection").unwrap();
+        let (sink, mut reported) = mpsc::channel(64);
+        lease.set_sink(sink);
+
+        let measurement = measure_stdp_collection_fastiv(
+            &mut lease,
+            "b1500gpib",
+            Default::default(),
+            Default::default(),
+            None,
+            2,
+            1.0,
+            1e-5,
+            1e-4,
+            StdpType::Depression,
+            10,
+            1e-8,
+            false,
+            0.0,
+            StdpCollectionMeasMode::SequentialMeasurement,
+        )
+        .unwrap();
+        drop(lease);
+
+        let mut chunks = vec![];
+        while let Ok(chunk) = reported.try_recv() {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), measurement.collection.len());
+        assert!(chunks.iter().all(|chunk| chunk.get("stdpMeasurement").is_some()));
+    }
+}
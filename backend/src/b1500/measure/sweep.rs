@@ -0,0 +1,213 @@
+use std::time::Instant;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::driver::{MeasureMode, Measurement, OperationMode, Step, DC_SAMPLING_PERIOD};
+use crate::b1500::wgfmu::{self, WgfmuDriver};
+
+use super::{utils::check_channels, utils::set_ranges, Error};
+
+/// Highest number of samples, and of sampling periods between them, a DC averaged measurement takes
+const MAX_DC_SAMPLES: usize = 65535;
+
+/// Quasi-static I-V sweep, the voltage is held at every step while the current is averaged.
+///
+/// Notes:
+///          ___         stop
+///      ___|   |___
+///  ___|           |___ start (bidirectional)
+///  |<>| avg_time, every stair is one step high
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IvSweep {
+    /// First voltage of the sweep. (Volts)
+    pub start: f64,
+    /// Last voltage of the sweep, or the turning point of a bidirectional one. (Volts)
+    pub stop: f64,
+    /// Voltage difference between two consecutive points, in absolute value. (Volts)
+    pub step: f64,
+    /// Whether the sweep goes back from stop to start
+    #[serde(default)]
+    pub bidirectional: bool,
+    /// Number of times the sweep is repeated
+    #[serde(default = "one_cycle")]
+    pub cycles: usize,
+    /// Time the current is averaged at every point. (seconds)
+    pub avg_time: f64,
+}
+
+fn one_cycle() -> usize {
+    1
+}
+
+impl IvSweep {
+    /// Voltages forced, in order, None when the sweep does not make sense
+    pub fn voltages(&self) -> Option<Vec<f64>> {
+        if self.step <= 0.0
+            || self.step.is_nan()
+            || !self.start.is_finite()
+            || !self.stop.is_finite()
+            || self.cycles < 1
+        {
+            return None;
+        }
+
+        let n_steps = f64::round((self.stop - self.start).abs() / self.step) as usize;
+        let direction = f64::signum(self.stop - self.start);
+        let mut sweep = (0..n_steps)
+            .map(|i| self.start + direction * self.step * i as f64)
+            .chain(std::iter::once(self.stop))
+            .collect::<Vec<f64>>();
+
+        if self.bidirectional {
+            let back = sweep.iter().rev().skip(1).cloned().collect::<Vec<f64>>();
+            sweep.extend(back);
+        }
+
+        // A bidirectional sweep ends where the next one starts, that point is not measured twice
+        let mut voltages = sweep.clone();
+        for _ in 1..self.cycles {
+            let skip = if self.bidirectional { 1 } else { 0 };
+            voltages.extend(sweep.iter().skip(skip));
+        }
+
+        Some(voltages)
+    }
+
+    /// Samples and sampling interval, in DC sampling periods, that average the current for about `avg_time`
+    fn averaging(&self) -> (usize, usize) {
+        let samples = f64::round(self.avg_time / DC_SAMPLING_PERIOD).max(1.0) as usize;
+        let interval = samples.div_ceil(MAX_DC_SAMPLES);
+        let points = (samples / interval).clamp(1, MAX_DC_SAMPLES);
+
+        (points, interval.min(MAX_DC_SAMPLES))
+    }
+}
+
+/// Runs a quasi-static I-V sweep in the DC operation mode. The force channel steps the voltage and measures it, the
+/// ground one is held at 0 V and averages the current.
+pub fn measure_iv_sweep<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    instrument: Option<&str>,
+    channels: Channels,
+    ranges: Ranges,
+    sweep: IvSweep,
+) -> Result<Vec<Measurement>, Error> {
+    let voltages = match sweep.voltages() {
+        Some(voltages) => voltages,
+        None => return Result::Err(Error::WgfmuError(wgfmu::Error::BadArguments)),
+    };
+    let (points, interval) = sweep.averaging();
+
+    info!(
+        "Measuring an I-V sweep from {} V to {} V, {} points averaged for {} ms",
+        sweep.start,
+        sweep.stop,
+        voltages.len(),
+        (points * interval) as f64 * DC_SAMPLING_PERIOD * 1e3
+    );
+
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+    wgfmu.initialize()?;
+
+    check_channels(wgfmu, channels)?;
+    wgfmu.set_operation_mode(channels.force, OperationMode::OperationModeDC)?;
+    wgfmu.set_operation_mode(channels.ground, OperationMode::OperationModeDC)?;
+    wgfmu.set_measure_mode(channels.force, MeasureMode::MeasureModeVoltage)?;
+    wgfmu.set_measure_mode(channels.ground, MeasureMode::MeasureModeCurrent)?;
+    set_ranges(wgfmu, channels, ranges)?;
+    wgfmu.connect(channels.force)?;
+    wgfmu.connect(channels.ground)?;
+    wgfmu.dc_force_voltage(channels.ground, 0.0)?;
+
+    let started = Instant::now();
+    let mut measurement = vec![];
+    let mut sweep_points = || -> Result<(), wgfmu::Error> {
+        for (idx, &voltage) in voltages.iter().enumerate() {
+            wgfmu.report_step(Step {
+                name: "sweep point".to_string(),
+                index: idx,
+                count: voltages.len(),
+            });
+
+            wgfmu.dc_force_voltage(channels.force, voltage)?;
+            let current = wgfmu.dc_measure_averaged_value(channels.ground, points, interval)?;
+            let point = Measurement {
+                voltage: wgfmu.dc_measure_value(channels.force)?,
+                current: Some(current),
+                time: started.elapsed().as_secs_f64(),
+            };
+
+            wgfmu.report_data(serde_json::to_value([point]).unwrap_or_default());
+            measurement.push(point);
+        }
+
+        Ok(())
+    };
+    let result = sweep_points();
+
+    // The device is left unbiased, whatever happened to the sweep
+    let unbiased = wgfmu.dc_force_voltage(channels.force, 0.0);
+
+    match result {
+        Err(wgfmu::Error::Aborted) => {
+            info!("Measurement aborted, {} points were measured", measurement.len());
+            return Err(Error::Aborted(serde_json::to_value(measurement).unwrap_or_default()));
+        }
+        res => res?,
+    }
+    unbiased?;
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(measurement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(start: f64, stop: f64, step: f64, bidirectional: bool, cycles: usize) -> IvSweep {
+        IvSweep {
+            start,
+            stop,
+            step,
+            bidirectional,
+            cycles,
+            avg_time: 1e-3,
+        }
+    }
+
+    fn assert_voltages(sweep: IvSweep, expected: &[f64]) {
+        let voltages = sweep.voltages().unwrap();
+        assert_eq!(voltages.len(), expected.len(), "{:?}", voltages);
+        for (voltage, expected) in voltages.iter().zip(expected) {
+            assert!((voltage - expected).abs() < 1e-9, "{:?}", voltages);
+        }
+    }
+
+    #[test]
+    fn voltages() {
+        assert_voltages(sweep(0.0, 1.0, 0.5, false, 1), &[0.0, 0.5, 1.0]);
+        assert_voltages(sweep(1.0, -1.0, 1.0, false, 1), &[1.0, 0.0, -1.0]);
+        // The last step is shortened to end on stop
+        assert_voltages(sweep(0.0, 1.0, 0.3, false, 1), &[0.0, 0.3, 0.6, 1.0]);
+        assert_voltages(sweep(0.0, 1.0, 0.5, true, 1), &[0.0, 0.5, 1.0, 0.5, 0.0]);
+        assert_voltages(sweep(0.0, 1.0, 0.5, false, 2), &[0.0, 0.5, 1.0, 0.0, 0.5, 1.0]);
+        assert_voltages(sweep(0.0, 1.0, 0.5, true, 2), &[0.0, 0.5, 1.0, 0.5, 0.0, 0.5, 1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn invalid_voltages() {
+        assert!(sweep(0.0, 1.0, 0.0, false, 1).voltages().is_none());
+        assert!(sweep(0.0, 1.0, -0.1, false, 1).voltages().is_none());
+        assert!(sweep(0.0, f64::NAN, 0.1, false, 1).voltages().is_none());
+        assert!(sweep(0.0, 1.0, 0.1, false, 0).voltages().is_none());
+    }
+}
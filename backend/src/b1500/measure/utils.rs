@@ -1,8 +1,8 @@
-use std::{sync::Arc, fs::File, io::Write};
+use std::{fs::File, io::Write, time::{Duration, Instant}};
 
-use log::info;
+use log::{info, warn};
 
-use crate::b1500::{WGFMU, CHANNEL1, CHANNEL2, wgfmu::{driver::{MeasureEventMode, OperationMode, MeasureMode}, WgfmuDriver}};
+use crate::b1500::{types::{Channels, CurrentRanges, Ranges}, wgfmu::{self, driver::{ChannelStatus, MeasureEventMode, OperationMode, MeasureMode, Measurement, RecordSet, Status}, WgfmuDriver}};
 use super::Error;
 
 
@@ -10,9 +10,191 @@ pub fn round_10ns(n: f64) -> f64 {
     f64::floor(n * 1e8) / 1e8
 }
 
-pub fn measure_conductance_fastiv(instrument: Option<&str>) -> Result<f64, Error> {
-    let wgfmu = Arc::clone(&WGFMU);
-    let mut wgfmu = wgfmu.lock()?;
+/// Time between two reads of the sequencer status while waiting for it
+const STATUS_POLL_PERIOD: Duration = Duration::from_millis(200);
+
+/// Fails when both roles are given to the same channel or a channel is not installed in the instrument. The session
+/// has to be open.
+pub fn check_channels<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels) -> Result<(), wgfmu::Error> {
+    if channels.force == channels.ground {
+        warn!("Channel {} can not both force and ground the device", channels.force);
+        return Result::Err(wgfmu::Error::BadArguments);
+    }
+
+    let installed = wgfmu.get_channel_ids()?;
+    for chan_id in [channels.force, channels.ground] {
+        if !installed.contains(&chan_id) {
+            warn!("Channel {} is not installed, the instrument has {:?}", chan_id, installed);
+            return Result::Err(wgfmu::Error::ChannelNotFoundError);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets the channels up for a Fast IV measurement, the force channel measures the voltage and the ground one the
+/// current. The session has to be open and initialized.
+pub fn setup_fastiv<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels, ranges: Ranges) -> Result<(), wgfmu::Error> {
+    check_channels(wgfmu, channels)?;
+
+    wgfmu.set_operation_mode(channels.force, OperationMode::OperationModeFastIV)?;
+    wgfmu.set_operation_mode(channels.ground, OperationMode::OperationModeFastIV)?;
+    wgfmu.set_measure_mode(channels.force, MeasureMode::MeasureModeVoltage)?;
+    wgfmu.set_measure_mode(channels.ground, MeasureMode::MeasureModeCurrent)?;
+    set_ranges(wgfmu, channels, ranges)?;
+    wgfmu.connect(channels.force)?;
+    wgfmu.connect(channels.ground)
+}
+
+/// Applies the ranges and delays that are set, the rest keep the values `initialize` left. The measure modes have to
+/// be set already.
+pub fn set_ranges<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels, ranges: Ranges) -> Result<(), wgfmu::Error> {
+    for chan_id in [channels.force, channels.ground] {
+        if let Some(range) = ranges.force_voltage {
+            wgfmu.set_force_voltage_range(chan_id, range)?;
+        }
+        if let Some(delay) = ranges.force_delay {
+            wgfmu.set_force_delay(chan_id, delay)?;
+        }
+        if let Some(delay) = ranges.measure_delay {
+            wgfmu.set_measure_delay(chan_id, delay)?;
+        }
+    }
+
+    if let Some(range) = ranges.measure_voltage {
+        wgfmu.set_measure_voltage_range(channels.force, range)?;
+    }
+    if let Some(range) = ranges.measure_current {
+        wgfmu.set_measure_current_range(channels.ground, range)?;
+    }
+
+    Ok(())
+}
+
+/// Adds range events to `pattern`, the current is measured at the high range during the `pulses`, (start, end) times
+/// of the pattern, and at the low range the rest of it
+pub fn add_range_events<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
+    pattern: &str,
+    ranges: CurrentRanges,
+    pulses: &[(f64, f64)],
+) -> Result<(), wgfmu::Error> {
+    if pulses.first().is_none_or(|&(start, _)| start > 0.0) {
+        wgfmu.set_range_event(pattern, "range_low", 0.0, ranges.low)?;
+    }
+
+    for (i, &(start, end)) in pulses.iter().enumerate() {
+        wgfmu.set_range_event(pattern, format!("range_high_{}", i).as_str(), start, ranges.high)?;
+        wgfmu.set_range_event(pattern, format!("range_low_{}", i).as_str(), end, ranges.low)?;
+    }
+
+    Ok(())
+}
+
+/// The voltage measured by the force channel, matched by time with the current measured by the ground one
+pub fn get_measurements<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels) -> Result<Vec<Measurement>, wgfmu::Error> {
+    let records = wgfmu.get_record_set(&[channels.force, channels.ground])?;
+    if records.is_mismatched() {
+        warn!(
+            "Channels {} and {} measured {} and {} points, aligning them by time",
+            channels.force,
+            channels.ground,
+            records.channels[0].samples.len(),
+            records.channels[1].samples.len()
+        );
+    }
+
+    Ok(records.measurements(channels.force, channels.ground))
+}
+
+/// Waits for the sequencer to finish, the measurements are reported as they get measured. When the measurement is
+/// aborted the points measured until then are returned with the error.
+pub fn wait_until_completed<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels) -> Result<(), Error> {
+    wait_until_completed_reporting(wgfmu, channels, |_, _| ())
+}
+
+/// Same as `wait_until_completed`, `report` gets every status of the force channel read while waiting.
+pub fn wait_until_completed_reporting<D, F>(wgfmu: &mut D, channels: Channels, mut report: F) -> Result<(), Error>
+where
+    D: WgfmuDriver + ?Sized,
+    F: FnMut(&mut D, &ChannelStatus),
+{
+    match poll_until_completed(wgfmu, channels, &mut report, true) {
+        Err(wgfmu::driver::Error::Aborted) => {
+            let partial = get_measurements(wgfmu, channels).unwrap_or_default();
+            info!("Measurement aborted, {} points were measured", partial.len());
+            Err(Error::Aborted(serde_json::to_value(partial).unwrap_or_default()))
+        }
+        res => Ok(res?),
+    }
+}
+
+/// Waits for the sequencer to finish without reporting the measured points, for the measurements that report their
+/// own results. An abort is seen while waiting, the data reported until then is kept.
+pub fn wait_until_completed_quietly<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels) -> Result<(), Error> {
+    Ok(poll_until_completed(wgfmu, channels, &mut |_, _| (), false)?)
+}
+
+/// Polls the sequencer until it is done, the points completed meanwhile are reported when `stream` is set
+fn poll_until_completed<D, F>(wgfmu: &mut D, channels: Channels, report: &mut F, stream: bool) -> Result<(), wgfmu::Error>
+where
+    D: WgfmuDriver + ?Sized,
+    F: FnMut(&mut D, &ChannelStatus),
+{
+    let mut offset = 0;
+    let mut read_events = 0;
+
+    loop {
+        let status = wgfmu.get_channel_status(channels.force)?;
+        report(wgfmu, &status);
+
+        match status.status {
+            Status::Running | Status::RunningIllegal if !stream => std::thread::sleep(STATUS_POLL_PERIOD),
+            Status::Running | Status::RunningIllegal => {
+                // Only completed events are read, nothing new to read until one more completes
+                let (completed_events, _) = wgfmu.get_completed_measure_event_size(channels.force)?;
+                if completed_events == read_events {
+                    std::thread::sleep(STATUS_POLL_PERIOD);
+                    continue;
+                }
+                read_events = completed_events;
+
+                let mut force = wgfmu.get_completed_measure_values(channels.force, offset)?;
+                let mut ground = wgfmu.get_completed_measure_values(channels.ground, offset)?;
+
+                // Both channels measure at the same times, the one that has less points completed limits the chunk
+                let completed = usize::min(force.samples.len(), ground.samples.len());
+                force.samples.truncate(completed);
+                ground.samples.truncate(completed);
+                if completed > 0 {
+                    offset += completed;
+                    let chunk = RecordSet { channels: vec![force, ground] }.measurements(channels.force, channels.ground);
+                    wgfmu.report_data(serde_json::to_value(chunk).unwrap_or_default());
+                }
+
+                std::thread::sleep(STATUS_POLL_PERIOD)
+            }
+            _ => return wgfmu.wait_until_completed(),
+        }
+    }
+}
+
+/// Waits `duration` holding the instrument, the status of the force channel is read every `STATUS_POLL_PERIOD` so an
+/// abort is seen meanwhile
+pub fn hold<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, channels: Channels, duration: Duration) -> Result<(), wgfmu::Error> {
+    let until = Instant::now() + duration;
+    loop {
+        wgfmu.get_channel_status(channels.force)?;
+
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(left.min(STATUS_POLL_PERIOD));
+    }
+}
+
+pub fn measure_conductance_fastiv<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, instrument: Option<&str>, channels: Channels, ranges: Ranges) -> Result<f64, Error> {
 
     println!("clear");
     wgfmu.clear()?;
@@ -22,7 +204,7 @@ pub fn measure_conductance_fastiv(instrument: Option<&str>) -> Result<f64, Error
     let test_v = -0.1;
     let test_time = 1.0;
     {
-        // CHANNEL2
+        // Force channel
         // Initializing the "v1" pattern at 0, this is for SMU1
         wgfmu.create_pattern("v1", 0.0)?;
 
@@ -31,7 +213,7 @@ pub fn measure_conductance_fastiv(instrument: Option<&str>) -> Result<f64, Error
         wgfmu.add_vector("v1", test_time, test_v)?;
 
         // Add the created waveform ONE time
-        wgfmu.add_sequence(CHANNEL2, "v1", 1)?;
+        wgfmu.add_sequence(channels.force, "v1", 1)?;
 
         // Sampling measurements High
         let time_sampling_resolution = 10e-3;
@@ -50,7 +232,7 @@ pub fn measure_conductance_fastiv(instrument: Option<&str>) -> Result<f64, Error
         // Sampling margin
         wgfmu.create_pattern("v1_margin", 0.0)?;
         wgfmu.add_vector("v1_margin", test_time / 8.0, 0.0)?;
-        wgfmu.add_sequence(CHANNEL2, "v1_margin", 1)?;
+        wgfmu.add_sequence(channels.force, "v1_margin", 1)?;
     }
 
     {
@@ -59,7 +241,7 @@ pub fn measure_conductance_fastiv(instrument: Option<&str>) -> Result<f64, Error
 
         // End at 0
         wgfmu.set_vector("v2", test_time * 9.0 / 8.0 + 1e-8, 0.0)?;
-        wgfmu.add_sequence(CHANNEL1, "v2", 1)?;
+        wgfmu.add_sequence(channels.ground, "v2", 1)?;
 
         let time_sampling_resolution = 10e-3;
         let points = f64::floor((test_time - avg_time) / time_sampling_resolution) as i32 - 1;
@@ -81,23 +263,18 @@ pub fn measure_conductance_fastiv(instrument: Option<&str>) -> Result<f64, Error
     }
     wgfmu.initialize()?;
 
-    wgfmu.set_operation_mode(CHANNEL2, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_operation_mode(CHANNEL1, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_measure_mode(CHANNEL2, MeasureMode::MeasureModeVoltage)?;
-    wgfmu.set_measure_mode(CHANNEL1, MeasureMode::MeasureModeCurrent)?;
-    wgfmu.connect(CHANNEL2)?;
-    wgfmu.connect(CHANNEL1)?;
+    setup_fastiv(wgfmu, channels, ranges)?;
 
     // wgfmu.do_self_calibration().unwrap();
 
     wgfmu.execute()?;
 
     info!("Performing measurements");
-    wgfmu.wait_until_completed()?;
+    wait_until_completed_quietly(wgfmu, channels)?;
 
     info!("Retrieving data...");
 
-    let measurement = wgfmu.get_measure_values(CHANNEL2)?;
+    let measurement = get_measurements(wgfmu, channels)?;
 
     let mut f = File::create("test.csv".to_owned()).expect("Could not open file");
 
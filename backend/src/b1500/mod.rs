@@ -1,4 +1,6 @@
 // pub mod measure;
+pub mod events;
+pub mod instrument;
 pub mod measure;
 pub mod types;
 pub mod utils;
@@ -7,18 +9,48 @@ pub mod wgfmu;
 pub const CHANNEL1: usize = 101;
 pub const CHANNEL2: usize = 102;
 
-use lazy_static::lazy_static;
-use std::sync::{Arc, Mutex};
-#[allow(unused_imports)]
-use wgfmu::{production::ProductionWgfmu, sim::TestWgfmu, WgfmuDriver};
+use crate::config::{Config, DriverKind};
+use instrument::Driver;
+use wgfmu::{
+    fault::FaultyWgfmu,
+    production::ProductionWgfmu,
+    remote::RemoteWgfmu,
+    sim::TestWgfmu,
+    trace::{RecordingWgfmu, ReplayWgfmu},
+};
 
-#[cfg(target_os = "windows")]
-lazy_static! {
-    pub static ref WGFMU: Arc<Mutex<ProductionWgfmu<'static>>> =
-        Arc::new(Mutex::new(ProductionWgfmu::new().unwrap()));
-}
+/// Creates the WGFMU driver described by the configuration.
+pub fn init_wgfmu(cfg: &Config) -> Result<Driver, Box<dyn std::error::Error>> {
+    let mut driver: Driver = match cfg.driver.kind {
+        DriverKind::Production => Box::new(ProductionWgfmu::new()?),
+        DriverKind::Simulator => Box::new(TestWgfmu::new(cfg.memristor.clone())?),
+        DriverKind::Replay => match &cfg.driver.trace {
+            Some(trace) => Box::new(ReplayWgfmu::new(trace)?),
+            None => return Err("The replay driver needs a trace file".into()),
+        },
+        DriverKind::Remote => match &cfg.driver.remote {
+            Some(remote) => Box::new(RemoteWgfmu::new(remote, cfg.driver.token.as_deref())?),
+            None => return Err("The remote driver needs the server address".into()),
+        },
+    };
+
+    // A replayed trace already holds the warning policy of the session it was recorded from
+    if cfg.driver.kind != DriverKind::Replay {
+        if let Some(level) = cfg.driver.warning_level {
+            driver.set_warning_level(level)?;
+        }
+        if let Some(level) = cfg.driver.warnings_as_errors {
+            driver.treat_warnings_as_errors(level)?;
+        }
+    }
+
+    if !cfg.faults.is_empty() {
+        driver = Box::new(FaultyWgfmu::new(driver, cfg.faults.clone()));
+    }
+
+    if let Some(record) = &cfg.driver.record {
+        driver = Box::new(RecordingWgfmu::new(driver, record)?);
+    }
 
-#[cfg(target_os = "linux")]
-lazy_static! {
-    pub static ref WGFMU: Arc<Mutex<TestWgfmu>> = Arc::new(Mutex::new(TestWgfmu::new().unwrap()));
+    Ok(driver)
 }
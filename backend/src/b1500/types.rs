@@ -1,3 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+use super::wgfmu::driver::{ForceVoltageRange, MeasureCurrentRange, MeasureVoltageRange};
+use super::{CHANNEL1, CHANNEL2};
+
 pub struct GaussianNoise {
     pub mean: f64,
     pub sigma: f64,
@@ -14,3 +19,46 @@ pub struct VoltageWaveFormPoint {
 }
 
 pub type VoltageWaveForm = Vec<VoltageWaveFormPoint>;
+
+/// Which WGFMU channels a measurement uses, the device under test is wired between them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Channels {
+    /// Forces the waveform and measures the voltage
+    pub force: usize,
+    /// Held at 0 V, measures the current
+    pub ground: usize,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels {
+            force: CHANNEL2,
+            ground: CHANNEL1,
+        }
+    }
+}
+
+/// Range and delay settings of a measurement, the ones left out keep the instrument defaults
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ranges {
+    /// Range both channels force with
+    pub force_voltage: Option<ForceVoltageRange>,
+    /// Range the force channel measures the voltage with
+    pub measure_voltage: Option<MeasureVoltageRange>,
+    /// Range the ground channel measures the current with
+    pub measure_current: Option<MeasureCurrentRange>,
+    /// Delay of the output of both channels, in seconds
+    pub force_delay: Option<f64>,
+    /// Delay of the measurements of both channels, in seconds
+    pub measure_delay: Option<f64>,
+}
+
+/// Current ranges a measurement switches between, the high one while the device is pulsed and the low one while it is
+/// read or at rest
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CurrentRanges {
+    pub high: MeasureCurrentRange,
+    pub low: MeasureCurrentRange,
+}
@@ -1,15 +1,14 @@
-use std::sync::{MutexGuard, Arc, PoisonError};
+use std::fmt::Display;
+use std::sync::PoisonError;
 
 use log::info;
 use rand_distr::{Distribution, Normal, NormalError};
-use sea_orm::strum::Display;
 
-use super::types::{Noise, VoltageWaveForm, VoltageWaveFormPoint};
-use super::wgfmu::driver::{OperationMode, MeasureMode};
+use super::measure::utils::setup_fastiv;
+use super::types::{Channels, Noise, Ranges, VoltageWaveForm, VoltageWaveFormPoint};
 use super::wgfmu::{self, WgfmuDriver};
-use super::{WGFMU, CHANNEL1, CHANNEL2};
 
-#[derive(Debug, Display)]
+#[derive(Debug)]
 pub enum Error {
     BadArguments(String),
     WgfmuError(wgfmu::Error),
@@ -17,6 +16,17 @@ pub enum Error {
     WgfmuMutexLockError
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadArguments(arguments) => write!(f, "bad arguments {}", arguments),
+            Error::WgfmuError(error) => write!(f, "{}", error),
+            Error::RandomDistributionError(error) => write!(f, "{}", error),
+            Error::WgfmuMutexLockError => write!(f, "the WGFMU mutex is poisoned"),
+        }
+    }
+}
+
 impl From<wgfmu::Error> for Error {
     fn from(error: wgfmu::Error) -> Self {
         Error::WgfmuError(error)
@@ -40,7 +50,7 @@ impl<T> From<PoisonError<T>> for Error {
 ///
 /// # Arguments
 ///
-/// * `wgfmu` - WGFMU static object, used when adding the waveform to the specified pattern.
+/// * `wgfmu` - WGFMU driver, used when adding the waveform to the specified pattern.
 /// * `waveform` - Non sampled voltage waveform to add noise to.
 /// * `n_points` - Number of sampling points to use.
 /// * `pattern` - Pattern name where to add the sampled waveform.
@@ -60,8 +70,8 @@ impl<T> From<PoisonError<T>> for Error {
 ///   [V]        .     .     .     .
 ///    |   *.*.*.*     *.*.*.*     *.*.*.*
 /// // In this case, `n_points` is 20.
-pub fn add_noisy_waveform<D: WgfmuDriver>(
-    wgfmu: &mut MutexGuard<D>,
+pub fn add_noisy_waveform<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
     waveform: &VoltageWaveForm,
     n_points: usize,
     pattern: &str,
@@ -140,8 +150,8 @@ pub fn add_noisy_waveform<D: WgfmuDriver>(
     Ok(())
 }
 
-pub fn add_waveform<D: WgfmuDriver>(
-    wgfmu: &mut MutexGuard<D>,
+pub fn add_waveform<D: WgfmuDriver + ?Sized>(
+    wgfmu: &mut D,
     waveform: &VoltageWaveForm,
     pattern: &str,
 ) -> Result<(), Error> {
@@ -152,9 +162,7 @@ pub fn add_waveform<D: WgfmuDriver>(
     Ok(())
 }
 
-pub fn calibrate(instrument: Option<&str>) -> Result<(), Error> {
-    let wgfmu = Arc::clone(&WGFMU);
-    let mut wgfmu = wgfmu.lock()?;
+pub fn calibrate<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, instrument: Option<&str>, channels: Channels) -> Result<(), Error> {
 
     wgfmu.clear()?;
 
@@ -163,12 +171,7 @@ pub fn calibrate(instrument: Option<&str>) -> Result<(), Error> {
     }
     wgfmu.initialize()?;
 
-    wgfmu.set_operation_mode(CHANNEL2, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_operation_mode(CHANNEL1, OperationMode::OperationModeFastIV)?;
-    wgfmu.set_measure_mode(CHANNEL2, MeasureMode::MeasureModeVoltage)?;
-    wgfmu.set_measure_mode(CHANNEL1, MeasureMode::MeasureModeCurrent)?;
-    wgfmu.connect(CHANNEL2)?;
-    wgfmu.connect(CHANNEL1)?;
+    setup_fastiv(wgfmu, channels, Ranges::default())?;
 
     wgfmu.do_self_calibration()?;
 
@@ -178,3 +181,18 @@ pub fn calibrate(instrument: Option<&str>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Ids of the WGFMU channels installed in the instrument
+pub fn discover_channels<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, instrument: Option<&str>) -> Result<Vec<usize>, Error> {
+    if let Some(instrument) = instrument {
+        wgfmu.open_session(instrument)?;
+    }
+
+    let channels = wgfmu.get_channel_ids();
+
+    if instrument.is_some() {
+        wgfmu.close_session()?;
+    }
+
+    Ok(channels?)
+}
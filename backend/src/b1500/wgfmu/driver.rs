@@ -9,6 +9,7 @@ pub enum CalibrationtTestResult {
 }
 
 #[allow(dead_code)]
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Status {
     Completed = 10000,
     Done = 10001,
@@ -19,11 +20,18 @@ pub enum Status {
     Idle = 10006,
 }
 
-#[derive(FromPrimitive, ToPrimitive, Debug, Serialize, Deserialize)]
+/// Error codes of the WGFMU library calls. They are kept as plain codes, which traces record and the remote server
+/// sends as they are, the text the library logs along with them is read after the failing call with
+/// `WgfmuDriver::reported`, the library keeps it for the whole session.
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Error {
     BadArguments, // This one is mine, does not correspond to any B1500 dll error.
     MutexUnlockError,
     NotImplemented,
+    ReplayMismatch, // The call does not match the next one in the replayed trace.
+    RemoteConnectionError, // The connection with the remote WGFMU server failed.
+    Aborted, // The measurement was aborted, the sequencer has been stopped.
+    RemoteAuthenticationError, // The remote WGFMU server refused the token.
 
     ParameterOutOfRangeError = -1,
     IllegalStringError = -2,
@@ -42,10 +50,29 @@ pub enum Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            // Error::NotImplemented(ref err) => write!(f, "RPPAL Spi error: {}", err),
-            _ => write!(f, "WGFMU error:"),
-        }
+        let description = match *self {
+            Error::BadArguments => "bad arguments",
+            Error::MutexUnlockError => "the driver mutex is poisoned",
+            Error::NotImplemented => "not implemented",
+            Error::ReplayMismatch => "the call does not match the replayed trace",
+            Error::RemoteConnectionError => "the connection with the remote WGFMU failed",
+            Error::Aborted => "the measurement was aborted",
+            Error::RemoteAuthenticationError => "the remote WGFMU refused the token",
+            Error::ParameterOutOfRangeError => "parameter out of range",
+            Error::IllegalStringError => "illegal string",
+            Error::ContextError => "the call is not allowed in the current state",
+            Error::FunctionNotSupportedError => "function not supported",
+            Error::CommunicationError => "communication with the instrument failed",
+            Error::FwError => "instrument firmware error",
+            Error::LibraryError => "WGFMU library error",
+            Error::UnidentifiedError => "unidentified error",
+            Error::ChannelNotFoundError => "channel not found",
+            Error::PatternNotFoundError => "pattern not found",
+            Error::EventNotFoundError => "event not found",
+            Error::PatternAlreadyExistsError => "pattern already exists",
+            Error::SequencerNotRunningError => "the sequencer is not running",
+        };
+        write!(f, "WGFMU error: {}", description)
     }
 }
 
@@ -57,7 +84,17 @@ impl<T> From<PoisonError<T>> for Error {
     }
 }
 
-#[derive(FromPrimitive, ToPrimitive, Debug)]
+/// Severity of the warnings the WGFMU library reports, every level includes the ones before it
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningLevel {
+    Off = 1000,
+    Severe = 1001,
+    Normal = 1002,
+    Information = 1003,
+}
+
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum OperationMode {
     OperationModeDC = 2000,
     OperationModeFastIV = 2001,
@@ -65,25 +102,222 @@ pub enum OperationMode {
     OperationModeSMU = 2003,
 }
 
-#[derive(FromPrimitive, ToPrimitive, Debug)]
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum MeasureMode {
     MeasureModeVoltage = 4000,
     MeasureModeCurrent = 4001,
 }
 
-#[derive(FromPrimitive, ToPrimitive, Debug)]
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum MeasureEventMode {
     MeasureEventDataAveraged = 12000,
     MeasureEventDataRaw = 12001,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+/// Range of the voltage a channel forces in the Fast IV and PG modes, auto picks the smallest one that fits the
+/// sequence
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ForceVoltageRange {
+    #[serde(rename = "auto")]
+    Auto = 3000,
+    #[serde(rename = "3V")]
+    Range3V = 3001,
+    #[serde(rename = "5V")]
+    Range5V = 3002,
+    #[serde(rename = "-10V")]
+    Range10VNegative = 3003,
+    #[serde(rename = "10V")]
+    Range10VPositive = 3004,
+}
+
+impl ForceVoltageRange {
+    /// Lowest and highest voltage the range can force, None for auto
+    pub fn limits(&self) -> Option<(f64, f64)> {
+        match self {
+            ForceVoltageRange::Auto => None,
+            ForceVoltageRange::Range3V => Some((-3.0, 3.0)),
+            ForceVoltageRange::Range5V => Some((-5.0, 5.0)),
+            ForceVoltageRange::Range10VNegative => Some((-10.0, 0.0)),
+            ForceVoltageRange::Range10VPositive => Some((0.0, 10.0)),
+        }
+    }
+}
+
+/// Range of the voltage a channel measures in the Fast IV mode
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MeasureVoltageRange {
+    #[serde(rename = "5V")]
+    Range5V = 5001,
+    #[serde(rename = "10V")]
+    Range10V = 5002,
+}
+
+impl MeasureVoltageRange {
+    /// Highest voltage, in absolute value, the range can measure
+    pub fn full_scale(&self) -> f64 {
+        match self {
+            MeasureVoltageRange::Range5V => 5.0,
+            MeasureVoltageRange::Range10V => 10.0,
+        }
+    }
+}
+
+/// Range of the current a channel measures in the Fast IV mode. Smaller ranges resolve smaller currents, high
+/// resistance devices need them to be read accurately.
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MeasureCurrentRange {
+    #[serde(rename = "1uA")]
+    Range1uA = 6001,
+    #[serde(rename = "10uA")]
+    Range10uA = 6002,
+    #[serde(rename = "100uA")]
+    Range100uA = 6003,
+    #[serde(rename = "1mA")]
+    Range1mA = 6004,
+    #[serde(rename = "10mA")]
+    Range10mA = 6005,
+}
+
+impl MeasureCurrentRange {
+    /// Highest current, in absolute value, the range can measure
+    pub fn full_scale(&self) -> f64 {
+        match self {
+            MeasureCurrentRange::Range1uA => 1e-6,
+            MeasureCurrentRange::Range10uA => 1e-5,
+            MeasureCurrentRange::Range100uA => 1e-4,
+            MeasureCurrentRange::Range1mA => 1e-3,
+            MeasureCurrentRange::Range10mA => 1e-2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Measurement {
     pub voltage: f64,
     pub current: Option<f64>,
     pub time: f64,
 }
 
+/// Time unit of the sampling interval of the DC averaged measurements, in seconds
+pub const DC_SAMPLING_PERIOD: f64 = 5e-9;
+
+/// Samples of different channels closer in time than this, in seconds, are taken at the same time
+const SAMPLE_TIME_TOLERANCE: f64 = 1e-10;
+
+/// A single value measured by a channel
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub time: f64,
+    pub value: f64,
+}
+
+/// Everything a channel measured, the values are voltages or currents depending on its measure mode
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelData {
+    pub chan_id: usize,
+    pub measure_mode: MeasureMode,
+    pub samples: Vec<Sample>,
+}
+
+/// Values of all the channels of a record set at one time, in the order of its channels. A channel without a sample
+/// at that time has None.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlignedSample {
+    pub time: f64,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Data of several channels measured during the same execution
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordSet {
+    pub channels: Vec<ChannelData>,
+}
+
+impl RecordSet {
+    /// Whether the channels reported different point counts
+    pub fn is_mismatched(&self) -> bool {
+        self.channels
+            .windows(2)
+            .any(|pair| pair[0].samples.len() != pair[1].samples.len())
+    }
+
+    /// Merges the samples of all channels by timestamp. When the channels reported different point counts, the
+    /// samples missing from a channel are None instead of being paired with the wrong time.
+    pub fn aligned(&self) -> Vec<AlignedSample> {
+        let mut next = vec![0; self.channels.len()];
+        let mut aligned = vec![];
+
+        loop {
+            let time = self
+                .channels
+                .iter()
+                .zip(next.iter())
+                .filter_map(|(channel, &i)| channel.samples.get(i))
+                .map(|sample| sample.time)
+                .reduce(f64::min);
+            let time = match time {
+                Some(time) => time,
+                None => return aligned,
+            };
+
+            let values = self
+                .channels
+                .iter()
+                .zip(next.iter_mut())
+                .map(|(channel, i)| match channel.samples.get(*i) {
+                    Some(sample) if sample.time - time <= SAMPLE_TIME_TOLERANCE => {
+                        *i += 1;
+                        Some(sample.value)
+                    }
+                    _ => None,
+                })
+                .collect();
+            aligned.push(AlignedSample { time, values });
+        }
+    }
+
+    /// Pairs the `voltage` channel with the `current` one as measurements. Times with no voltage sample are dropped,
+    /// times with no current sample have no current.
+    pub fn measurements(&self, voltage: usize, current: usize) -> Vec<Measurement> {
+        let position = |chan_id| self.channels.iter().position(|channel| channel.chan_id == chan_id);
+        let voltage = match position(voltage) {
+            Some(voltage) => voltage,
+            None => return vec![],
+        };
+        let current = position(current);
+
+        self.aligned()
+            .into_iter()
+            .filter_map(|sample| {
+                Some(Measurement {
+                    voltage: sample.values[voltage]?,
+                    current: current.and_then(|current| sample.values[current]),
+                    time: sample.time,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Sequencer state of a channel, times are in seconds
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelStatus {
+    pub status: Status,
+    pub elapsed_time: f64,
+    pub total_time: f64,
+}
+
+/// Part of a longer measurement, e.g. the third delay point out of ten of an STDP collection
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    pub name: String,
+    pub index: usize,
+    pub count: usize,
+}
+
 pub type Res = Result<(), Error>;
 
 pub trait WgfmuDriver {
@@ -91,6 +325,23 @@ pub trait WgfmuDriver {
     fn open_session(&mut self, instrument: &str) -> Res;
     fn close_session(&mut self) -> Res;
     fn clear(&mut self) -> Res;
+    /// Ids of the channels installed in the instrument, the session has to be open
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error>;
+    /// Text of the last error reported by the WGFMU library, empty when there is none
+    fn get_error(&mut self) -> Result<String, Error>;
+    /// Text of every error reported by the WGFMU library
+    fn get_error_summary(&mut self) -> Result<String, Error>;
+    /// Text of every warning reported by the WGFMU library
+    fn get_warning_summary(&mut self) -> Result<String, Error>;
+    /// Errors and warnings reported by the WGFMU library, one per line, empty when there are none
+    fn reported(&mut self) -> String {
+        let texts = [self.get_error_summary(), self.get_warning_summary()];
+        let texts: Vec<String> = texts.into_iter().flatten().filter(|text| !text.is_empty()).collect();
+        texts.join("\n")
+    }
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res;
+    /// Warnings at `level` or more severe fail the call that raised them, `WarningLevel::Off` disables it
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res;
     fn create_pattern<'a>(&mut self, pattern: &'a str, init_v: f64) -> Res;
     fn add_vector<'a>(&mut self, pattern: &'a str, d_time: f64, voltage: f64) -> Res;
     fn add_vectors<'a>(&mut self, pattern: &'a str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res;
@@ -104,6 +355,8 @@ pub trait WgfmuDriver {
         average: f64,
         measure_event_mode: MeasureEventMode,
     ) -> Res;
+    /// Switches the current range of the channels running `pattern` to `range` at `time` of the pattern
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res;
     fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res;
     fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res;
     fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res;
@@ -112,9 +365,327 @@ pub trait WgfmuDriver {
     fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res;
     fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error>;
     fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error>;
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res;
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res;
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res;
+    /// Delays the output of the channel by `delay` seconds, from -50 ns to 50 ns
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res;
+    /// Delays the measurements of the channel by `delay` seconds, from -50 ns to 50 ns
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res;
     fn connect(&mut self, chan_id: usize) -> Res;
     fn execute(&mut self) -> Res;
     fn wait_until_completed(&mut self) -> Res;
-    fn get_measure_values(&mut self, chan_id: usize) -> Result<Vec<Measurement>, Error>;
+    fn abort(&mut self) -> Res;
+    fn abort_channel(&mut self, chan_id: usize) -> Res;
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error>;
+    /// Points of `chan_id` as the voltage, paired by time with the current measured by the `current` channel
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error>;
+    /// Number of measure events of the channel, (completed, total), it can be read while the sequencer runs.
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error>;
+    /// Samples of the channel already measured, starting at `offset`. It can be read while the sequencer runs.
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error>;
+    /// Every sample of a single channel, together with its measure mode
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error>;
     fn do_self_calibration(&mut self) -> Res;
+    /// Forces `voltage` right away, the channel has to be in the DC operation mode
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res;
+    /// Measures a single sample right away, a voltage or a current depending on the measure mode of the channel
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error>;
+    /// Averages `points` samples taken every `interval` times `DC_SAMPLING_PERIOD`, both from 1 to 65535
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error>;
+
+    /// Data of all `channels` measured in the last execution
+    fn get_record_set(&mut self, channels: &[usize]) -> Result<RecordSet, Error> {
+        let channels = channels
+            .iter()
+            .map(|&chan_id| self.get_channel_data(chan_id))
+            .collect::<Result<Vec<ChannelData>, Error>>()?;
+
+        Ok(RecordSet { channels })
+    }
+
+    /// Reports which step of a measurement is about to run, only drivers that publish progress care about it.
+    fn report_step(&mut self, _step: Step) {}
+
+    /// Reports data measured so far, only drivers that publish progress care about it.
+    fn report_data(&mut self, _data: serde_json::Value) {}
+}
+
+impl<D: WgfmuDriver + ?Sized> WgfmuDriver for Box<D> {
+    fn open_session(&mut self, instrument: &str) -> Res {
+        (**self).open_session(instrument)
+    }
+
+    fn close_session(&mut self) -> Res {
+        (**self).close_session()
+    }
+
+    fn clear(&mut self) -> Res {
+        (**self).clear()
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        (**self).get_channel_ids()
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        (**self).get_error()
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        (**self).get_error_summary()
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        (**self).get_warning_summary()
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        (**self).set_warning_level(level)
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        (**self).treat_warnings_as_errors(level)
+    }
+
+    fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
+        (**self).create_pattern(pattern, init_v)
+    }
+
+    fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        (**self).add_vector(pattern, d_time, voltage)
+    }
+
+    fn add_vectors(&mut self, pattern: &str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res {
+        (**self).add_vectors(pattern, d_time, voltage)
+    }
+
+    fn set_measure_event(
+        &mut self,
+        pattern: &str,
+        event: &str,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    ) -> Res {
+        (**self).set_measure_event(
+            pattern,
+            event,
+            time,
+            points,
+            interval,
+            average,
+            measure_event_mode,
+        )
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        (**self).set_range_event(pattern, event, time, range)
+    }
+
+    fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
+        (**self).add_sequence(chan_id, pattern, count)
+    }
+
+    fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res {
+        (**self).add_sequences(chan_id, pattern, count)
+    }
+
+    fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
+        (**self).set_vector(pattern, time, voltage)
+    }
+
+    fn initialize(&mut self) -> Res {
+        (**self).initialize()
+    }
+
+    fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
+        (**self).set_operation_mode(chan_id, operation_mode)
+    }
+
+    fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
+        (**self).set_measure_mode(chan_id, mode)
+    }
+
+    fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
+        (**self).get_measure_mode(chan_id)
+    }
+
+    fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
+        (**self).get_operation_mode(chan_id)
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        (**self).set_force_voltage_range(chan_id, range)
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        (**self).set_measure_voltage_range(chan_id, range)
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        (**self).set_measure_current_range(chan_id, range)
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        (**self).set_force_delay(chan_id, delay)
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        (**self).set_measure_delay(chan_id, delay)
+    }
+
+    fn connect(&mut self, chan_id: usize) -> Res {
+        (**self).connect(chan_id)
+    }
+
+    fn execute(&mut self) -> Res {
+        (**self).execute()
+    }
+
+    fn wait_until_completed(&mut self) -> Res {
+        (**self).wait_until_completed()
+    }
+
+    fn abort(&mut self) -> Res {
+        (**self).abort()
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        (**self).abort_channel(chan_id)
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        (**self).get_channel_status(chan_id)
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        (**self).get_measure_values(chan_id, current)
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        (**self).get_completed_measure_event_size(chan_id)
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        (**self).get_completed_measure_values(chan_id, offset)
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        (**self).get_channel_data(chan_id)
+    }
+
+    fn do_self_calibration(&mut self) -> Res {
+        (**self).do_self_calibration()
+    }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        (**self).dc_force_voltage(chan_id, voltage)
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        (**self).dc_measure_value(chan_id)
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        (**self).dc_measure_averaged_value(chan_id, points, interval)
+    }
+
+    fn report_step(&mut self, step: Step) {
+        (**self).report_step(step)
+    }
+
+    fn report_data(&mut self, data: serde_json::Value) {
+        (**self).report_data(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(chan_id: usize, measure_mode: MeasureMode, samples: &[(f64, f64)]) -> ChannelData {
+        ChannelData {
+            chan_id,
+            measure_mode,
+            samples: samples.iter().map(|&(time, value)| Sample { time, value }).collect(),
+        }
+    }
+
+    fn record_set(voltage: &[(f64, f64)], current: &[(f64, f64)]) -> RecordSet {
+        RecordSet {
+            channels: vec![
+                channel(101, MeasureMode::MeasureModeVoltage, voltage),
+                channel(102, MeasureMode::MeasureModeCurrent, current),
+            ],
+        }
+    }
+
+    #[test]
+    fn equal_counts() {
+        let records = record_set(&[(0.0, 1.0), (1e-6, 2.0)], &[(0.0, 1e-3), (1e-6, 2e-3)]);
+
+        assert!(!records.is_mismatched());
+        assert_eq!(
+            records.measurements(101, 102),
+            vec![
+                Measurement { voltage: 1.0, current: Some(1e-3), time: 0.0 },
+                Measurement { voltage: 2.0, current: Some(2e-3), time: 1e-6 },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_current_sample() {
+        let records = record_set(&[(0.0, 1.0), (1e-6, 2.0), (2e-6, 3.0)], &[(0.0, 1e-3), (2e-6, 3e-3)]);
+
+        assert!(records.is_mismatched());
+        assert_eq!(
+            records.measurements(101, 102),
+            vec![
+                Measurement { voltage: 1.0, current: Some(1e-3), time: 0.0 },
+                Measurement { voltage: 2.0, current: None, time: 1e-6 },
+                Measurement { voltage: 3.0, current: Some(3e-3), time: 2e-6 },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_voltage_sample() {
+        let records = record_set(&[(0.0, 1.0), (2e-6, 3.0)], &[(0.0, 1e-3), (1e-6, 2e-3), (2e-6, 3e-3)]);
+
+        assert!(records.is_mismatched());
+        assert_eq!(
+            records.aligned()[1],
+            AlignedSample {
+                time: 1e-6,
+                values: vec![None, Some(2e-3)],
+            }
+        );
+        assert_eq!(
+            records.measurements(101, 102),
+            vec![
+                Measurement { voltage: 1.0, current: Some(1e-3), time: 0.0 },
+                Measurement { voltage: 3.0, current: Some(3e-3), time: 2e-6 },
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamps_within_tolerance() {
+        let within = record_set(&[(1e-6, 2.0)], &[(1e-6 + SAMPLE_TIME_TOLERANCE / 2.0, 2e-3)]);
+        assert_eq!(
+            within.measurements(101, 102),
+            vec![Measurement { voltage: 2.0, current: Some(2e-3), time: 1e-6 }]
+        );
+
+        let beyond = record_set(&[(1e-6, 2.0)], &[(1e-6 + SAMPLE_TIME_TOLERANCE * 2.0, 2e-3)]);
+        assert_eq!(beyond.aligned().len(), 2);
+        assert_eq!(
+            beyond.measurements(101, 102),
+            vec![Measurement { voltage: 2.0, current: None, time: 1e-6 }]
+        );
+    }
 }
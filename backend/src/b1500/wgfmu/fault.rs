@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::driver::*;
+
+// Fault injection WGFMU driver, it delegates every call to an inner driver unless a rule says that call has to fail.
+// Used to check how measurements and the web server behave when the instrument misbehaves.
+
+/// Driver functions a fault can be attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Call {
+    OpenSession,
+    CloseSession,
+    Clear,
+    GetChannelIds,
+    GetError,
+    GetErrorSummary,
+    GetWarningSummary,
+    SetWarningLevel,
+    TreatWarningsAsErrors,
+    CreatePattern,
+    AddVector,
+    AddVectors,
+    SetMeasureEvent,
+    SetRangeEvent,
+    AddSequence,
+    AddSequences,
+    SetVector,
+    Initialize,
+    SetOperationMode,
+    SetMeasureMode,
+    GetMeasureMode,
+    GetOperationMode,
+    SetForceVoltageRange,
+    SetMeasureVoltageRange,
+    SetMeasureCurrentRange,
+    SetForceDelay,
+    SetMeasureDelay,
+    Connect,
+    Execute,
+    WaitUntilCompleted,
+    Abort,
+    AbortChannel,
+    GetChannelStatus,
+    GetMeasureValues,
+    GetCompletedMeasureEventSize,
+    GetCompletedMeasureValues,
+    GetChannelData,
+    DoSelfCalibration,
+    DcForceVoltage,
+    DcMeasureValue,
+    DcMeasureAveragedValue,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    /// The call returns this error without reaching the inner driver
+    Error(Error),
+    /// The call panics, poisoning the mutex that guards the driver
+    Panic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultRule {
+    pub call: Call,
+    /// Which call fails, counting from 1 since the driver was created. Every call fails when not specified.
+    pub nth: Option<usize>,
+    pub fault: Fault,
+}
+
+pub struct FaultyWgfmu<D: WgfmuDriver> {
+    inner: D,
+    rules: Vec<FaultRule>,
+    counts: HashMap<Call, usize>,
+}
+
+impl<D: WgfmuDriver> FaultyWgfmu<D> {
+    pub fn new(inner: D, rules: Vec<FaultRule>) -> FaultyWgfmu<D> {
+        FaultyWgfmu {
+            inner,
+            rules,
+            counts: HashMap::new(),
+        }
+    }
+
+    fn inject(&mut self, call: Call) -> Res {
+        let count = self.counts.entry(call).or_insert(0);
+        *count += 1;
+        let count = *count;
+
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| rule.call == call && rule.nth.is_none_or(|nth| nth == count));
+
+        match rule.map(|rule| rule.fault) {
+            Some(Fault::Error(err)) => {
+                warn!("Injecting {:?} on call {} to {:?}", err, count, call);
+                Result::Err(err)
+            }
+            Some(Fault::Panic) => panic!("Injected panic on call {} to {:?}", count, call),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<D: WgfmuDriver> WgfmuDriver for FaultyWgfmu<D> {
+    fn open_session(&mut self, instrument: &str) -> Res {
+        self.inject(Call::OpenSession)?;
+        self.inner.open_session(instrument)
+    }
+
+    fn close_session(&mut self) -> Res {
+        self.inject(Call::CloseSession)?;
+        self.inner.close_session()
+    }
+
+    fn clear(&mut self) -> Res {
+        self.inject(Call::Clear)?;
+        self.inner.clear()
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        self.inject(Call::GetChannelIds)?;
+        self.inner.get_channel_ids()
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        self.inject(Call::GetError)?;
+        self.inner.get_error()
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        self.inject(Call::GetErrorSummary)?;
+        self.inner.get_error_summary()
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        self.inject(Call::GetWarningSummary)?;
+        self.inner.get_warning_summary()
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        self.inject(Call::SetWarningLevel)?;
+        self.inner.set_warning_level(level)
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        self.inject(Call::TreatWarningsAsErrors)?;
+        self.inner.treat_warnings_as_errors(level)
+    }
+
+    fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
+        self.inject(Call::CreatePattern)?;
+        self.inner.create_pattern(pattern, init_v)
+    }
+
+    fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        self.inject(Call::AddVector)?;
+        self.inner.add_vector(pattern, d_time, voltage)
+    }
+
+    fn add_vectors(&mut self, pattern: &str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res {
+        self.inject(Call::AddVectors)?;
+        self.inner.add_vectors(pattern, d_time, voltage)
+    }
+
+    fn set_measure_event(
+        &mut self,
+        pattern: &str,
+        event: &str,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    ) -> Res {
+        self.inject(Call::SetMeasureEvent)?;
+        self.inner.set_measure_event(
+            pattern,
+            event,
+            time,
+            points,
+            interval,
+            average,
+            measure_event_mode,
+        )
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        self.inject(Call::SetRangeEvent)?;
+        self.inner.set_range_event(pattern, event, time, range)
+    }
+
+    fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
+        self.inject(Call::AddSequence)?;
+        self.inner.add_sequence(chan_id, pattern, count)
+    }
+
+    fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res {
+        self.inject(Call::AddSequences)?;
+        self.inner.add_sequences(chan_id, pattern, count)
+    }
+
+    fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
+        self.inject(Call::SetVector)?;
+        self.inner.set_vector(pattern, time, voltage)
+    }
+
+    fn initialize(&mut self) -> Res {
+        self.inject(Call::Initialize)?;
+        self.inner.initialize()
+    }
+
+    fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
+        self.inject(Call::SetOperationMode)?;
+        self.inner.set_operation_mode(chan_id, operation_mode)
+    }
+
+    fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
+        self.inject(Call::SetMeasureMode)?;
+        self.inner.set_measure_mode(chan_id, mode)
+    }
+
+    fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
+        self.inject(Call::GetMeasureMode)?;
+        self.inner.get_measure_mode(chan_id)
+    }
+
+    fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
+        self.inject(Call::GetOperationMode)?;
+        self.inner.get_operation_mode(chan_id)
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        self.inject(Call::SetForceVoltageRange)?;
+        self.inner.set_force_voltage_range(chan_id, range)
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        self.inject(Call::SetMeasureVoltageRange)?;
+        self.inner.set_measure_voltage_range(chan_id, range)
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        self.inject(Call::SetMeasureCurrentRange)?;
+        self.inner.set_measure_current_range(chan_id, range)
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.inject(Call::SetForceDelay)?;
+        self.inner.set_force_delay(chan_id, delay)
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.inject(Call::SetMeasureDelay)?;
+        self.inner.set_measure_delay(chan_id, delay)
+    }
+
+    fn connect(&mut self, chan_id: usize) -> Res {
+        self.inject(Call::Connect)?;
+        self.inner.connect(chan_id)
+    }
+
+    fn execute(&mut self) -> Res {
+        self.inject(Call::Execute)?;
+        self.inner.execute()
+    }
+
+    fn wait_until_completed(&mut self) -> Res {
+        self.inject(Call::WaitUntilCompleted)?;
+        self.inner.wait_until_completed()
+    }
+
+    fn abort(&mut self) -> Res {
+        self.inject(Call::Abort)?;
+        self.inner.abort()
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        self.inject(Call::AbortChannel)?;
+        self.inner.abort_channel(chan_id)
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        self.inject(Call::GetChannelStatus)?;
+        self.inner.get_channel_status(chan_id)
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        self.inject(Call::GetMeasureValues)?;
+        self.inner.get_measure_values(chan_id, current)
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        self.inject(Call::GetCompletedMeasureEventSize)?;
+        self.inner.get_completed_measure_event_size(chan_id)
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        self.inject(Call::GetCompletedMeasureValues)?;
+        self.inner.get_completed_measure_values(chan_id, offset)
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        self.inject(Call::GetChannelData)?;
+        self.inner.get_channel_data(chan_id)
+    }
+
+    fn do_self_calibration(&mut self) -> Res {
+        self.inject(Call::DoSelfCalibration)?;
+        self.inner.do_self_calibration()
+    }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        self.inject(Call::DcForceVoltage)?;
+        self.inner.dc_force_voltage(chan_id, voltage)
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        self.inject(Call::DcMeasureValue)?;
+        self.inner.dc_measure_value(chan_id)
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        self.inject(Call::DcMeasureAveragedValue)?;
+        self.inner.dc_measure_averaged_value(chan_id, points, interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::b1500::instrument::InstrumentManager;
+    use crate::b1500::measure::{self, utils::measure_conductance_fastiv};
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+
+    fn faulty(call: Call, nth: Option<usize>, fault: Fault) -> FaultyWgfmu<TestWgfmu> {
+        let inner = TestWgfmu::new(MemristorParams::default()).unwrap();
+        FaultyWgfmu::new(inner, vec![FaultRule { call, nth, fault }])
+    }
+
+    fn read<D: WgfmuDriver + ?Sized>(wgfmu: &mut D, instrument: Option<&str>) -> Result<f64, measure::Error> {
+        measure_conductance_fastiv(wgfmu, instrument, Default::default(), Default::default())
+    }
+
+    #[test]
+    fn communication_error_on_nth_execute() {
+        let mut wgfmu = faulty(Call::Execute, Some(2), Fault::Error(Error::CommunicationError));
+
+        assert!(read(&mut wgfmu, None).is_ok());
+        assert!(matches!(
+            read(&mut wgfmu, None),
+            Err(measure::Error::WgfmuError(Error::CommunicationError))
+        ));
+        assert!(read(&mut wgfmu, None).is_ok());
+    }
+
+    #[test]
+    fn context_error_on_open_session() {
+        let mut wgfmu = faulty(Call::OpenSession, None, Fault::Error(Error::ContextError));
+
+        assert!(matches!(
+            read(&mut wgfmu, Some("b1500gpib")),
+            Err(measure::Error::WgfmuError(Error::ContextError))
+        ));
+        assert!(read(&mut wgfmu, None).is_ok());
+    }
+
+    #[test]
+    fn panic_poisons_the_driver() {
+        let manager = InstrumentManager::new(Box::new(faulty(Call::Execute, Some(1), Fault::Panic)));
+
+        let panicking = Arc::clone(&manager);
+        let result = thread::spawn(move || {
+            let mut lease = panicking.lease("faulty measurement").unwrap();
+            read(&mut lease, None)
+        })
+        .join();
+        assert!(result.is_err());
+
+        let mut lease = manager.lease("next measurement").unwrap();
+        assert!(matches!(read(&mut lease, None), Err(measure::Error::WgfmuMutexLockError)));
+    }
+}
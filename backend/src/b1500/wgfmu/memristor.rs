@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+// Compact memristor model used by the simulated WGFMU. It is a VTEAM style model, with a normalized state variable
+// `x` (0 -> HRS, 1 -> LRS) that only moves when the voltage across the device is beyond one of the two thresholds,
+// and a sinh shaped I-V characteristic.
+//
+//            | k_set * (v / v_set - 1)^alpha_set * (1 - x)          v > v_set
+//  dx / dt = | 0                                                    v_reset <= v <= v_set
+//            | -k_reset * (v / v_reset - 1)^alpha_reset * x         v < v_reset
+//
+//  i = G(x) * sinh(nonlinearity * v) / nonlinearity,  G(x) = x / r_lrs + (1 - x) / r_hrs
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MemristorParams {
+    /// Resistance of the device when fully SET, low resistance state. (Ohm)
+    pub r_lrs: f64,
+    /// Resistance of the device when fully RESET, high resistance state. (Ohm)
+    pub r_hrs: f64,
+    /// Positive voltage threshold above which the device starts to SET. (Volts)
+    pub v_set: f64,
+    /// Negative voltage threshold below which the device starts to RESET. (Volts)
+    pub v_reset: f64,
+    /// SET rate constant. (1/s)
+    pub k_set: f64,
+    /// RESET rate constant. (1/s)
+    pub k_reset: f64,
+    /// SET voltage dependence exponent.
+    pub alpha_set: f64,
+    /// RESET voltage dependence exponent.
+    pub alpha_reset: f64,
+    /// I-V nonlinearity factor, 0 means an ohmic device. (1/V)
+    pub nonlinearity: f64,
+    /// State of the device when the simulator is started, between 0 (HRS) and 1 (LRS).
+    pub initial_state: f64,
+    /// Maximum integration step while the device is switching. (seconds)
+    pub max_time_step: f64,
+}
+
+impl Default for MemristorParams {
+    fn default() -> Self {
+        MemristorParams {
+            r_lrs: 10e3,
+            r_hrs: 100e3,
+            v_set: 0.8,
+            v_reset: -0.8,
+            k_set: 1e3,
+            k_reset: 1e3,
+            alpha_set: 3.0,
+            alpha_reset: 3.0,
+            nonlinearity: 2.0,
+            initial_state: 0.5,
+            max_time_step: 1e-6,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Memristor {
+    params: MemristorParams,
+    /// Normalized state variable, 0 -> HRS, 1 -> LRS
+    x: f64,
+}
+
+impl Memristor {
+    pub fn new(params: MemristorParams) -> Memristor {
+        let x = params.initial_state.clamp(0.0, 1.0);
+        Memristor { params, x }
+    }
+
+    pub fn state(&self) -> f64 {
+        self.x
+    }
+
+    pub fn conductance(&self) -> f64 {
+        self.x / self.params.r_lrs + (1.0 - self.x) / self.params.r_hrs
+    }
+
+    /// Current flowing through the device when `voltage` is applied across it. (A)
+    pub fn current(&self, voltage: f64) -> f64 {
+        let a = self.params.nonlinearity;
+        if a == 0.0 {
+            self.conductance() * voltage
+        } else {
+            self.conductance() * f64::sinh(a * voltage) / a
+        }
+    }
+
+    /// Switching rate of the state variable, the direction is given by the sign.
+    fn rate(&self, voltage: f64) -> f64 {
+        let p = &self.params;
+        if voltage > p.v_set {
+            p.k_set * (voltage / p.v_set - 1.0).powf(p.alpha_set)
+        } else if voltage < p.v_reset {
+            -p.k_reset * (voltage / p.v_reset - 1.0).powf(p.alpha_reset)
+        } else {
+            0.0
+        }
+    }
+
+    /// Integrates the state variable over a linear voltage ramp from `v_start` to `v_end` lasting `dtime` seconds.
+    pub fn apply(&mut self, v_start: f64, v_end: f64, dtime: f64) {
+        if dtime <= 0.0 {
+            return;
+        }
+
+        // Below both thresholds during the whole ramp, nothing to integrate
+        if v_start.max(v_end) <= self.params.v_set && v_start.min(v_end) >= self.params.v_reset {
+            return;
+        }
+
+        let steps = f64::ceil(dtime / self.params.max_time_step).max(1.0) as usize;
+        let step = dtime / steps as f64;
+
+        for i in 0..steps {
+            // The rate is evaluated at the middle of each step and the state is updated with the exact solution for a
+            // constant voltage, so large steps saturate instead of overshooting the bounds.
+            let voltage = v_start + (v_end - v_start) * (i as f64 + 0.5) / steps as f64;
+            let rate = self.rate(voltage);
+            if rate > 0.0 {
+                self.x = 1.0 - (1.0 - self.x) * f64::exp(-rate * step);
+            } else if rate < 0.0 {
+                self.x *= f64::exp(rate * step);
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
 #[rustfmt::skip]
 pub mod sim;
 pub mod driver;
+pub mod fault;
+pub mod memristor;
 pub mod production;
+pub mod remote;
+pub mod trace;
 pub mod types;
 
 pub use driver::Error;
@@ -1,8 +1,8 @@
 use lazy_static::lazy_static;
 use libloading::{Library, Symbol};
-use log::info;
+use log::{info, warn};
 use num_traits::FromPrimitive;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_double, c_int};
 
 use super::driver::*;
@@ -10,7 +10,7 @@ use super::types::*;
 // WGFMU Library rust bindings, see https://l4.granasat.space/docs/B1500A/wgfmu/programming_guide for more details.
 
 lazy_static! {
-    static ref DLL: Library = unsafe { Library::new("./wgfmu.dll").unwrap() };
+    static ref DLL: Result<Library, libloading::Error> = unsafe { Library::new("./wgfmu.dll") };
 }
 
 #[allow(dead_code)]
@@ -105,6 +105,25 @@ pub struct ProductionWgfmu<'a> {
     dcmeasure_averaged_value:               Symbol<'a, DcmeasureAveragedValue>,
 }
 
+/// Points read from the instrument memory in a single GetMeasureValues call
+const READ_BATCH_SIZE: usize = 4096;
+
+/// Reads a text of the library, `size` gives the length of the buffer `text` fills
+fn read_text(size: GetErrorSize, text: GetError) -> Result<String, Error> {
+    let mut length: c_int = 0;
+    get_result(size(&mut length as *mut c_int))?;
+    if length <= 0 {
+        return Ok(String::new());
+    }
+
+    let mut buffer: Vec<c_char> = vec![0; length as usize + 1];
+    let mut length = buffer.len() as c_int;
+    get_result(text(buffer.as_mut_ptr(), &mut length as *mut c_int))?;
+
+    let text = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+    Ok(text.to_string_lossy().trim_end().to_string())
+}
+
 fn get_result(ret: i32) -> Res {
     match ret {
         0 => Ok(()),
@@ -154,6 +173,37 @@ impl<'a> WgfmuDriver for ProductionWgfmu<'a> {
         get_result(ret)
     }
 
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        let mut size: c_int = 0;
+        get_result((self.get_channel_id_size)(&mut size as *mut c_int))?;
+
+        let mut ids: Vec<c_int> = vec![0; size.max(0) as usize];
+        get_result((self.get_channel_ids)(ids.as_mut_ptr(), &mut size as *mut c_int))?;
+        ids.truncate(size.max(0) as usize);
+
+        Ok(ids.into_iter().map(|id| id as usize).collect())
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        read_text(*self.get_error_size, *self.get_error)
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        read_text(*self.get_error_summary_size, *self.get_error_summary)
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        read_text(*self.get_warning_summary_size, *self.get_warning_summary)
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        get_result((self.set_warning_level)(level as i32))
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        get_result((self.treat_warnings_as_errors)(level as i32))
+    }
+
     fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
         let ret;
         unsafe {
@@ -232,6 +282,12 @@ impl<'a> WgfmuDriver for ProductionWgfmu<'a> {
         get_result(ret)
     }
 
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        let pattern = CString::new(pattern).unwrap();
+        let event = CString::new(event).unwrap();
+        get_result((self.set_range_event)(pattern.as_ptr(), event.as_ptr(), time, range as i32))
+    }
+
     fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
         let ret;
         unsafe {
@@ -295,6 +351,26 @@ impl<'a> WgfmuDriver for ProductionWgfmu<'a> {
         get_result(ret)
     }
 
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        get_result((self.set_force_voltage_range)(chan_id as i32, range as i32))
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        get_result((self.set_measure_voltage_range)(chan_id as i32, range as i32))
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        get_result((self.set_measure_current_range)(chan_id as i32, range as i32))
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        get_result((self.set_force_delay)(chan_id as i32, delay))
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        get_result((self.set_measure_delay)(chan_id as i32, delay))
+    }
+
     fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
         #[allow(unused_mut)]
         unsafe {
@@ -345,6 +421,44 @@ impl<'a> WgfmuDriver for ProductionWgfmu<'a> {
         get_result(ret)
     }
 
+    fn abort(&mut self) -> Res {
+        let ret;
+        unsafe {
+            ret = (self.abort)();
+        }
+        get_result(ret)
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        let ret;
+        unsafe {
+            ret = (self.abort_channel)(chan_id as c_int);
+        }
+        get_result(ret)
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        let mut status: c_int = 0;
+        let mut elapsed_time: c_double = 0.0;
+        let mut total_time: c_double = 0.0;
+        let ret;
+        unsafe {
+            ret = (self.get_channel_status)(
+                chan_id as c_int,
+                &mut status as *mut c_int,
+                &mut elapsed_time as *mut c_double,
+                &mut total_time as *mut c_double,
+            );
+        }
+        get_result(ret)?;
+
+        Ok(ChannelStatus {
+            status: FromPrimitive::from_i32(status).ok_or(Error::UnidentifiedError)?,
+            elapsed_time,
+            total_time,
+        })
+    }
+
     fn wait_until_completed(&mut self) -> Res {
         let ret;
         unsafe {
@@ -353,51 +467,35 @@ impl<'a> WgfmuDriver for ProductionWgfmu<'a> {
         get_result(ret)
     }
 
-    fn get_measure_values(&mut self, chan_id: usize) -> Result<Vec<Measurement>, Error> {
-        let mut meas_vec = Vec::<Measurement>::new();
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        let measurement_size = self.get_measure_value_size(chan_id as i32)?;
+        self.read_measure_values(chan_id, current, 0, measurement_size as usize)
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        let mut complete: c_int = 0;
+        let mut total: c_int = 0;
+        let ret;
         unsafe {
-            let measurement_size = self.get_measure_value_size(chan_id as i32)?;
-            let measurement_size1 = self.get_measure_value_size(101 as i32)?;
-            info!(
-                "Size 102: {}\nSize 101: {}",
-                measurement_size, measurement_size1
+            ret = (self.get_completed_measure_event_size)(
+                chan_id as c_int,
+                &mut complete as *mut c_int,
+                &mut total as *mut c_int,
             );
-            let op_mode = self.get_operation_mode(chan_id as i32)?;
-            match op_mode {
-                OperationMode::OperationModeFastIV => {
-                    // let mut voltage_vec = Vec::<f64>::new();
-                    // let mut current_vec = Vec::<f64>::new();
-                    // let mut time_vec = Vec::<f64>::new();
-
-                    for i in 0..measurement_size {
-                        let mut voltage = 0.0;
-                        let voltage = &mut voltage as *mut c_double;
-
-                        let mut current = 0.0;
-                        let current = &mut current as *mut c_double;
-
-                        let mut time = 0.0;
-                        let time = &mut time as *mut c_double;
-
-                        let ret = (self.get_measure_value)(101 as i32, i as c_int, time, current);
-                        get_result(ret)?;
-
-                        let ret =
-                            (self.get_measure_value)(chan_id as i32, i as c_int, time, voltage);
-
-                        get_result(ret)?;
-
-                        meas_vec.push(Measurement {
-                            voltage: *voltage,
-                            current: Some(*current),
-                            time: *time,
-                        });
-                    }
-                    Ok(meas_vec)
-                }
-                _ => Result::Err(Error::NotImplemented),
-            }
         }
+        get_result(ret)?;
+
+        Ok((complete as usize, total as usize))
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        let completed = self.get_measure_value_size(chan_id as i32)?;
+        self.read_channel_data(chan_id, offset, completed as usize)
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        let size = self.get_measure_value_size(chan_id as i32)?;
+        self.read_channel_data(chan_id, 0, size as usize)
     }
 
     fn do_self_calibration(&mut self) -> Res {
@@ -421,6 +519,27 @@ impl<'a> WgfmuDriver for ProductionWgfmu<'a> {
         }
         get_result(ret)
     }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        get_result((self.dcforce_voltage)(chan_id as i32, voltage))
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        let mut value: c_double = 0.0;
+        get_result((self.dcmeasure_value)(chan_id as i32, &mut value as *mut c_double))?;
+        Ok(value)
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        let mut value: c_double = 0.0;
+        get_result((self.dcmeasure_averaged_value)(
+            chan_id as i32,
+            points as c_int,
+            interval as c_int,
+            &mut value as *mut c_double,
+        ))?;
+        Ok(value)
+    }
 }
 
 impl<'a> ProductionWgfmu<'a> {
@@ -428,99 +547,177 @@ impl<'a> ProductionWgfmu<'a> {
     #[allow(unused)]
     pub fn new() -> Result<ProductionWgfmu<'a>, Box<dyn std::error::Error>> {
 
+        let dll = match DLL.as_ref() {
+            Ok(dll) => dll,
+            Err(err) => return Err(format!("Could not load the WGFMU library: {}", err).into()),
+        };
+
         unsafe {
             Ok(ProductionWgfmu {
-                open_session:                           DLL.get::<OpenSession>              (b"WGFMU_openSession").unwrap(),
-                close_session:                          DLL.get::<CloseSession>             (b"WGFMU_closeSession").unwrap(),
-                initialize:                             DLL.get::<Initialize>               (b"WGFMU_initialize").unwrap(),
-                set_timeout:                            DLL.get::<SetTimeout>               (b"WGFMU_setTimeout").unwrap(),
-                do_self_calibration:                    DLL.get::<DoSelfCalibration>        (b"WGFMU_doSelfCalibration").unwrap(),
-                do_self_test:                           DLL.get::<DoSelfTest>               (b"WGFMU_doSelfTest").unwrap(),
-                get_channel_id_size:                    DLL.get::<GetChannelIdSize>         (b"WGFMU_getChannelIdSize").unwrap(),
-                get_channel_ids:                        DLL.get::<GetChannelIds>            (b"WGFMU_getChannelIds").unwrap(),
-                get_error_size:                         DLL.get::<GetErrorSize>             (b"WGFMU_getErrorSize").unwrap(),
-                get_error:                              DLL.get::<GetError>                 (b"WGFMU_getError").unwrap(),
-                get_error_summary_size:                 DLL.get::<GetErrorSummarySize>      (b"WGFMU_getErrorSummarySize").unwrap(),
-                get_error_summary:                      DLL.get::<GetErrorSummary>          (b"WGFMU_getErrorSummary").unwrap(),
-                treat_warnings_as_errors:               DLL.get::<TreatWarningsAsErrors>    (b"WGFMU_treatWarningsAsErrors").unwrap(),
-                set_warning_level:                      DLL.get::<SetWarningLevel>          (b"WGFMU_setWarningLevel").unwrap(),
-                get_warning_level:                      DLL.get::<GetWarningLevel>          (b"WGFMU_getWarningLevel").unwrap(),
-                get_warning_summary_size:               DLL.get::<GetWarningSummarySize>    (b"WGFMU_getWarningSummarySize").unwrap(),
-                get_warning_summary:                    DLL.get::<GetWarningSummary>        (b"WGFMU_getWarningSummary").unwrap(),
-                open_log_file:                          DLL.get::<OpenLogFile>              (b"WGFMU_openLogFile").unwrap(),
-                close_log_file:                         DLL.get::<CloseLogFile>             (b"WGFMU_closeLogFile").unwrap(),
-                set_operation_mode:                     DLL.get::<SetOperationMode>         (b"WGFMU_setOperationMode").unwrap(),
-                get_operation_mode:                     DLL.get::<GetOperationMode>         (b"WGFMU_getOperationMode").unwrap(),
-                set_force_voltage_range:                DLL.get::<SetForceVoltageRange>     (b"WGFMU_setForceVoltageRange").unwrap(),
-                get_force_voltage_range:                DLL.get::<GetForceVoltageRange>     (b"WGFMU_getForceVoltageRange").unwrap(),
-                set_measure_mode:                       DLL.get::<SetMeasureMode>           (b"WGFMU_setMeasureMode").unwrap(),
-                get_measure_mode:                       DLL.get::<GetMeasureMode>           (b"WGFMU_getMeasureMode").unwrap(),
-                set_measure_current_range:              DLL.get::<SetMeasureCurrentRange>   (b"WGFMU_setMeasureCurrentRange").unwrap(),
-                get_measure_current_range:              DLL.get::<GetMeasureCurrentRange>   (b"WGFMU_getMeasureCurrentRange").unwrap(),
-                set_measure_voltage_range:              DLL.get::<SetMeasureVoltageRange>   (b"WGFMU_setMeasureVoltageRange").unwrap(),
-                get_measure_voltage_range:              DLL.get::<GetMeasureVoltageRange>   (b"WGFMU_getMeasureVoltageRange").unwrap(),
-                set_force_delay:                        DLL.get::<SetForceDelay>            (b"WGFMU_setForceDelay").unwrap(),
-                get_force_delay:                        DLL.get::<GetForceDelay>            (b"WGFMU_getForceDelay").unwrap(),
-                set_measure_delay:                      DLL.get::<SetMeasureDelay>          (b"WGFMU_setMeasureDelay").unwrap(),
-                get_measure_delay:                      DLL.get::<GetMeasureDelay>          (b"WGFMU_getMeasureDelay").unwrap(),
-                set_measure_enabled:                    DLL.get::<SetMeasureEnabled>        (b"WGFMU_setMeasureEnabled").unwrap(),
-                is_measure_enabled:                     DLL.get::<IsMeasureEnabled>         (b"WGFMU_isMeasureEnabled").unwrap(),
-                set_trigger_out_mode:                   DLL.get::<SetTriggerOutMode>        (b"WGFMU_setTriggerOutMode").unwrap(),
-                get_trigger_out_mode:                   DLL.get::<GetTriggerOutMode>        (b"WGFMU_getTriggerOutMode").unwrap(),
-                connect:                                DLL.get::<Connect>                  (b"WGFMU_connect").unwrap(),
-                disconnect:                             DLL.get::<Disconnect>               (b"WGFMU_disconnect").unwrap(),
-                clear:                                  DLL.get::<Clear>                    (b"WGFMU_clear").unwrap(),
-                create_pattern:                         DLL.get::<CreatePattern>            (b"WGFMU_createPattern").unwrap(),
-                add_vector:                             DLL.get::<AddVector>                (b"WGFMU_addVector").unwrap(),
-                add_vectors:                            DLL.get::<AddVectors>               (b"WGFMU_addVectors").unwrap(),
-                set_vector:                             DLL.get::<SetVector>                (b"WGFMU_setVector").unwrap(),
-                set_vectors:                            DLL.get::<SetVectors>               (b"WGFMU_setVectors").unwrap(),
-                create_merged_pattern:                  DLL.get::<CreateMergedPattern>      (b"WGFMU_createMergedPattern").unwrap(),
-                create_multiplied_pattern:              DLL.get::<CreateMultipliedPattern>  (b"WGFMU_createMultipliedPattern").unwrap(),
-                create_offset_pattern:                  DLL.get::<CreateOffsetPattern>      (b"WGFMU_createOffsetPattern").unwrap(),
-                set_measure_event:                      DLL.get::<SetMeasureEvent>          (b"WGFMU_setMeasureEvent").unwrap(),
-                set_range_event:                        DLL.get::<SetRangeEvent>            (b"WGFMU_setRangeEvent").unwrap(),
-                set_trigger_out_event:                  DLL.get::<SetTriggerOutEvent>       (b"WGFMU_setTriggerOutEvent").unwrap(),
-                add_sequence:                           DLL.get::<AddSequence>              (b"WGFMU_addSequence").unwrap(),
-                add_sequences:                          DLL.get::<AddSequences>             (b"WGFMU_addSequences").unwrap(),
-                get_pattern_force_value_size:           DLL.get::<GetPatternForceValueSize> (b"WGFMU_getPatternForceValueSize").unwrap(),
-                get_pattern_force_values:               DLL.get::<GetPatternForceValues>    (b"WGFMU_getPatternForceValues").unwrap(),
-                get_pattern_force_value:                DLL.get::<GetPatternForceValue>     (b"WGFMU_getPatternForceValue").unwrap(),
-                get_pattern_interpolated_force_value:   DLL.get::<GetPatternInterpolatedForceValue>(b"WGFMU_getPatternInterpolatedForceValue").unwrap(),
-                get_pattern_measure_time_size:          DLL.get::<GetPatternMeasureTimeSize>(b"WGFMU_getPatternMeasureTimeSize").unwrap(),
-                get_pattern_measure_times:              DLL.get::<GetPatternMeasureTimes>   (b"WGFMU_getPatternMeasureTimes").unwrap(),
-                get_pattern_measure_time:               DLL.get::<GetPatternMeasureTime>    (b"WGFMU_getPatternMeasureTime").unwrap(),
-                get_force_value_size:                   DLL.get::<GetForceValueSize>        (b"WGFMU_getForceValueSize").unwrap(),
-                get_force_values:                       DLL.get::<GetForceValues>           (b"WGFMU_getForceValues").unwrap(),
-                get_force_value:                        DLL.get::<GetForceValue>            (b"WGFMU_getForceValue").unwrap(),
-                get_interpolated_force_value:           DLL.get::<GetInterpolatedForceValue>(b"WGFMU_getInterpolatedForceValue").unwrap(),
-                get_measure_time_size:                  DLL.get::<GetMeasureTimeSize>       (b"WGFMU_getMeasureTimeSize").unwrap(),
-                get_measure_times:                      DLL.get::<GetMeasureTimes>          (b"WGFMU_getMeasureTimes").unwrap(),
-                get_measure_time:                       DLL.get::<GetMeasureTime>           (b"WGFMU_getMeasureTime").unwrap(),
-                get_measure_event_size:                 DLL.get::<GetMeasureEventSize>      (b"WGFMU_getMeasureEventSize").unwrap(),
-                get_measure_events:                     DLL.get::<GetMeasureEvents>         (b"WGFMU_getMeasureEvents").unwrap(),
-                get_measure_event:                      DLL.get::<GetMeasureEvent>          (b"WGFMU_getMeasureEvent").unwrap(),
-                get_measure_event_attribute:            DLL.get::<GetMeasureEventAttribute> (b"WGFMU_getMeasureEventAttribute").unwrap(),
-                update:                                 DLL.get::<Update>                   (b"WGFMU_update").unwrap(),
-                update_channel:                         DLL.get::<UpdateChannel>            (b"WGFMU_updateChannel").unwrap(),
-                execute:                                DLL.get::<Execute>                  (b"WGFMU_execute").unwrap(),
-                abort:                                  DLL.get::<Abort>                    (b"WGFMU_abort").unwrap(),
-                abort_channel:                          DLL.get::<AbortChannel>             (b"WGFMU_abortChannel").unwrap(),
-                get_channel_status:                     DLL.get::<GetChannelStatus>         (b"WGFMU_getChannelStatus").unwrap(),
-                wait_until_completed:                   DLL.get::<WaitUntilCompleted>       (b"WGFMU_waitUntilCompleted").unwrap(),
-                get_measure_value_size:                 DLL.get::<GetMeasureValueSize>      (b"WGFMU_getMeasureValueSize").unwrap(),
-                get_measure_values:                     DLL.get::<GetMeasureValues>         (b"WGFMU_getMeasureValues").unwrap(),
-                get_measure_value:                      DLL.get::<GetMeasureValue>          (b"WGFMU_getMeasureValue").unwrap(),
-                get_completed_measure_event_size:       DLL.get::<GetCompletedMeasureEventSize>(b"WGFMU_getCompletedMeasureEventSize").unwrap(),
-                is_measure_event_completed:             DLL.get::<IsMeasureEventCompleted>  (b"WGFMU_isMeasureEventCompleted").unwrap(),
-                export_ascii:                           DLL.get::<ExportAscii>              (b"WGFMU_exportAscii").unwrap(),
-                dcforce_voltage:                        DLL.get::<DcforceVoltage>           (b"WGFMU_dcforceVoltage").unwrap(),
-                dcmeasure_value:                        DLL.get::<DcmeasureValue>           (b"WGFMU_dcmeasureValue").unwrap(),
-                dcmeasure_averaged_value:               DLL.get::<DcmeasureAveragedValue>   (b"WGFMU_dcmeasureAveragedValue").unwrap(),
+                open_session:                           dll.get::<OpenSession>              (b"WGFMU_openSession")?,
+                close_session:                          dll.get::<CloseSession>             (b"WGFMU_closeSession")?,
+                initialize:                             dll.get::<Initialize>               (b"WGFMU_initialize")?,
+                set_timeout:                            dll.get::<SetTimeout>               (b"WGFMU_setTimeout")?,
+                do_self_calibration:                    dll.get::<DoSelfCalibration>        (b"WGFMU_doSelfCalibration")?,
+                do_self_test:                           dll.get::<DoSelfTest>               (b"WGFMU_doSelfTest")?,
+                get_channel_id_size:                    dll.get::<GetChannelIdSize>         (b"WGFMU_getChannelIdSize")?,
+                get_channel_ids:                        dll.get::<GetChannelIds>            (b"WGFMU_getChannelIds")?,
+                get_error_size:                         dll.get::<GetErrorSize>             (b"WGFMU_getErrorSize")?,
+                get_error:                              dll.get::<GetError>                 (b"WGFMU_getError")?,
+                get_error_summary_size:                 dll.get::<GetErrorSummarySize>      (b"WGFMU_getErrorSummarySize")?,
+                get_error_summary:                      dll.get::<GetErrorSummary>          (b"WGFMU_getErrorSummary")?,
+                treat_warnings_as_errors:               dll.get::<TreatWarningsAsErrors>    (b"WGFMU_treatWarningsAsErrors")?,
+                set_warning_level:                      dll.get::<SetWarningLevel>          (b"WGFMU_setWarningLevel")?,
+                get_warning_level:                      dll.get::<GetWarningLevel>          (b"WGFMU_getWarningLevel")?,
+                get_warning_summary_size:               dll.get::<GetWarningSummarySize>    (b"WGFMU_getWarningSummarySize")?,
+                get_warning_summary:                    dll.get::<GetWarningSummary>        (b"WGFMU_getWarningSummary")?,
+                open_log_file:                          dll.get::<OpenLogFile>              (b"WGFMU_openLogFile")?,
+                close_log_file:                         dll.get::<CloseLogFile>             (b"WGFMU_closeLogFile")?,
+                set_operation_mode:                     dll.get::<SetOperationMode>         (b"WGFMU_setOperationMode")?,
+                get_operation_mode:                     dll.get::<GetOperationMode>         (b"WGFMU_getOperationMode")?,
+                set_force_voltage_range:                dll.get::<SetForceVoltageRange>     (b"WGFMU_setForceVoltageRange")?,
+                get_force_voltage_range:                dll.get::<GetForceVoltageRange>     (b"WGFMU_getForceVoltageRange")?,
+                set_measure_mode:                       dll.get::<SetMeasureMode>           (b"WGFMU_setMeasureMode")?,
+                get_measure_mode:                       dll.get::<GetMeasureMode>           (b"WGFMU_getMeasureMode")?,
+                set_measure_current_range:              dll.get::<SetMeasureCurrentRange>   (b"WGFMU_setMeasureCurrentRange")?,
+                get_measure_current_range:              dll.get::<GetMeasureCurrentRange>   (b"WGFMU_getMeasureCurrentRange")?,
+                set_measure_voltage_range:              dll.get::<SetMeasureVoltageRange>   (b"WGFMU_setMeasureVoltageRange")?,
+                get_measure_voltage_range:              dll.get::<GetMeasureVoltageRange>   (b"WGFMU_getMeasureVoltageRange")?,
+                set_force_delay:                        dll.get::<SetForceDelay>            (b"WGFMU_setForceDelay")?,
+                get_force_delay:                        dll.get::<GetForceDelay>            (b"WGFMU_getForceDelay")?,
+                set_measure_delay:                      dll.get::<SetMeasureDelay>          (b"WGFMU_setMeasureDelay")?,
+                get_measure_delay:                      dll.get::<GetMeasureDelay>          (b"WGFMU_getMeasureDelay")?,
+                set_measure_enabled:                    dll.get::<SetMeasureEnabled>        (b"WGFMU_setMeasureEnabled")?,
+                is_measure_enabled:                     dll.get::<IsMeasureEnabled>         (b"WGFMU_isMeasureEnabled")?,
+                set_trigger_out_mode:                   dll.get::<SetTriggerOutMode>        (b"WGFMU_setTriggerOutMode")?,
+                get_trigger_out_mode:                   dll.get::<GetTriggerOutMode>        (b"WGFMU_getTriggerOutMode")?,
+                connect:                                dll.get::<Connect>                  (b"WGFMU_connect")?,
+                disconnect:                             dll.get::<Disconnect>               (b"WGFMU_disconnect")?,
+                clear:                                  dll.get::<Clear>                    (b"WGFMU_clear")?,
+                create_pattern:                         dll.get::<CreatePattern>            (b"WGFMU_createPattern")?,
+                add_vector:                             dll.get::<AddVector>                (b"WGFMU_addVector")?,
+                add_vectors:                            dll.get::<AddVectors>               (b"WGFMU_addVectors")?,
+                set_vector:                             dll.get::<SetVector>                (b"WGFMU_setVector")?,
+                set_vectors:                            dll.get::<SetVectors>               (b"WGFMU_setVectors")?,
+                create_merged_pattern:                  dll.get::<CreateMergedPattern>      (b"WGFMU_createMergedPattern")?,
+                create_multiplied_pattern:              dll.get::<CreateMultipliedPattern>  (b"WGFMU_createMultipliedPattern")?,
+                create_offset_pattern:                  dll.get::<CreateOffsetPattern>      (b"WGFMU_createOffsetPattern")?,
+                set_measure_event:                      dll.get::<SetMeasureEvent>          (b"WGFMU_setMeasureEvent")?,
+                set_range_event:                        dll.get::<SetRangeEvent>            (b"WGFMU_setRangeEvent")?,
+                set_trigger_out_event:                  dll.get::<SetTriggerOutEvent>       (b"WGFMU_setTriggerOutEvent")?,
+                add_sequence:                           dll.get::<AddSequence>              (b"WGFMU_addSequence")?,
+                add_sequences:                          dll.get::<AddSequences>             (b"WGFMU_addSequences")?,
+                get_pattern_force_value_size:           dll.get::<GetPatternForceValueSize> (b"WGFMU_getPatternForceValueSize")?,
+                get_pattern_force_values:               dll.get::<GetPatternForceValues>    (b"WGFMU_getPatternForceValues")?,
+                get_pattern_force_value:                dll.get::<GetPatternForceValue>     (b"WGFMU_getPatternForceValue")?,
+                get_pattern_interpolated_force_value:   dll.get::<GetPatternInterpolatedForceValue>(b"WGFMU_getPatternInterpolatedForceValue")?,
+                get_pattern_measure_time_size:          dll.get::<GetPatternMeasureTimeSize>(b"WGFMU_getPatternMeasureTimeSize")?,
+                get_pattern_measure_times:              dll.get::<GetPatternMeasureTimes>   (b"WGFMU_getPatternMeasureTimes")?,
+                get_pattern_measure_time:               dll.get::<GetPatternMeasureTime>    (b"WGFMU_getPatternMeasureTime")?,
+                get_force_value_size:                   dll.get::<GetForceValueSize>        (b"WGFMU_getForceValueSize")?,
+                get_force_values:                       dll.get::<GetForceValues>           (b"WGFMU_getForceValues")?,
+                get_force_value:                        dll.get::<GetForceValue>            (b"WGFMU_getForceValue")?,
+                get_interpolated_force_value:           dll.get::<GetInterpolatedForceValue>(b"WGFMU_getInterpolatedForceValue")?,
+                get_measure_time_size:                  dll.get::<GetMeasureTimeSize>       (b"WGFMU_getMeasureTimeSize")?,
+                get_measure_times:                      dll.get::<GetMeasureTimes>          (b"WGFMU_getMeasureTimes")?,
+                get_measure_time:                       dll.get::<GetMeasureTime>           (b"WGFMU_getMeasureTime")?,
+                get_measure_event_size:                 dll.get::<GetMeasureEventSize>      (b"WGFMU_getMeasureEventSize")?,
+                get_measure_events:                     dll.get::<GetMeasureEvents>         (b"WGFMU_getMeasureEvents")?,
+                get_measure_event:                      dll.get::<GetMeasureEvent>          (b"WGFMU_getMeasureEvent")?,
+                get_measure_event_attribute:            dll.get::<GetMeasureEventAttribute> (b"WGFMU_getMeasureEventAttribute")?,
+                update:                                 dll.get::<Update>                   (b"WGFMU_update")?,
+                update_channel:                         dll.get::<UpdateChannel>            (b"WGFMU_updateChannel")?,
+                execute:                                dll.get::<Execute>                  (b"WGFMU_execute")?,
+                abort:                                  dll.get::<Abort>                    (b"WGFMU_abort")?,
+                abort_channel:                          dll.get::<AbortChannel>             (b"WGFMU_abortChannel")?,
+                get_channel_status:                     dll.get::<GetChannelStatus>         (b"WGFMU_getChannelStatus")?,
+                wait_until_completed:                   dll.get::<WaitUntilCompleted>       (b"WGFMU_waitUntilCompleted")?,
+                get_measure_value_size:                 dll.get::<GetMeasureValueSize>      (b"WGFMU_getMeasureValueSize")?,
+                get_measure_values:                     dll.get::<GetMeasureValues>         (b"WGFMU_getMeasureValues")?,
+                get_measure_value:                      dll.get::<GetMeasureValue>          (b"WGFMU_getMeasureValue")?,
+                get_completed_measure_event_size:       dll.get::<GetCompletedMeasureEventSize>(b"WGFMU_getCompletedMeasureEventSize")?,
+                is_measure_event_completed:             dll.get::<IsMeasureEventCompleted>  (b"WGFMU_isMeasureEventCompleted")?,
+                export_ascii:                           dll.get::<ExportAscii>              (b"WGFMU_exportAscii")?,
+                dcforce_voltage:                        dll.get::<DcforceVoltage>           (b"WGFMU_dcforceVoltage")?,
+                dcmeasure_value:                        dll.get::<DcmeasureValue>           (b"WGFMU_dcmeasureValue")?,
+                dcmeasure_averaged_value:               dll.get::<DcmeasureAveragedValue>   (b"WGFMU_dcmeasureAveragedValue")?,
             })
         }
     }
 
+    /// Reads the points `start..end` of `chan_id` as the voltage, matched by time with the current of `current`
+    fn read_measure_values(
+        &mut self,
+        chan_id: usize,
+        current: usize,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<Measurement>, Error> {
+        match self.get_operation_mode(chan_id as i32)? {
+            OperationMode::OperationModeFastIV => {
+                let current_end = usize::min(end, self.get_measure_value_size(current as i32)? as usize);
+                let records = RecordSet {
+                    channels: vec![
+                        self.read_channel_data(chan_id, start, end)?,
+                        self.read_channel_data(current, start, current_end)?,
+                    ],
+                };
+                if records.is_mismatched() {
+                    warn!(
+                        "Channels {} and {} measured {} and {} points, aligning them by time",
+                        chan_id,
+                        current,
+                        records.channels[0].samples.len(),
+                        records.channels[1].samples.len()
+                    );
+                }
+
+                Ok(records.measurements(chan_id, current))
+            }
+            _ => Result::Err(Error::NotImplemented),
+        }
+    }
+
+    /// Reads the points `start..end` of a channel, `READ_BATCH_SIZE` points at a time
+    fn read_channel_data(&mut self, chan_id: usize, start: usize, end: usize) -> Result<ChannelData, Error> {
+        let measure_mode = self.get_measure_mode(chan_id as i32)?;
+        let mut samples = Vec::with_capacity(end.saturating_sub(start));
+        let mut time = vec![0.0; READ_BATCH_SIZE];
+        let mut value = vec![0.0; READ_BATCH_SIZE];
+
+        let mut index = start;
+        while index < end {
+            let mut length = usize::min(READ_BATCH_SIZE, end - index) as c_int;
+            let ret = (self.get_measure_values)(
+                chan_id as c_int,
+                index as c_int,
+                &mut length as *mut c_int,
+                time.as_mut_ptr(),
+                value.as_mut_ptr(),
+            );
+            get_result(ret)?;
+
+            // The library returns how many points it actually read
+            if length <= 0 {
+                break;
+            }
+            let length = length as usize;
+            samples.extend(
+                time.iter()
+                    .zip(value.iter())
+                    .take(length)
+                    .map(|(&time, &value)| Sample { time, value }),
+            );
+            index += length;
+        }
+
+        Ok(ChannelData {
+            chan_id,
+            measure_mode,
+            samples,
+        })
+    }
+
     fn get_measure_value_size(&mut self, chan_id: i32) -> Result<u32, Error> {
         unsafe {
             let mut complete = 0;
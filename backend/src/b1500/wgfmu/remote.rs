@@ -0,0 +1,536 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+use super::driver::*;
+use super::trace::{Request, Response};
+
+// Remote WGFMU. `serve` exposes a driver over TCP and `RemoteWgfmu` forwards every call to it, so the instrument
+// attached to the lab PC can be driven from another machine. Each call is a JSON line with a `Request`, answered
+// with a JSON line holding the `Result` of the call. The connection is neither encrypted nor authenticated beyond a
+// shared token. The client opens with a handshake line holding its token, empty when it has none, and the server
+// answers it like a call before any other one is made. Without a token only loopback addresses are served.
+
+/// A client that makes no call for this long gives the driver back, it is acquired again on its next call
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct RemoteWgfmu {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RemoteWgfmu {
+    pub fn new(address: &str, token: Option<&str>) -> Result<RemoteWgfmu, Box<dyn std::error::Error>> {
+        let writer = TcpStream::connect(address)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        let mut remote = RemoteWgfmu { reader, writer };
+
+        writeln!(remote.writer, "{}", token.unwrap_or_default())?;
+        match remote.receive()? {
+            Response::Done => (),
+            _ => return Err(Error::RemoteConnectionError.into()),
+        }
+
+        info!("Connected to the remote WGFMU at {}", address);
+        Ok(remote)
+    }
+
+    fn request(&mut self, request: Request) -> Result<Response, Error> {
+        let mut line = serde_json::to_string(&request).map_err(|err| {
+            error!("Could not serialize WGFMU request {:?}: {}", request, err);
+            Error::RemoteConnectionError
+        })?;
+        line.push('\n');
+
+        self.writer.write_all(line.as_bytes()).map_err(|err| {
+            error!("Could not send WGFMU request: {}", err);
+            Error::RemoteConnectionError
+        })?;
+
+        self.receive()
+    }
+
+    /// Reads the result of the last call
+    fn receive(&mut self) -> Result<Response, Error> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => {
+                error!("Remote WGFMU closed the connection");
+                return Result::Err(Error::RemoteConnectionError);
+            }
+            Ok(_) => (),
+            Err(err) => {
+                error!("Could not receive WGFMU response: {}", err);
+                return Result::Err(Error::RemoteConnectionError);
+            }
+        }
+
+        match serde_json::from_str::<Result<Response, Error>>(line.as_str()) {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Malformed WGFMU response: {}", err);
+                Result::Err(Error::RemoteConnectionError)
+            }
+        }
+    }
+
+    fn request_done(&mut self, request: Request) -> Res {
+        match self.request(request)? {
+            Response::Done => Ok(()),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn request_text(&mut self, request: Request) -> Result<String, Error> {
+        match self.request(request)? {
+            Response::Text(text) => Ok(text),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn request_value(&mut self, request: Request) -> Result<f64, Error> {
+        match self.request(request)? {
+            Response::Value(value) => Ok(value),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+}
+
+/// Serves a WGFMU to remote clients, one thread per connection. `acquire` is called with the client address once it
+/// presented `token`, if any, and gives the driver the client uses until it disconnects or stays idle for
+/// `IDLE_TIMEOUT`. Addresses other than loopback are refused without a token.
+pub fn serve<D, F>(acquire: F, address: impl ToSocketAddrs, token: Option<String>) -> std::io::Result<()>
+where
+    D: WgfmuDriver,
+    F: Fn(&str) -> Result<D, Error> + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind(address)?;
+    let local = listener.local_addr()?;
+    if token.is_none() && !local.ip().is_loopback() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("Serving the WGFMU at {} needs a token, only loopback is served without one", local),
+        ));
+    }
+    info!("Serving the WGFMU at {}", local);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Could not accept WGFMU client: {}", err);
+                continue;
+            }
+        };
+
+        let acquire = acquire.clone();
+        let token = token.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+            info!("WGFMU client {} connected", peer);
+
+            let result = authenticate(&stream, token.as_deref())
+                .and_then(|reader| serve_client(&acquire, peer.as_str(), reader, stream));
+            if let Err(err) = result {
+                warn!("WGFMU client {} failed: {}", peer, err);
+            }
+
+            info!("WGFMU client {} disconnected", peer);
+        });
+    }
+
+    Ok(())
+}
+
+/// Reads the handshake line of the client and answers it, the client is accepted when the line is `token` or the
+/// server has none. Returns the reader for the calls that follow.
+fn authenticate(
+    stream: &TcpStream,
+    token: Option<&str>,
+) -> Result<BufReader<TcpStream>, Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream.try_clone()?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let accepted = token.is_none_or(|token| line.trim_end_matches(['\r', '\n']) == token);
+
+    let reply: Result<Response, Error> = if accepted {
+        Ok(Response::Done)
+    } else {
+        Result::Err(Error::RemoteAuthenticationError)
+    };
+    writeln!(writer, "{}", serde_json::to_string(&reply)?)?;
+
+    if !accepted {
+        return Err("wrong token".into());
+    }
+    Ok(reader)
+}
+
+/// Answers the calls of a client. The driver is acquired on the first call and given back when the client stays idle
+/// for `IDLE_TIMEOUT`, so a forgotten client does not keep the instrument from the measurement queue.
+fn serve_client<D, F>(
+    acquire: &F,
+    peer: &str,
+    mut reader: BufReader<TcpStream>,
+    mut writer: TcpStream,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    D: WgfmuDriver,
+    F: Fn(&str) -> Result<D, Error>,
+{
+    let mut wgfmu: Option<D> = None;
+    let mut line = String::new();
+
+    loop {
+        // Only a client holding the driver is timed out
+        reader.get_ref().set_read_timeout(wgfmu.as_ref().map(|_| IDLE_TIMEOUT))?;
+
+        // A line cut by the timeout is kept, the rest of it comes with the next read
+        match reader.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => (),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                info!("WGFMU client {} is idle, releasing the driver", peer);
+                wgfmu = None;
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let request = line.trim().to_string();
+        line.clear();
+        if request.is_empty() {
+            continue;
+        }
+
+        let result = match serde_json::from_str::<Request>(request.as_str()) {
+            Ok(request) => {
+                let driver = match wgfmu.take() {
+                    Some(driver) => Ok(driver),
+                    None => acquire(peer),
+                };
+                match driver {
+                    Ok(mut driver) => {
+                        let result = request.call(&mut driver);
+                        wgfmu = Some(driver);
+                        result
+                    }
+                    Err(err) => Result::Err(err),
+                }
+            }
+            Err(err) => {
+                warn!("Malformed WGFMU request: {}", err);
+                Result::Err(Error::BadArguments)
+            }
+        };
+
+        writeln!(writer, "{}", serde_json::to_string(&result)?)?;
+    }
+}
+
+impl WgfmuDriver for RemoteWgfmu {
+    fn open_session(&mut self, instrument: &str) -> Res {
+        self.request_done(Request::OpenSession {
+            instrument: instrument.to_string(),
+        })
+    }
+
+    fn close_session(&mut self) -> Res {
+        self.request_done(Request::CloseSession)
+    }
+
+    fn clear(&mut self) -> Res {
+        self.request_done(Request::Clear)
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        match self.request(Request::GetChannelIds)? {
+            Response::ChannelIds(ids) => Ok(ids),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        self.request_text(Request::GetError)
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        self.request_text(Request::GetErrorSummary)
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        self.request_text(Request::GetWarningSummary)
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        self.request_done(Request::SetWarningLevel { level })
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        self.request_done(Request::TreatWarningsAsErrors { level })
+    }
+
+    fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
+        self.request_done(Request::CreatePattern {
+            pattern: pattern.to_string(),
+            init_v,
+        })
+    }
+
+    fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        self.request_done(Request::AddVector {
+            pattern: pattern.to_string(),
+            d_time,
+            voltage,
+        })
+    }
+
+    fn add_vectors(&mut self, pattern: &str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res {
+        self.request_done(Request::AddVectors {
+            pattern: pattern.to_string(),
+            d_time,
+            voltage,
+        })
+    }
+
+    fn set_measure_event(
+        &mut self,
+        pattern: &str,
+        event: &str,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    ) -> Res {
+        self.request_done(Request::SetMeasureEvent {
+            pattern: pattern.to_string(),
+            event: event.to_string(),
+            time,
+            points,
+            interval,
+            average,
+            measure_event_mode,
+        })
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        self.request_done(Request::SetRangeEvent {
+            pattern: pattern.to_string(),
+            event: event.to_string(),
+            time,
+            range,
+        })
+    }
+
+    fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
+        self.request_done(Request::AddSequence {
+            chan_id,
+            pattern: pattern.to_string(),
+            count,
+        })
+    }
+
+    fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res {
+        self.request_done(Request::AddSequences {
+            chan_id,
+            pattern: pattern.iter().map(|p| p.to_string()).collect(),
+            count,
+        })
+    }
+
+    fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
+        self.request_done(Request::SetVector {
+            pattern: pattern.to_string(),
+            time,
+            voltage,
+        })
+    }
+
+    fn initialize(&mut self) -> Res {
+        self.request_done(Request::Initialize)
+    }
+
+    fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
+        self.request_done(Request::SetOperationMode {
+            chan_id,
+            operation_mode,
+        })
+    }
+
+    fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
+        self.request_done(Request::SetMeasureMode { chan_id, mode })
+    }
+
+    fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
+        match self.request(Request::GetMeasureMode { chan_id })? {
+            Response::MeasureMode(mode) => Ok(mode),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
+        match self.request(Request::GetOperationMode { chan_id })? {
+            Response::OperationMode(mode) => Ok(mode),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        self.request_done(Request::SetForceVoltageRange { chan_id, range })
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        self.request_done(Request::SetMeasureVoltageRange { chan_id, range })
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        self.request_done(Request::SetMeasureCurrentRange { chan_id, range })
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.request_done(Request::SetForceDelay { chan_id, delay })
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.request_done(Request::SetMeasureDelay { chan_id, delay })
+    }
+
+    fn connect(&mut self, chan_id: usize) -> Res {
+        self.request_done(Request::Connect { chan_id })
+    }
+
+    fn execute(&mut self) -> Res {
+        self.request_done(Request::Execute)
+    }
+
+    fn wait_until_completed(&mut self) -> Res {
+        self.request_done(Request::WaitUntilCompleted)
+    }
+
+    fn abort(&mut self) -> Res {
+        self.request_done(Request::Abort)
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        self.request_done(Request::AbortChannel { chan_id })
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        match self.request(Request::GetChannelStatus { chan_id })? {
+            Response::ChannelStatus(status) => Ok(status),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        match self.request(Request::GetMeasureValues { chan_id, current })? {
+            Response::Measurements(measurements) => Ok(measurements),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        match self.request(Request::GetCompletedMeasureEventSize { chan_id })? {
+            Response::EventSize(completed, total) => Ok((completed, total)),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        match self.request(Request::GetCompletedMeasureValues { chan_id, offset })? {
+            Response::ChannelData(data) => Ok(data),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        match self.request(Request::GetChannelData { chan_id })? {
+            Response::ChannelData(data) => Ok(data),
+            _ => Result::Err(Error::RemoteConnectionError),
+        }
+    }
+
+    fn do_self_calibration(&mut self) -> Res {
+        self.request_done(Request::DoSelfCalibration)
+    }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        self.request_done(Request::DcForceVoltage { chan_id, voltage })
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        self.request_value(Request::DcMeasureValue { chan_id })
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        self.request_value(Request::DcMeasureAveragedValue {
+            chan_id,
+            points,
+            interval,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+
+    /// Serves a simulated WGFMU on a free loopback port, returns its address
+    fn start(token: Option<&str>) -> String {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+
+        let acquire = |_: &str| Ok(TestWgfmu::new(MemristorParams::default()).unwrap());
+        let token = token.map(str::to_string);
+        let served = address.clone();
+        std::thread::spawn(move || serve(acquire, served.as_str(), token));
+
+        // The server is up once it takes connections
+        for _ in 0..100 {
+            if TcpStream::connect(address.as_str()).is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        address
+    }
+
+    /// A few calls whose answers are told apart, so a client one answer behind fails them
+    fn round_trip(remote: &mut RemoteWgfmu) {
+        assert_eq!(remote.get_channel_ids(), Ok(vec![101, 102]));
+        assert_eq!(remote.create_pattern("p", 0.0), Ok(()));
+        assert_eq!(remote.create_pattern("p", 0.0), Err(Error::PatternAlreadyExistsError));
+        assert_eq!(remote.add_vector("p", 1e-6, 1.0), Ok(()));
+    }
+
+    #[test]
+    fn without_token() {
+        let address = start(None);
+        round_trip(&mut RemoteWgfmu::new(address.as_str(), None).unwrap());
+    }
+
+    #[test]
+    fn with_token() {
+        let address = start(Some("secret"));
+        round_trip(&mut RemoteWgfmu::new(address.as_str(), Some("secret")).unwrap());
+    }
+
+    #[test]
+    fn token_the_server_does_not_need() {
+        let address = start(None);
+        round_trip(&mut RemoteWgfmu::new(address.as_str(), Some("secret")).unwrap());
+    }
+
+    #[test]
+    fn wrong_token() {
+        let address = start(Some("secret"));
+        assert!(RemoteWgfmu::new(address.as_str(), Some("guess")).is_err());
+        assert!(RemoteWgfmu::new(address.as_str(), None).is_err());
+    }
+}
@@ -1,28 +1,116 @@
 // use libloading::{Library, Symbol};
+use log::debug;
 use num_traits::{FromPrimitive};
-use std::{collections::HashMap};
+use std::{collections::HashMap, time::Instant};
 // use std::ffi::{CStr, CString};
 // use std::os::raw::{c_char, c_double, c_int};
 // use std::rc::Rc;
 
 use super::driver::*;
+use super::memristor::{Memristor, MemristorParams};
+use crate::b1500::{CHANNEL1, CHANNEL2};
 
 // WGFMU Library rust bindings, see https://l4.granasat.space/docs/B1500A/wgfmu/programming_guide for more details.
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct TestWgfmu {
-    vectors: HashMap<String, Vector>,
-    last_t: f64,
-    measured_waveform: Vec<Measurement>
+    patterns: HashMap<String, Pattern>,
+    channels: HashMap<usize, Channel>,
+    device: Memristor,
+    /// The simulated device sits between these two channels, (top electrode, bottom electrode)
+    device_channels: (usize, usize),
+    /// Last execution, the sequencer runs in real time from when it was started
+    execution: Option<Execution>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Execution {
+    started: Instant,
+    total_time: f64,
 }
 
 #[derive(Clone, Debug)]
-struct Vector {
-    measurements: Vec<Measurement>,
+struct Channel {
+    sequence: Vec<(String, usize)>,
+    operation_mode: OperationMode,
+    measure_mode: MeasureMode,
+    ranges: Ranges,
+    /// Voltage forced in the DC operation mode
+    dc_voltage: f64,
+    measured: Vec<(f64, f64)>, // (time, value)
+    /// Sequencer time at which the channel was aborted
+    stopped: Option<f64>,
+    /// Time at which each measure event of the last execution ends
+    event_ends: Vec<f64>,
+}
+
+/// Range and delay settings of a channel, `initialize` sets them back to these defaults
+#[derive(Clone, Copy, Debug)]
+struct Ranges {
+    force_voltage: ForceVoltageRange,
+    measure_voltage: MeasureVoltageRange,
+    measure_current: MeasureCurrentRange,
+    force_delay: f64,
+    measure_delay: f64,
+}
+
+impl Default for Ranges {
+    fn default() -> Self {
+        Ranges {
+            force_voltage: ForceVoltageRange::Auto,
+            measure_voltage: MeasureVoltageRange::Range5V,
+            measure_current: MeasureCurrentRange::Range10mA,
+            force_delay: 0.0,
+            measure_delay: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Pattern {
+    init_v: f64,
+    vectors: Vec<(f64, f64)>, // (time, voltage), time is absolute from the beginning of the pattern
     last_t: f64,
+    events: Vec<MeasureEvent>,
+    /// Current range switches, (time, range), sorted by time
+    range_events: Vec<(f64, MeasureCurrentRange)>,
+}
+
+#[derive(Clone, Debug)]
+struct MeasureEvent {
+    time: f64,
+    points: usize,
+    interval: f64,
+    average: f64,
+    mode: MeasureEventMode,
 }
 
+/// A single measurement point of a channel, it covers the averaging window [start, start + average]
+#[derive(Clone, Copy, Debug)]
+struct MeasureWindow {
+    start: f64,
+    average: f64,
+    mode: MeasureEventMode,
+}
+
+// B1530A limits, Page 1-14, Table 1-6 on https://twiki.cern.ch/twiki/pub/Main/AtlasEdinburghGroupHardwareUpgradeDocumentation/Agilent_B1530A_WGFMU_UserGuide.pdf
+const MAX_VECTORS: usize = 2048; // Per pattern
+const MIN_DTIME: f64 = 1e-8;
+const MAX_VOLTAGE: f64 = 10.0;
+const MIN_INTERVAL: f64 = 1e-8;
+const MAX_AVERAGE: f64 = 0.02097152;
+const MAX_MEASURE_POINTS: usize = 4_000_000; // Per channel
+const MAX_DELAY: f64 = 50e-9; // Force and measure delays, in absolute value
+const MAX_DC_SAMPLES: usize = 65535; // Points and interval of the DC averaged measurements
+/// Times are compared with this tolerance, as they are usually the result of rounding to 10 ns
+const TIME_EPSILON: f64 = 1e-12;
+
+/// The B1530A digitizes at 200 MSa/s, raw data has one point every 5 ns of the averaging window
+const SAMPLING_PERIOD: f64 = 5e-9;
+/// Maximum number of points evaluated to compute an averaged measurement point
+const MAX_AVERAGING_POINTS: usize = 16;
+
 fn get_result(ret: i32) -> Res {
     match ret {
         0 => Ok(()),
@@ -48,16 +136,64 @@ impl WgfmuDriver for TestWgfmu {
     }
 
     fn clear(&mut self) -> Res {
-        let ret = 0;
-        get_result(ret)
+        // The device keeps its state, only the setup is cleared
+        self.patterns.clear();
+        for channel in self.channels.values_mut() {
+            channel.sequence.clear();
+            channel.measured.clear();
+            channel.stopped = None;
+            channel.event_ends.clear();
+        }
+        self.execution = None;
+
+        get_result(0)
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        let mut ids = self.channels.keys().copied().collect::<Vec<usize>>();
+        ids.sort();
+        Ok(ids)
+    }
+
+    // The simulator raises no warnings and its errors come with no text
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    fn set_warning_level(&mut self, _level: WarningLevel) -> Res {
+        Ok(())
+    }
+
+    fn treat_warnings_as_errors(&mut self, _level: WarningLevel) -> Res {
+        Ok(())
     }
 
     fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
-        self.vectors.insert(
+        if pattern.is_empty() {
+            return Result::Err(Error::IllegalStringError);
+        }
+        if self.patterns.contains_key(pattern) {
+            return Result::Err(Error::PatternAlreadyExistsError);
+        }
+        check_voltage(init_v)?;
+
+        self.patterns.insert(
             pattern.to_string(),
-            Vector {
-                measurements: Vec::new(),
+            Pattern {
+                init_v,
+                vectors: Vec::new(),
                 last_t: 0.0,
+                events: Vec::new(),
+                range_events: Vec::new(),
             },
         );
 
@@ -65,24 +201,24 @@ impl WgfmuDriver for TestWgfmu {
     }
 
     fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        // Same as the production driver, vectors that take no time are given the shortest one
+        let d_time = if d_time > 0.0 { d_time } else { MIN_DTIME };
 
-        let mut d_time = d_time;
-
-        if ! (d_time > 0.0) {
-            d_time = 1e-8;
+        if d_time < MIN_DTIME - TIME_EPSILON {
+            return Result::Err(Error::ParameterOutOfRangeError);
         }
+        check_voltage(voltage)?;
 
-        match self.vectors.get_mut(pattern) {
-            Some(vector) => {
-                vector.last_t = vector.last_t + d_time;
-                vector.measurements.push(Measurement {
-                    voltage,
-                    current: Some(voltage/2.0),
-                    time: vector.last_t,
-                });
+        match self.patterns.get_mut(pattern) {
+            Some(pattern) => {
+                if pattern.vectors.len() >= MAX_VECTORS {
+                    return Result::Err(Error::ParameterOutOfRangeError);
+                }
+                pattern.last_t += d_time;
+                pattern.vectors.push((pattern.last_t, voltage));
                 get_result(0)
             }
-            None => get_result(0),
+            None => Result::Err(Error::PatternNotFoundError),
         }
     }
     
@@ -111,44 +247,76 @@ impl WgfmuDriver for TestWgfmu {
         average: f64,
         measure_event_mode: MeasureEventMode,
     ) -> Res {
-        let ret = 0;
-        get_result(ret)
+        let pattern = match self.patterns.get_mut(pattern) {
+            Some(pattern) => pattern,
+            None => return Result::Err(Error::PatternNotFoundError),
+        };
+
+        if time < 0.0
+            || points < 1
+            || (points > 1 && interval < MIN_INTERVAL - TIME_EPSILON)
+            || (average != 0.0 && average < MIN_DTIME - TIME_EPSILON)
+            || average > MAX_AVERAGE
+            || (points > 1 && average > interval + TIME_EPSILON)
+        {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+
+        let event = MeasureEvent {
+            time,
+            points: points as usize,
+            interval,
+            average,
+            mode: measure_event_mode,
+        };
+
+        // Events of a pattern cannot overlap
+        let idx = pattern.events.partition_point(|ev| ev.time <= time);
+        let overlaps_prev = idx > 0 && pattern.events[idx - 1].end_time() > event.time + TIME_EPSILON;
+        let overlaps_next = idx < pattern.events.len() && event.end_time() > pattern.events[idx].time + TIME_EPSILON;
+        if overlaps_prev || overlaps_next {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+        pattern.events.insert(idx, event);
+
+        get_result(0)
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        let pattern = match self.patterns.get_mut(pattern) {
+            Some(pattern) => pattern,
+            None => return Result::Err(Error::PatternNotFoundError),
+        };
+
+        if event.is_empty() {
+            return Result::Err(Error::IllegalStringError);
+        }
+        if time < 0.0 {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+
+        let idx = pattern.range_events.partition_point(|&(t, _)| t <= time);
+        pattern.range_events.insert(idx, (time, range));
+
+        get_result(0)
     }
 
     fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
         let ret = 0;
 
-        match self.vectors.get_mut(pattern) {
-            Some(vector) => {
-                let mut vector_copy = vector.to_owned();
-                let cycle_time = vector.last_t;
-                for i in 0..count-1 {
-                    // vector.measurements.append(
-                    //     &mut vector_copy.measurements.iter().map(|meas| {
-                    //         Measurement {
-                    //             voltage: meas.voltage,
-                    //             current: meas.current,
-                    //             time: meas.time + vector.last_t
-                    //         }
-                    //     }).collect::<Vec<Measurement>>()
-                    // );
-                    // vector.last_t = vector.last_t + cycle_time;
-
-                    self.measured_waveform.append(
-                        &mut vector_copy.measurements.iter().map(|meas| {
-                            Measurement {
-                                voltage: meas.voltage,
-                                current: meas.current,
-                                time: meas.time + cycle_time * (i as f64) + self.last_t
-                            }
-                        }).collect::<Vec<Measurement>>()
-                    );
-                }
-                self.last_t = self.last_t + cycle_time * (count as f64 - 1.0);
-            },
-            None => {}
+        let channel = match self.channels.get_mut(&chan_id) {
+            Some(channel) => channel,
+            None => return Result::Err(Error::ChannelNotFoundError),
         };
 
+        if !self.patterns.contains_key(pattern) {
+            return Result::Err(Error::PatternNotFoundError);
+        }
+        if count < 1 {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+        channel.sequence.push((pattern.to_string(), count));
+
         get_result(ret)
     }
 
@@ -161,52 +329,105 @@ impl WgfmuDriver for TestWgfmu {
         }
 
         for i in 0..len {
-            self.add_sequence(chan_id, pattern[i], count[i]);
+            self.add_sequence(chan_id, pattern[i], count[i])?;
         }
 
         get_result(ret)
     }
 
     fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
-        let ret = 0;
-        get_result(ret)
+        if time < MIN_DTIME - TIME_EPSILON {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+        check_voltage(voltage)?;
+
+        let pattern = match self.patterns.get_mut(pattern) {
+            Some(pattern) => pattern,
+            None => return Result::Err(Error::PatternNotFoundError),
+        };
+
+        // Replaces the vector at `time` if there is one, inserts it keeping the time order otherwise
+        let idx = pattern.vectors.partition_point(|&(t, _)| t < time);
+        match pattern.vectors.get(idx) {
+            Some(&(t, _)) if t == time => pattern.vectors[idx].1 = voltage,
+            _ => {
+                if pattern.vectors.len() >= MAX_VECTORS {
+                    return Result::Err(Error::ParameterOutOfRangeError);
+                }
+                pattern.vectors.insert(idx, (time, voltage));
+            }
+        }
+        pattern.last_t = pattern.last_t.max(time);
+
+        get_result(0)
     }
 
     fn initialize(&mut self) -> Res {
+        for channel in self.channels.values_mut() {
+            channel.ranges = Ranges::default();
+            channel.dc_voltage = 0.0;
+        }
+
         get_result(0)
     }
 
     fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
-        let ret = 0;
-        get_result(ret)
+        match self.channels.get_mut(&chan_id) {
+            Some(channel) => {
+                channel.operation_mode = operation_mode;
+                get_result(0)
+            }
+            None => Result::Err(Error::ChannelNotFoundError),
+        }
     }
 
     fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
-        let ret = 0;
-        get_result(ret)
+        match self.channels.get_mut(&chan_id) {
+            Some(channel) => {
+                channel.measure_mode = mode;
+                get_result(0)
+            }
+            None => Result::Err(Error::ChannelNotFoundError),
+        }
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        self.set_ranges(chan_id, |ranges| ranges.force_voltage = range)
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        self.set_ranges(chan_id, |ranges| ranges.measure_voltage = range)
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        self.set_ranges(chan_id, |ranges| ranges.measure_current = range)
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        if delay.abs() > MAX_DELAY {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+        self.set_ranges(chan_id, |ranges| ranges.force_delay = delay)
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        if delay.abs() > MAX_DELAY {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+        self.set_ranges(chan_id, |ranges| ranges.measure_delay = delay)
     }
 
     fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
-        #[allow(unused_mut)]
-        let ret = 0;
-        match get_result(ret) {
-            Ok(_) => match FromPrimitive::from_i32(MeasureMode::MeasureModeCurrent as i32) {
-                Some(meas_mode) => Ok(meas_mode),
-                None => Result::Err(Error::UnidentifiedError),
-            },
-            Err(err) => Result::Err(err),
+        match self.channels.get(&(chan_id as usize)) {
+            Some(channel) => Ok(channel.measure_mode),
+            None => Result::Err(Error::ChannelNotFoundError),
         }
     }
 
     fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
-        #[allow(unused_mut)]
-        let ret = 0;
-        match get_result(ret) {
-            Ok(_) => match FromPrimitive::from_i32(OperationMode::OperationModeFastIV as i32) {
-                Some(op_mode) => Ok(op_mode),
-                None => Result::Err(Error::UnidentifiedError),
-            },
-            Err(err) => Result::Err(err),
+        match self.channels.get(&(chan_id as usize)) {
+            Some(channel) => Ok(channel.operation_mode),
+            None => Result::Err(Error::ChannelNotFoundError),
         }
     }
 
@@ -216,35 +437,606 @@ impl WgfmuDriver for TestWgfmu {
     }
 
     fn execute(&mut self) -> Res {
-        let ret = 0;
-        get_result(ret)
+        self.check_setup()?;
+
+        let waveforms = self
+            .channels
+            .iter()
+            .map(|(&chan_id, channel)| (chan_id, self.build_waveform(channel)))
+            .collect::<HashMap<usize, Waveform>>();
+        let total_time = waveforms.values().map(|waveform| waveform.end_time()).fold(0.0, f64::max);
+
+        // Every measurement point is computed from one or more evaluations of the channel at `queries` times,
+        // (time, channel, point index), each channel accumulates them in `points`, (time, sum, count).
+        let mut queries: Vec<(f64, usize, usize)> = vec![];
+        let mut points: HashMap<usize, Vec<(f64, f64, usize)>> = HashMap::new();
+        for (&chan_id, waveform) in waveforms.iter() {
+            let chan_points = points.entry(chan_id).or_default();
+
+            for window in waveform.windows.iter() {
+                match window.mode {
+                    MeasureEventMode::MeasureEventDataAveraged => {
+                        let n = f64::round(window.average / SAMPLING_PERIOD).clamp(1.0, MAX_AVERAGING_POINTS as f64) as usize;
+                        for j in 0..n {
+                            let t = if window.average > 0.0 {
+                                window.start + window.average * (j as f64 + 0.5) / n as f64
+                            } else {
+                                window.start
+                            };
+                            queries.push((t, chan_id, chan_points.len()));
+                        }
+                        // Averaged points are time stamped at the start of their window
+                        chan_points.push((window.start, 0.0, 0));
+                    }
+                    MeasureEventMode::MeasureEventDataRaw => {
+                        let n = f64::round(window.average / SAMPLING_PERIOD).max(1.0) as usize;
+                        for j in 0..n {
+                            let t = window.start + j as f64 * SAMPLING_PERIOD;
+                            queries.push((t, chan_id, chan_points.len()));
+                            chan_points.push((t, 0.0, 0));
+                        }
+                    }
+                }
+            }
+        }
+        queries.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let (top, bottom) = self.device_channels;
+        let empty = Waveform::default();
+        let top_wf = waveforms.get(&top).unwrap_or(&empty);
+        let bottom_wf = waveforms.get(&bottom).unwrap_or(&empty);
+        let device_voltage = |t: f64, right: bool| top_wf.voltage(t, right) - bottom_wf.voltage(t, right);
+
+        // Breakpoints of the voltage across the device, it is linear between two consecutive ones
+        let mut breakpoints = top_wf
+            .points
+            .iter()
+            .chain(bottom_wf.points.iter())
+            .map(|&(t, _)| t)
+            .collect::<Vec<f64>>();
+        breakpoints.sort_by(|a, b| a.total_cmp(b));
+        breakpoints.dedup();
+
+        let mut t = 0.0;
+        let mut breakpoints = breakpoints.into_iter().peekable();
+        for (sample_t, chan_id, idx) in queries {
+            // Integrate the device up to the sample time
+            while let Some(&bp) = breakpoints.peek() {
+                if bp > sample_t {
+                    break;
+                }
+                self.device.apply(device_voltage(t, true), device_voltage(bp, false), bp - t);
+                t = bp;
+                breakpoints.next();
+            }
+            self.device.apply(device_voltage(t, true), device_voltage(sample_t, false), sample_t - t);
+            t = sample_t;
+
+            // Values beyond the measurement range saturate at its full scale
+            let channel = &self.channels[&chan_id];
+            let value = match channel.measure_mode {
+                MeasureMode::MeasureModeVoltage => {
+                    let full_scale = channel.ranges.measure_voltage.full_scale();
+                    waveforms[&chan_id].voltage(sample_t, false).clamp(-full_scale, full_scale)
+                }
+                MeasureMode::MeasureModeCurrent => {
+                    // Current sourced by the channel into the device
+                    let current = self.device.current(device_voltage(sample_t, false));
+                    let range = waveforms[&chan_id].current_range(sample_t).unwrap_or(channel.ranges.measure_current);
+                    let full_scale = range.full_scale();
+                    if chan_id == top {
+                        current.clamp(-full_scale, full_scale)
+                    } else if chan_id == bottom {
+                        (-current).clamp(-full_scale, full_scale)
+                    } else {
+                        0.0
+                    }
+                }
+            };
+            let point = &mut points.get_mut(&chan_id).unwrap()[idx];
+            point.1 += value;
+            point.2 += 1;
+        }
+
+        for (chan_id, channel) in self.channels.iter_mut() {
+            channel.measured = points[chan_id].iter().map(|&(time, sum, n)| (time, sum / n as f64)).collect();
+        }
+
+        // Whatever is left of the sequences after the last sample still affects the device
+        for bp in breakpoints {
+            self.device.apply(device_voltage(t, true), device_voltage(bp, false), bp - t);
+            t = bp;
+        }
+
+        debug!("Simulated device state: {}, conductance: {} S", self.device.state(), self.device.conductance());
+
+        // The results are already there, but they are only handed out as the sequencer time goes by
+        for (chan_id, channel) in self.channels.iter_mut() {
+            channel.stopped = None;
+            channel.event_ends = waveforms[chan_id].event_ends.clone();
+        }
+        self.execution = Some(Execution {
+            started: Instant::now(),
+            total_time,
+        });
+
+        get_result(0)
     }
 
     fn wait_until_completed(&mut self) -> Res {
-        let ret = 0;
-        get_result(ret)
+        if let Some(execution) = self.execution {
+            let remaining = execution.total_time - execution.started.elapsed().as_secs_f64();
+            if remaining > 0.0 && self.channels.values().any(|channel| channel.stopped.is_none()) {
+                std::thread::sleep(std::time::Duration::from_secs_f64(remaining));
+            }
+        }
+        get_result(0)
+    }
+
+    fn abort(&mut self) -> Res {
+        // Nothing was executed, there is nothing to stop
+        if self.execution.is_none() {
+            return get_result(0);
+        }
+
+        let chan_ids = self.channels.keys().copied().collect::<Vec<usize>>();
+        for chan_id in chan_ids {
+            self.abort_channel(chan_id)?;
+        }
+        get_result(0)
     }
 
-    fn get_measure_values(&mut self, chan_id: usize) -> Result<Vec<Measurement>, Error> {
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        let execution = match self.execution {
+            Some(execution) => execution,
+            None => return Result::Err(Error::SequencerNotRunningError),
+        };
+        let elapsed = execution.started.elapsed().as_secs_f64();
+
+        match self.channels.get_mut(&chan_id) {
+            Some(channel) => {
+                // Only the points measured before the abort are kept
+                if channel.stopped.is_none() && elapsed < execution.total_time {
+                    channel.stopped = Some(elapsed);
+                    channel.measured.retain(|&(time, _)| time <= elapsed);
+                }
+                get_result(0)
+            }
+            None => Result::Err(Error::ChannelNotFoundError),
+        }
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        let channel = match self.channels.get(&chan_id) {
+            Some(channel) => channel,
+            None => return Result::Err(Error::ChannelNotFoundError),
+        };
+        let execution = match self.execution {
+            Some(execution) => execution,
+            None => {
+                return Ok(ChannelStatus {
+                    status: Status::Idle,
+                    elapsed_time: 0.0,
+                    total_time: 0.0,
+                })
+            }
+        };
+
+        let elapsed = execution.started.elapsed().as_secs_f64();
+        let (status, elapsed_time) = match channel.stopped {
+            Some(stopped) => (Status::AbortCompleted, stopped),
+            None if elapsed < execution.total_time => (Status::Running, elapsed),
+            None => (Status::Completed, execution.total_time),
+        };
+
+        Ok(ChannelStatus {
+            status,
+            elapsed_time,
+            total_time: execution.total_time,
+        })
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
         std::thread::sleep(std::time::Duration::from_millis(4000));
 
-        Ok(self.measured_waveform.to_owned())
+        // Same as the production driver, the value of `chan_id` is returned as the voltage and `current` as the current
+        let records = RecordSet {
+            channels: vec![
+                self.channel_data(chan_id, 0, f64::INFINITY)?,
+                self.channel_data(current, 0, f64::INFINITY)?,
+            ],
+        };
+
+        Ok(records.measurements(chan_id, current))
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        let channel = self.channels.get(&chan_id).ok_or(Error::ChannelNotFoundError)?;
+        let t = self.sequencer_time(channel);
+
+        Ok((
+            channel.event_ends.iter().filter(|&&end| end <= t + TIME_EPSILON).count(),
+            channel.event_ends.len(),
+        ))
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        let channel = self.channels.get(&chan_id).ok_or(Error::ChannelNotFoundError)?;
+        let t = self.sequencer_time(channel);
+
+        self.channel_data(chan_id, offset, t)
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        self.channel_data(chan_id, 0, f64::INFINITY)
     }
 
     fn do_self_calibration(&mut self) -> Res {
         Ok(())
     }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        check_voltage(voltage)?;
+        let channel = self.dc_channel(chan_id)?;
+        channel.dc_voltage = voltage;
+
+        get_result(0)
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        self.dc_measure(chan_id, 0.0)
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        if !(1..=MAX_DC_SAMPLES).contains(&points) || !(1..=MAX_DC_SAMPLES).contains(&interval) {
+            return Result::Err(Error::ParameterOutOfRangeError);
+        }
+
+        self.dc_measure(chan_id, (points * interval) as f64 * DC_SAMPLING_PERIOD)
+    }
 }
 
 impl TestWgfmu {
     #[rustfmt::skip]
-    pub fn new() -> Result<TestWgfmu, Box<dyn std::error::Error>> {
+    pub fn new(params: MemristorParams) -> Result<TestWgfmu, Box<dyn std::error::Error>> {
+        let channel = Channel {
+            sequence: vec![],
+            operation_mode: OperationMode::OperationModeFastIV,
+            measure_mode: MeasureMode::MeasureModeVoltage,
+            ranges: Ranges::default(),
+            dc_voltage: 0.0,
+            measured: vec![],
+            stopped: None,
+            event_ends: vec![],
+        };
+
         Ok(TestWgfmu {
-            vectors: HashMap::new(),
-            last_t: 0.0,
-            measured_waveform: vec![]
+            patterns: HashMap::new(),
+            channels: HashMap::from([(CHANNEL1, channel.clone()), (CHANNEL2, channel)]),
+            device: Memristor::new(params),
+            device_channels: (CHANNEL2, CHANNEL1),
+            execution: None,
+        })
+    }
+
+    /// A channel in the DC operation mode
+    fn dc_channel(&mut self, chan_id: usize) -> Result<&mut Channel, Error> {
+        match self.channels.get_mut(&chan_id) {
+            Some(channel) if channel.operation_mode == OperationMode::OperationModeDC => Ok(channel),
+            Some(_) => Result::Err(Error::ContextError),
+            None => Result::Err(Error::ChannelNotFoundError),
+        }
+    }
+
+    /// Measures a DC channel, the device is biased by the DC voltages for `duration` seconds first
+    fn dc_measure(&mut self, chan_id: usize, duration: f64) -> Result<f64, Error> {
+        self.dc_channel(chan_id)?;
+
+        let (top, bottom) = self.device_channels;
+        let dc_voltage = |chan_id| match self.channels.get(&chan_id) {
+            Some(channel) if channel.operation_mode == OperationMode::OperationModeDC => channel.dc_voltage,
+            _ => 0.0,
+        };
+        let device_voltage = dc_voltage(top) - dc_voltage(bottom);
+        self.device.apply(device_voltage, device_voltage, duration);
+
+        // Values beyond the measurement range saturate at its full scale
+        let channel = &self.channels[&chan_id];
+        let value = match channel.measure_mode {
+            MeasureMode::MeasureModeVoltage => {
+                let full_scale = channel.ranges.measure_voltage.full_scale();
+                channel.dc_voltage.clamp(-full_scale, full_scale)
+            }
+            MeasureMode::MeasureModeCurrent => {
+                let current = self.device.current(device_voltage);
+                let full_scale = channel.ranges.measure_current.full_scale();
+                if chan_id == top {
+                    current.clamp(-full_scale, full_scale)
+                } else if chan_id == bottom {
+                    (-current).clamp(-full_scale, full_scale)
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Changes the range settings of a channel
+    fn set_ranges<F: FnOnce(&mut Ranges)>(&mut self, chan_id: usize, set: F) -> Res {
+        match self.channels.get_mut(&chan_id) {
+            Some(channel) => {
+                set(&mut channel.ranges);
+                get_result(0)
+            }
+            None => Result::Err(Error::ChannelNotFoundError),
+        }
+    }
+
+    /// Time the sequencer of `channel` has run in the last execution
+    fn sequencer_time(&self, channel: &Channel) -> f64 {
+        match (self.execution, channel.stopped) {
+            (None, _) => 0.0,
+            (Some(_), Some(stopped)) => stopped,
+            (Some(execution), None) => execution.started.elapsed().as_secs_f64().min(execution.total_time),
+        }
+    }
+
+    /// Samples of `chan_id` starting at `offset`, measured up to `until`
+    fn channel_data(&self, chan_id: usize, offset: usize, until: f64) -> Result<ChannelData, Error> {
+        let channel = self.channels.get(&chan_id).ok_or(Error::ChannelNotFoundError)?;
+
+        Ok(ChannelData {
+            chan_id,
+            measure_mode: channel.measure_mode,
+            samples: channel
+                .measured
+                .iter()
+                .skip(offset)
+                .take_while(|&&(time, _)| time <= until)
+                .map(|&(time, value)| Sample { time, value })
+                .collect(),
         })
     }
+
+    /// Validates the whole setup the same way the instrument does when it is sent to the sequencer
+    fn check_setup(&self) -> Res {
+        for channel in self.channels.values() {
+            let mut min_v: f64 = 0.0;
+            let mut max_v: f64 = 0.0;
+            let mut measure_points = 0;
+
+            for (name, count) in channel.sequence.iter() {
+                let pattern = &self.patterns[name];
+
+                // Every event has to finish before the end of the pattern
+                if pattern.events.iter().any(|ev| ev.end_time() > pattern.last_t + TIME_EPSILON) {
+                    return Result::Err(Error::ParameterOutOfRangeError);
+                }
+                if pattern.range_events.iter().any(|&(time, _)| time > pattern.last_t + TIME_EPSILON) {
+                    return Result::Err(Error::ParameterOutOfRangeError);
+                }
+
+                measure_points += count * pattern.events.iter().map(|ev| ev.n_values()).sum::<usize>();
+
+                for &(_, voltage) in pattern.vectors.iter() {
+                    min_v = min_v.min(voltage);
+                    max_v = max_v.max(voltage);
+                }
+                min_v = min_v.min(pattern.init_v);
+                max_v = max_v.max(pattern.init_v);
+            }
+
+            if measure_points > MAX_MEASURE_POINTS {
+                return Result::Err(Error::ParameterOutOfRangeError);
+            }
+
+            // The whole sequence of a channel has to fit in a single force range, that is +-5 V, 0 to +10 V or -10 V
+            // to 0 V.
+            let half_range = MAX_VOLTAGE / 2.0;
+            if (min_v < -half_range && max_v > 0.0) || (max_v > half_range && min_v < 0.0) {
+                return Result::Err(Error::ParameterOutOfRangeError);
+            }
+
+            // A fixed range has to fit the sequence too
+            if let Some((low, high)) = channel.ranges.force_voltage.limits() {
+                if min_v < low || max_v > high {
+                    return Result::Err(Error::ParameterOutOfRangeError);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lays out the sequence of a channel as an absolute time waveform
+    fn build_waveform(&self, channel: &Channel) -> Waveform {
+        let mut waveform = Waveform::default();
+        let mut t = 0.0;
+
+        for (name, count) in channel.sequence.iter() {
+            let pattern = &self.patterns[name];
+
+            for _ in 0..*count {
+                // Every pattern starts at its initial voltage, the jump from the previous pattern is instantaneous
+                waveform.points.push((t, pattern.init_v));
+                for &(time, voltage) in pattern.vectors.iter() {
+                    waveform.points.push((t + time, voltage));
+                }
+
+                for event in pattern.events.iter() {
+                    waveform.event_ends.push(t + event.end_time());
+                    for k in 0..event.points {
+                        waveform.windows.push(MeasureWindow {
+                            start: t + event.time + k as f64 * event.interval,
+                            average: event.average,
+                            mode: event.mode,
+                        });
+                    }
+                }
+
+                for &(time, range) in pattern.range_events.iter() {
+                    waveform.ranges.push((t + time, range));
+                }
+
+                t += pattern.last_t;
+            }
+        }
+
+        waveform
+    }
+}
+
+fn check_voltage(voltage: f64) -> Res {
+    if voltage.abs() > MAX_VOLTAGE {
+        return Result::Err(Error::ParameterOutOfRangeError);
+    }
+    Ok(())
+}
+
+impl MeasureEvent {
+    /// eventEndTime = time + interval * (points - 1) + average
+    fn end_time(&self) -> f64 {
+        self.time + self.interval * (self.points as f64 - 1.0) + self.average
+    }
+
+    /// Number of values the event stores in the channel memory
+    fn n_values(&self) -> usize {
+        match self.mode {
+            MeasureEventMode::MeasureEventDataAveraged => self.points,
+            MeasureEventMode::MeasureEventDataRaw => {
+                self.points * f64::round(self.average / SAMPLING_PERIOD).max(1.0) as usize
+            }
+        }
+    }
+}
+
+/// Piecewise linear voltage forced by a channel. A jump is represented by two consecutive points at the same time.
+#[derive(Debug, Default)]
+struct Waveform {
+    points: Vec<(f64, f64)>, // (time, voltage)
+    windows: Vec<MeasureWindow>,
+    event_ends: Vec<f64>,
+    /// Current range switches, (time, range), sorted by time
+    ranges: Vec<(f64, MeasureCurrentRange)>,
+}
+
+impl Waveform {
+    /// Time at which the last vector ends
+    fn end_time(&self) -> f64 {
+        self.points.last().map_or(0.0, |&(time, _)| time)
+    }
+
+    /// Current range set by the last range event at or before `t`, None before the first one
+    fn current_range(&self, t: f64) -> Option<MeasureCurrentRange> {
+        let idx = self.ranges.partition_point(|&(time, _)| time <= t + TIME_EPSILON);
+        idx.checked_sub(1).map(|i| self.ranges[i].1)
+    }
+
+    /// Voltage forced at time `t`, when there is a jump at `t` the value right after it (`right`) or right before it
+    /// is returned. Before the sequence starts and after it ends the channel holds the first and last voltages.
+    fn voltage(&self, t: f64, right: bool) -> f64 {
+        let lo = self.points.partition_point(|&(time, _)| time < t);
+        let hi = self.points.partition_point(|&(time, _)| time <= t);
+
+        if lo < hi {
+            return if right { self.points[hi - 1].1 } else { self.points[lo].1 };
+        }
+
+        match (lo.checked_sub(1).and_then(|i| self.points.get(i)), self.points.get(lo)) {
+            (Some(&(t_0, v_0)), Some(&(t_1, v_1))) => v_0 + (t - t_0) * (v_1 - v_0) / (t_1 - t_0),
+            (Some(&(_, v)), None) | (None, Some(&(_, v))) => v,
+            (None, None) => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wgfmu() -> TestWgfmu {
+        TestWgfmu::new(MemristorParams::default()).unwrap()
+    }
+
+    #[test]
+    fn too_many_vectors() {
+        let mut wgfmu = wgfmu();
+        wgfmu.create_pattern("p", 0.0).unwrap();
+        for _ in 0..MAX_VECTORS {
+            wgfmu.add_vector("p", 1e-8, 1.0).unwrap();
+        }
+
+        assert_eq!(wgfmu.add_vector("p", 1e-8, 1.0), Err(Error::ParameterOutOfRangeError));
+    }
+
+    #[test]
+    fn dtime_under_10ns() {
+        let mut wgfmu = wgfmu();
+        wgfmu.create_pattern("p", 0.0).unwrap();
+
+        assert_eq!(wgfmu.add_vector("p", 9e-9, 1.0), Err(Error::ParameterOutOfRangeError));
+        // Same as the production driver, a vector that takes no time lasts the shortest dtime
+        assert_eq!(wgfmu.add_vector("p", 0.0, 1.0), Ok(()));
+        assert!((wgfmu.patterns["p"].last_t - MIN_DTIME).abs() < TIME_EPSILON);
+    }
+
+    #[test]
+    fn voltage_out_of_range() {
+        let mut wgfmu = wgfmu();
+        wgfmu.create_pattern("p", 0.0).unwrap();
+
+        assert_eq!(wgfmu.add_vector("p", 1e-8, 10.1), Err(Error::ParameterOutOfRangeError));
+        assert_eq!(wgfmu.add_vector("p", 1e-8, -10.1), Err(Error::ParameterOutOfRangeError));
+        assert_eq!(wgfmu.create_pattern("q", 10.1), Err(Error::ParameterOutOfRangeError));
+        assert_eq!(wgfmu.add_vector("p", 1e-8, 10.0), Ok(()));
+    }
+
+    #[test]
+    fn too_many_measure_points() {
+        let mut wgfmu = wgfmu();
+        wgfmu.create_pattern("p", 0.0).unwrap();
+        wgfmu.add_vector("p", 0.03, 0.0).unwrap();
+        wgfmu
+            .set_measure_event("p", "m", 0.0, 2_000_000, 1e-8, 0.0, MeasureEventMode::MeasureEventDataAveraged)
+            .unwrap();
+        wgfmu.add_sequence(CHANNEL1, "p", 3).unwrap();
+
+        assert_eq!(wgfmu.execute(), Err(Error::ParameterOutOfRangeError));
+    }
+
+    #[test]
+    fn overlapping_events() {
+        let mut wgfmu = wgfmu();
+        wgfmu.create_pattern("p", 0.0).unwrap();
+        wgfmu.add_vector("p", 1e-3, 0.0).unwrap();
+        wgfmu
+            .set_measure_event("p", "a", 1e-4, 10, 1e-5, 0.0, MeasureEventMode::MeasureEventDataAveraged)
+            .unwrap();
+
+        // Starts before the end of the first one, then ends after the start of the first one
+        assert_eq!(
+            wgfmu.set_measure_event("p", "b", 1.5e-4, 10, 1e-5, 0.0, MeasureEventMode::MeasureEventDataAveraged),
+            Err(Error::ParameterOutOfRangeError)
+        );
+        assert_eq!(
+            wgfmu.set_measure_event("p", "c", 5e-5, 10, 1e-5, 0.0, MeasureEventMode::MeasureEventDataAveraged),
+            Err(Error::ParameterOutOfRangeError)
+        );
+        assert_eq!(
+            wgfmu.set_measure_event("p", "d", 3e-4, 10, 1e-5, 0.0, MeasureEventMode::MeasureEventDataAveraged),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn duplicate_pattern() {
+        let mut wgfmu = wgfmu();
+        wgfmu.create_pattern("p", 0.0).unwrap();
+
+        assert_eq!(wgfmu.create_pattern("p", 0.0), Err(Error::PatternAlreadyExistsError));
+    }
 }
 
 
@@ -0,0 +1,1052 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use super::driver::*;
+
+// WGFMU call traces. `RecordingWgfmu` wraps any driver and writes every call, with its arguments and result, to a
+// JSON lines file. `ReplayWgfmu` reads one of those files and serves the recorded results back, so a session captured
+// with the instrument can be reproduced without it.
+
+/// A driver call and its arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum Request {
+    OpenSession {
+        instrument: String,
+    },
+    CloseSession,
+    Clear,
+    GetChannelIds,
+    GetError,
+    GetErrorSummary,
+    GetWarningSummary,
+    SetWarningLevel {
+        level: WarningLevel,
+    },
+    TreatWarningsAsErrors {
+        level: WarningLevel,
+    },
+    CreatePattern {
+        pattern: String,
+        init_v: f64,
+    },
+    AddVector {
+        pattern: String,
+        d_time: f64,
+        voltage: f64,
+    },
+    AddVectors {
+        pattern: String,
+        d_time: Vec<f64>,
+        voltage: Vec<f64>,
+    },
+    SetMeasureEvent {
+        pattern: String,
+        event: String,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    },
+    SetRangeEvent {
+        pattern: String,
+        event: String,
+        time: f64,
+        range: MeasureCurrentRange,
+    },
+    AddSequence {
+        chan_id: usize,
+        pattern: String,
+        count: usize,
+    },
+    AddSequences {
+        chan_id: usize,
+        pattern: Vec<String>,
+        count: Vec<usize>,
+    },
+    SetVector {
+        pattern: String,
+        time: f64,
+        voltage: f64,
+    },
+    Initialize,
+    SetOperationMode {
+        chan_id: usize,
+        operation_mode: OperationMode,
+    },
+    SetMeasureMode {
+        chan_id: usize,
+        mode: MeasureMode,
+    },
+    GetMeasureMode {
+        chan_id: i32,
+    },
+    GetOperationMode {
+        chan_id: i32,
+    },
+    SetForceVoltageRange {
+        chan_id: usize,
+        range: ForceVoltageRange,
+    },
+    SetMeasureVoltageRange {
+        chan_id: usize,
+        range: MeasureVoltageRange,
+    },
+    SetMeasureCurrentRange {
+        chan_id: usize,
+        range: MeasureCurrentRange,
+    },
+    SetForceDelay {
+        chan_id: usize,
+        delay: f64,
+    },
+    SetMeasureDelay {
+        chan_id: usize,
+        delay: f64,
+    },
+    Connect {
+        chan_id: usize,
+    },
+    Execute,
+    WaitUntilCompleted,
+    Abort,
+    AbortChannel {
+        chan_id: usize,
+    },
+    GetChannelStatus {
+        chan_id: usize,
+    },
+    GetMeasureValues {
+        chan_id: usize,
+        current: usize,
+    },
+    GetCompletedMeasureEventSize {
+        chan_id: usize,
+    },
+    GetCompletedMeasureValues {
+        chan_id: usize,
+        offset: usize,
+    },
+    GetChannelData {
+        chan_id: usize,
+    },
+    DoSelfCalibration,
+    DcForceVoltage {
+        chan_id: usize,
+        voltage: f64,
+    },
+    DcMeasureValue {
+        chan_id: usize,
+    },
+    DcMeasureAveragedValue {
+        chan_id: usize,
+        points: usize,
+        interval: usize,
+    },
+}
+
+impl Request {
+    /// Performs the call on `wgfmu`, used to serve requests coming from another process.
+    pub fn call<D: WgfmuDriver + ?Sized>(self, wgfmu: &mut D) -> Result<Response, Error> {
+        match self {
+            Request::OpenSession { instrument } => wgfmu.open_session(instrument.as_str()),
+            Request::CloseSession => wgfmu.close_session(),
+            Request::Clear => wgfmu.clear(),
+            Request::GetChannelIds => return wgfmu.get_channel_ids().map(Response::ChannelIds),
+            Request::GetError => return wgfmu.get_error().map(Response::Text),
+            Request::GetErrorSummary => return wgfmu.get_error_summary().map(Response::Text),
+            Request::GetWarningSummary => return wgfmu.get_warning_summary().map(Response::Text),
+            Request::SetWarningLevel { level } => wgfmu.set_warning_level(level),
+            Request::TreatWarningsAsErrors { level } => wgfmu.treat_warnings_as_errors(level),
+            Request::CreatePattern { pattern, init_v } => wgfmu.create_pattern(pattern.as_str(), init_v),
+            Request::AddVector {
+                pattern,
+                d_time,
+                voltage,
+            } => wgfmu.add_vector(pattern.as_str(), d_time, voltage),
+            Request::AddVectors {
+                pattern,
+                d_time,
+                voltage,
+            } => wgfmu.add_vectors(pattern.as_str(), d_time, voltage),
+            Request::SetMeasureEvent {
+                pattern,
+                event,
+                time,
+                points,
+                interval,
+                average,
+                measure_event_mode,
+            } => wgfmu.set_measure_event(
+                pattern.as_str(),
+                event.as_str(),
+                time,
+                points,
+                interval,
+                average,
+                measure_event_mode,
+            ),
+            Request::SetRangeEvent {
+                pattern,
+                event,
+                time,
+                range,
+            } => wgfmu.set_range_event(pattern.as_str(), event.as_str(), time, range),
+            Request::AddSequence {
+                chan_id,
+                pattern,
+                count,
+            } => wgfmu.add_sequence(chan_id, pattern.as_str(), count),
+            Request::AddSequences {
+                chan_id,
+                pattern,
+                count,
+            } => wgfmu.add_sequences(chan_id, pattern.iter().map(|p| p.as_str()).collect(), count),
+            Request::SetVector {
+                pattern,
+                time,
+                voltage,
+            } => wgfmu.set_vector(pattern.as_str(), time, voltage),
+            Request::Initialize => wgfmu.initialize(),
+            Request::SetOperationMode {
+                chan_id,
+                operation_mode,
+            } => wgfmu.set_operation_mode(chan_id, operation_mode),
+            Request::SetMeasureMode { chan_id, mode } => wgfmu.set_measure_mode(chan_id, mode),
+            Request::GetMeasureMode { chan_id } => {
+                return wgfmu.get_measure_mode(chan_id).map(Response::MeasureMode)
+            }
+            Request::GetOperationMode { chan_id } => {
+                return wgfmu.get_operation_mode(chan_id).map(Response::OperationMode)
+            }
+            Request::SetForceVoltageRange { chan_id, range } => wgfmu.set_force_voltage_range(chan_id, range),
+            Request::SetMeasureVoltageRange { chan_id, range } => wgfmu.set_measure_voltage_range(chan_id, range),
+            Request::SetMeasureCurrentRange { chan_id, range } => wgfmu.set_measure_current_range(chan_id, range),
+            Request::SetForceDelay { chan_id, delay } => wgfmu.set_force_delay(chan_id, delay),
+            Request::SetMeasureDelay { chan_id, delay } => wgfmu.set_measure_delay(chan_id, delay),
+            Request::Connect { chan_id } => wgfmu.connect(chan_id),
+            Request::Execute => wgfmu.execute(),
+            Request::WaitUntilCompleted => wgfmu.wait_until_completed(),
+            Request::Abort => wgfmu.abort(),
+            Request::AbortChannel { chan_id } => wgfmu.abort_channel(chan_id),
+            Request::GetChannelStatus { chan_id } => {
+                return wgfmu.get_channel_status(chan_id).map(Response::ChannelStatus)
+            }
+            Request::GetMeasureValues { chan_id, current } => {
+                return wgfmu.get_measure_values(chan_id, current).map(Response::Measurements)
+            }
+            Request::GetCompletedMeasureEventSize { chan_id } => {
+                return wgfmu
+                    .get_completed_measure_event_size(chan_id)
+                    .map(|(completed, total)| Response::EventSize(completed, total))
+            }
+            Request::GetCompletedMeasureValues { chan_id, offset } => {
+                return wgfmu
+                    .get_completed_measure_values(chan_id, offset)
+                    .map(Response::ChannelData)
+            }
+            Request::GetChannelData { chan_id } => {
+                return wgfmu.get_channel_data(chan_id).map(Response::ChannelData)
+            }
+            Request::DoSelfCalibration => wgfmu.do_self_calibration(),
+            Request::DcForceVoltage { chan_id, voltage } => wgfmu.dc_force_voltage(chan_id, voltage),
+            Request::DcMeasureValue { chan_id } => return wgfmu.dc_measure_value(chan_id).map(Response::Value),
+            Request::DcMeasureAveragedValue {
+                chan_id,
+                points,
+                interval,
+            } => {
+                return wgfmu
+                    .dc_measure_averaged_value(chan_id, points, interval)
+                    .map(Response::Value)
+            }
+        }
+        .map(|_| Response::Done)
+    }
+}
+
+/// Value returned by a successful driver call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Done,
+    MeasureMode(MeasureMode),
+    OperationMode(OperationMode),
+    Measurements(Vec<Measurement>),
+    ChannelStatus(ChannelStatus),
+    EventSize(usize, usize),
+    ChannelData(ChannelData),
+    Text(String),
+    ChannelIds(Vec<usize>),
+    Value(f64),
+}
+
+/// A line of the trace file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub request: Request,
+    pub result: Result<Response, Error>,
+}
+
+pub struct RecordingWgfmu<D: WgfmuDriver> {
+    inner: D,
+    trace: File,
+}
+
+impl<D: WgfmuDriver> RecordingWgfmu<D> {
+    pub fn new(inner: D, path: &str) -> Result<RecordingWgfmu<D>, Box<dyn std::error::Error>> {
+        Ok(RecordingWgfmu {
+            inner,
+            trace: File::create(path)?,
+        })
+    }
+
+    /// Appends a call to the trace. A failure writing the trace is logged, it never fails the call itself.
+    fn record<T: Clone>(
+        &mut self,
+        request: Request,
+        result: &Result<T, Error>,
+        response: impl FnOnce(T) -> Response,
+    ) {
+        let entry = TraceEntry {
+            request,
+            result: result.clone().map(response),
+        };
+
+        let written = serde_json::to_string(&entry)
+            .map_err(|err| err.to_string())
+            .and_then(|line| writeln!(self.trace, "{}", line).map_err(|err| err.to_string()));
+
+        if let Err(err) = written {
+            error!("Could not write WGFMU trace entry: {}", err);
+        }
+    }
+}
+
+impl<D: WgfmuDriver> WgfmuDriver for RecordingWgfmu<D> {
+    fn open_session(&mut self, instrument: &str) -> Res {
+        let res = self.inner.open_session(instrument);
+        let request = Request::OpenSession {
+            instrument: instrument.to_string(),
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn close_session(&mut self) -> Res {
+        let res = self.inner.close_session();
+        self.record(Request::CloseSession, &res, |_| Response::Done);
+        res
+    }
+
+    fn clear(&mut self) -> Res {
+        let res = self.inner.clear();
+        self.record(Request::Clear, &res, |_| Response::Done);
+        res
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        let res = self.inner.get_channel_ids();
+        self.record(Request::GetChannelIds, &res, Response::ChannelIds);
+        res
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        let res = self.inner.get_error();
+        self.record(Request::GetError, &res, Response::Text);
+        res
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        let res = self.inner.get_error_summary();
+        self.record(Request::GetErrorSummary, &res, Response::Text);
+        res
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        let res = self.inner.get_warning_summary();
+        self.record(Request::GetWarningSummary, &res, Response::Text);
+        res
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        let res = self.inner.set_warning_level(level);
+        self.record(Request::SetWarningLevel { level }, &res, |_| Response::Done);
+        res
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        let res = self.inner.treat_warnings_as_errors(level);
+        self.record(Request::TreatWarningsAsErrors { level }, &res, |_| {
+            Response::Done
+        });
+        res
+    }
+
+    fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
+        let res = self.inner.create_pattern(pattern, init_v);
+        let request = Request::CreatePattern {
+            pattern: pattern.to_string(),
+            init_v,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        let res = self.inner.add_vector(pattern, d_time, voltage);
+        let request = Request::AddVector {
+            pattern: pattern.to_string(),
+            d_time,
+            voltage,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn add_vectors(&mut self, pattern: &str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res {
+        let res = self.inner.add_vectors(pattern, d_time.clone(), voltage.clone());
+        let request = Request::AddVectors {
+            pattern: pattern.to_string(),
+            d_time,
+            voltage,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_measure_event(
+        &mut self,
+        pattern: &str,
+        event: &str,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    ) -> Res {
+        let res = self.inner.set_measure_event(
+            pattern,
+            event,
+            time,
+            points,
+            interval,
+            average,
+            measure_event_mode,
+        );
+        let request = Request::SetMeasureEvent {
+            pattern: pattern.to_string(),
+            event: event.to_string(),
+            time,
+            points,
+            interval,
+            average,
+            measure_event_mode,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        let res = self.inner.set_range_event(pattern, event, time, range);
+        let request = Request::SetRangeEvent {
+            pattern: pattern.to_string(),
+            event: event.to_string(),
+            time,
+            range,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
+        let res = self.inner.add_sequence(chan_id, pattern, count);
+        let request = Request::AddSequence {
+            chan_id,
+            pattern: pattern.to_string(),
+            count,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res {
+        let request = Request::AddSequences {
+            chan_id,
+            pattern: pattern.iter().map(|p| p.to_string()).collect(),
+            count: count.clone(),
+        };
+        let res = self.inner.add_sequences(chan_id, pattern, count);
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
+        let res = self.inner.set_vector(pattern, time, voltage);
+        let request = Request::SetVector {
+            pattern: pattern.to_string(),
+            time,
+            voltage,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn initialize(&mut self) -> Res {
+        let res = self.inner.initialize();
+        self.record(Request::Initialize, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
+        let res = self.inner.set_operation_mode(chan_id, operation_mode);
+        let request = Request::SetOperationMode {
+            chan_id,
+            operation_mode,
+        };
+        self.record(request, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
+        let res = self.inner.set_measure_mode(chan_id, mode);
+        self.record(Request::SetMeasureMode { chan_id, mode }, &res, |_| {
+            Response::Done
+        });
+        res
+    }
+
+    fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
+        let res = self.inner.get_measure_mode(chan_id);
+        self.record(
+            Request::GetMeasureMode { chan_id },
+            &res,
+            Response::MeasureMode,
+        );
+        res
+    }
+
+    fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
+        let res = self.inner.get_operation_mode(chan_id);
+        self.record(
+            Request::GetOperationMode { chan_id },
+            &res,
+            Response::OperationMode,
+        );
+        res
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        let res = self.inner.set_force_voltage_range(chan_id, range);
+        self.record(Request::SetForceVoltageRange { chan_id, range }, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        let res = self.inner.set_measure_voltage_range(chan_id, range);
+        self.record(Request::SetMeasureVoltageRange { chan_id, range }, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        let res = self.inner.set_measure_current_range(chan_id, range);
+        self.record(Request::SetMeasureCurrentRange { chan_id, range }, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        let res = self.inner.set_force_delay(chan_id, delay);
+        self.record(Request::SetForceDelay { chan_id, delay }, &res, |_| Response::Done);
+        res
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        let res = self.inner.set_measure_delay(chan_id, delay);
+        self.record(Request::SetMeasureDelay { chan_id, delay }, &res, |_| Response::Done);
+        res
+    }
+
+    fn connect(&mut self, chan_id: usize) -> Res {
+        let res = self.inner.connect(chan_id);
+        self.record(Request::Connect { chan_id }, &res, |_| Response::Done);
+        res
+    }
+
+    fn execute(&mut self) -> Res {
+        let res = self.inner.execute();
+        self.record(Request::Execute, &res, |_| Response::Done);
+        res
+    }
+
+    fn wait_until_completed(&mut self) -> Res {
+        let res = self.inner.wait_until_completed();
+        self.record(Request::WaitUntilCompleted, &res, |_| Response::Done);
+        res
+    }
+
+    fn abort(&mut self) -> Res {
+        let res = self.inner.abort();
+        self.record(Request::Abort, &res, |_| Response::Done);
+        res
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        let res = self.inner.abort_channel(chan_id);
+        self.record(Request::AbortChannel { chan_id }, &res, |_| Response::Done);
+        res
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        let res = self.inner.get_channel_status(chan_id);
+        self.record(
+            Request::GetChannelStatus { chan_id },
+            &res,
+            Response::ChannelStatus,
+        );
+        res
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        let res = self.inner.get_measure_values(chan_id, current);
+        self.record(
+            Request::GetMeasureValues { chan_id, current },
+            &res,
+            Response::Measurements,
+        );
+        res
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        let res = self.inner.get_completed_measure_event_size(chan_id);
+        self.record(
+            Request::GetCompletedMeasureEventSize { chan_id },
+            &res,
+            |(completed, total)| Response::EventSize(completed, total),
+        );
+        res
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        let res = self.inner.get_completed_measure_values(chan_id, offset);
+        self.record(
+            Request::GetCompletedMeasureValues { chan_id, offset },
+            &res,
+            Response::ChannelData,
+        );
+        res
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        let res = self.inner.get_channel_data(chan_id);
+        self.record(Request::GetChannelData { chan_id }, &res, Response::ChannelData);
+        res
+    }
+
+    fn do_self_calibration(&mut self) -> Res {
+        let res = self.inner.do_self_calibration();
+        self.record(Request::DoSelfCalibration, &res, |_| Response::Done);
+        res
+    }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        let res = self.inner.dc_force_voltage(chan_id, voltage);
+        self.record(Request::DcForceVoltage { chan_id, voltage }, &res, |_| Response::Done);
+        res
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        let res = self.inner.dc_measure_value(chan_id);
+        self.record(Request::DcMeasureValue { chan_id }, &res, Response::Value);
+        res
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        let res = self.inner.dc_measure_averaged_value(chan_id, points, interval);
+        let request = Request::DcMeasureAveragedValue {
+            chan_id,
+            points,
+            interval,
+        };
+        self.record(request, &res, Response::Value);
+        res
+    }
+}
+
+pub struct ReplayWgfmu {
+    entries: VecDeque<TraceEntry>,
+}
+
+impl ReplayWgfmu {
+    pub fn new(path: &str) -> Result<ReplayWgfmu, Box<dyn std::error::Error>> {
+        let mut entries = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push_back(serde_json::from_str::<TraceEntry>(line.as_str())?);
+        }
+
+        Ok(ReplayWgfmu { entries })
+    }
+
+    /// Serves the next entry of the trace. The call has to be the recorded one, different arguments are only
+    /// reported, as they usually come from floating point rounding or a changed measurement parameter.
+    fn replay(&mut self, request: Request) -> Result<Response, Error> {
+        let entry = match self.entries.pop_front() {
+            Some(entry) => entry,
+            None => {
+                error!("WGFMU trace exhausted, unexpected call {:?}", request);
+                return Result::Err(Error::ReplayMismatch);
+            }
+        };
+
+        if std::mem::discriminant(&entry.request) != std::mem::discriminant(&request) {
+            error!(
+                "WGFMU trace mismatch, expected {:?} but got {:?}",
+                entry.request, request
+            );
+            return Result::Err(Error::ReplayMismatch);
+        }
+
+        if entry.request != request {
+            warn!(
+                "WGFMU trace arguments differ, recorded {:?} but got {:?}",
+                entry.request, request
+            );
+        }
+
+        entry.result
+    }
+
+    fn replay_done(&mut self, request: Request) -> Res {
+        match self.replay(request)? {
+            Response::Done => Ok(()),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn replay_text(&mut self, request: Request) -> Result<String, Error> {
+        match self.replay(request)? {
+            Response::Text(text) => Ok(text),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn replay_value(&mut self, request: Request) -> Result<f64, Error> {
+        match self.replay(request)? {
+            Response::Value(value) => Ok(value),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+}
+
+impl WgfmuDriver for ReplayWgfmu {
+    fn open_session(&mut self, instrument: &str) -> Res {
+        self.replay_done(Request::OpenSession {
+            instrument: instrument.to_string(),
+        })
+    }
+
+    fn close_session(&mut self) -> Res {
+        self.replay_done(Request::CloseSession)
+    }
+
+    fn clear(&mut self) -> Res {
+        self.replay_done(Request::Clear)
+    }
+
+    fn get_channel_ids(&mut self) -> Result<Vec<usize>, Error> {
+        match self.replay(Request::GetChannelIds)? {
+            Response::ChannelIds(ids) => Ok(ids),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn get_error(&mut self) -> Result<String, Error> {
+        self.replay_text(Request::GetError)
+    }
+
+    fn get_error_summary(&mut self) -> Result<String, Error> {
+        self.replay_text(Request::GetErrorSummary)
+    }
+
+    fn get_warning_summary(&mut self) -> Result<String, Error> {
+        self.replay_text(Request::GetWarningSummary)
+    }
+
+    fn set_warning_level(&mut self, level: WarningLevel) -> Res {
+        self.replay_done(Request::SetWarningLevel { level })
+    }
+
+    fn treat_warnings_as_errors(&mut self, level: WarningLevel) -> Res {
+        self.replay_done(Request::TreatWarningsAsErrors { level })
+    }
+
+    fn create_pattern(&mut self, pattern: &str, init_v: f64) -> Res {
+        self.replay_done(Request::CreatePattern {
+            pattern: pattern.to_string(),
+            init_v,
+        })
+    }
+
+    fn add_vector(&mut self, pattern: &str, d_time: f64, voltage: f64) -> Res {
+        self.replay_done(Request::AddVector {
+            pattern: pattern.to_string(),
+            d_time,
+            voltage,
+        })
+    }
+
+    fn add_vectors(&mut self, pattern: &str, d_time: Vec<f64>, voltage: Vec<f64>) -> Res {
+        self.replay_done(Request::AddVectors {
+            pattern: pattern.to_string(),
+            d_time,
+            voltage,
+        })
+    }
+
+    fn set_measure_event(
+        &mut self,
+        pattern: &str,
+        event: &str,
+        time: f64,
+        points: i32,
+        interval: f64,
+        average: f64,
+        measure_event_mode: MeasureEventMode,
+    ) -> Res {
+        self.replay_done(Request::SetMeasureEvent {
+            pattern: pattern.to_string(),
+            event: event.to_string(),
+            time,
+            points,
+            interval,
+            average,
+            measure_event_mode,
+        })
+    }
+
+    fn set_range_event(&mut self, pattern: &str, event: &str, time: f64, range: MeasureCurrentRange) -> Res {
+        self.replay_done(Request::SetRangeEvent {
+            pattern: pattern.to_string(),
+            event: event.to_string(),
+            time,
+            range,
+        })
+    }
+
+    fn add_sequence(&mut self, chan_id: usize, pattern: &str, count: usize) -> Res {
+        self.replay_done(Request::AddSequence {
+            chan_id,
+            pattern: pattern.to_string(),
+            count,
+        })
+    }
+
+    fn add_sequences(&mut self, chan_id: usize, pattern: Vec<&str>, count: Vec<usize>) -> Res {
+        self.replay_done(Request::AddSequences {
+            chan_id,
+            pattern: pattern.iter().map(|p| p.to_string()).collect(),
+            count,
+        })
+    }
+
+    fn set_vector(&mut self, pattern: &str, time: f64, voltage: f64) -> Res {
+        self.replay_done(Request::SetVector {
+            pattern: pattern.to_string(),
+            time,
+            voltage,
+        })
+    }
+
+    fn initialize(&mut self) -> Res {
+        self.replay_done(Request::Initialize)
+    }
+
+    fn set_operation_mode(&mut self, chan_id: usize, operation_mode: OperationMode) -> Res {
+        self.replay_done(Request::SetOperationMode {
+            chan_id,
+            operation_mode,
+        })
+    }
+
+    fn set_measure_mode(&mut self, chan_id: usize, mode: MeasureMode) -> Res {
+        self.replay_done(Request::SetMeasureMode { chan_id, mode })
+    }
+
+    fn get_measure_mode(&mut self, chan_id: i32) -> Result<MeasureMode, Error> {
+        match self.replay(Request::GetMeasureMode { chan_id })? {
+            Response::MeasureMode(mode) => Ok(mode),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn get_operation_mode(&mut self, chan_id: i32) -> Result<OperationMode, Error> {
+        match self.replay(Request::GetOperationMode { chan_id })? {
+            Response::OperationMode(mode) => Ok(mode),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn set_force_voltage_range(&mut self, chan_id: usize, range: ForceVoltageRange) -> Res {
+        self.replay_done(Request::SetForceVoltageRange { chan_id, range })
+    }
+
+    fn set_measure_voltage_range(&mut self, chan_id: usize, range: MeasureVoltageRange) -> Res {
+        self.replay_done(Request::SetMeasureVoltageRange { chan_id, range })
+    }
+
+    fn set_measure_current_range(&mut self, chan_id: usize, range: MeasureCurrentRange) -> Res {
+        self.replay_done(Request::SetMeasureCurrentRange { chan_id, range })
+    }
+
+    fn set_force_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.replay_done(Request::SetForceDelay { chan_id, delay })
+    }
+
+    fn set_measure_delay(&mut self, chan_id: usize, delay: f64) -> Res {
+        self.replay_done(Request::SetMeasureDelay { chan_id, delay })
+    }
+
+    fn connect(&mut self, chan_id: usize) -> Res {
+        self.replay_done(Request::Connect { chan_id })
+    }
+
+    fn execute(&mut self) -> Res {
+        self.replay_done(Request::Execute)
+    }
+
+    fn wait_until_completed(&mut self) -> Res {
+        self.replay_done(Request::WaitUntilCompleted)
+    }
+
+    fn abort(&mut self) -> Res {
+        self.replay_done(Request::Abort)
+    }
+
+    fn abort_channel(&mut self, chan_id: usize) -> Res {
+        self.replay_done(Request::AbortChannel { chan_id })
+    }
+
+    fn get_channel_status(&mut self, chan_id: usize) -> Result<ChannelStatus, Error> {
+        match self.replay(Request::GetChannelStatus { chan_id })? {
+            Response::ChannelStatus(status) => Ok(status),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn get_measure_values(&mut self, chan_id: usize, current: usize) -> Result<Vec<Measurement>, Error> {
+        match self.replay(Request::GetMeasureValues { chan_id, current })? {
+            Response::Measurements(measurements) => Ok(measurements),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn get_completed_measure_event_size(&mut self, chan_id: usize) -> Result<(usize, usize), Error> {
+        match self.replay(Request::GetCompletedMeasureEventSize { chan_id })? {
+            Response::EventSize(completed, total) => Ok((completed, total)),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn get_completed_measure_values(&mut self, chan_id: usize, offset: usize) -> Result<ChannelData, Error> {
+        match self.replay(Request::GetCompletedMeasureValues { chan_id, offset })? {
+            Response::ChannelData(data) => Ok(data),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn get_channel_data(&mut self, chan_id: usize) -> Result<ChannelData, Error> {
+        match self.replay(Request::GetChannelData { chan_id })? {
+            Response::ChannelData(data) => Ok(data),
+            _ => Result::Err(Error::ReplayMismatch),
+        }
+    }
+
+    fn do_self_calibration(&mut self) -> Res {
+        self.replay_done(Request::DoSelfCalibration)
+    }
+
+    fn dc_force_voltage(&mut self, chan_id: usize, voltage: f64) -> Res {
+        self.replay_done(Request::DcForceVoltage { chan_id, voltage })
+    }
+
+    fn dc_measure_value(&mut self, chan_id: usize) -> Result<f64, Error> {
+        self.replay_value(Request::DcMeasureValue { chan_id })
+    }
+
+    fn dc_measure_averaged_value(&mut self, chan_id: usize, points: usize, interval: usize) -> Result<f64, Error> {
+        self.replay_value(Request::DcMeasureAveragedValue {
+            chan_id,
+            points,
+            interval,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b1500::measure::epsc::{measure_epsc_fastiv, Epsc};
+    use crate::b1500::wgfmu::memristor::MemristorParams;
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+
+    /// A trace file of the temporary directory, unique to the test
+    fn trace_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("wgfmu_trace_{}_{}.jsonl", name, std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn epsc() -> Epsc {
+        Epsc {
+            amplitude: 1.0,
+            width: 1e-6,
+            v_read: 0.1,
+            baseline: 1e-6,
+            read_time: 1e-5,
+            n_points: 20,
+            avg_time: 1e-8,
+        }
+    }
+
+    #[test]
+    fn replays_a_recorded_measurement() {
+        let path = trace_path("round_trip");
+
+        let mut recording = RecordingWgfmu::new(TestWgfmu::new(MemristorParams::default()).unwrap(), &path).unwrap();
+        let recorded =
+            measure_epsc_fastiv(&mut recording, Some("x"), Default::default(), Default::default(), epsc()).unwrap();
+        drop(recording);
+
+        let mut replay = ReplayWgfmu::new(&path).unwrap();
+        let replayed = measure_epsc_fastiv(&mut replay, Some("x"), Default::default(), Default::default(), epsc());
+        std::fs::remove_file(&path).unwrap();
+
+        let replayed = replayed.unwrap();
+        assert!(!recorded.iv.is_empty());
+        assert_eq!(replayed.iv, recorded.iv);
+        assert_eq!(replayed.parameters, recorded.parameters);
+        assert!(replay.entries.is_empty());
+    }
+
+    #[test]
+    fn other_call_order_mismatches() {
+        let path = trace_path("mismatch");
+
+        let mut recording = RecordingWgfmu::new(TestWgfmu::new(MemristorParams::default()).unwrap(), &path).unwrap();
+        recording.create_pattern("p", 0.0).unwrap();
+        recording.add_vector("p", 1e-6, 1.0).unwrap();
+        drop(recording);
+
+        let mut replay = ReplayWgfmu::new(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(replay.add_vector("p", 1e-6, 1.0), Err(Error::ReplayMismatch));
+    }
+}
@@ -106,7 +106,7 @@ pub type SetRangeEvent = extern "C" fn(
     pattern: *const c_char,
     event: *const c_char,
     time: c_double,
-    time: c_int,
+    range: c_int,
 ) -> c_int;
 pub type SetTriggerOutEvent =
     extern "C" fn(pattern: *const c_char, event: *const c_char, time: c_double, duration: c_double);
@@ -236,7 +236,7 @@ pub type GetMeasureValue =
 
 // WGFMU Data retrieve - Event
 pub type GetCompletedMeasureEventSize =
-    extern "C" fn(chan_id: c_int, complete: *mut c_int, total: *mut c_int);
+    extern "C" fn(chan_id: c_int, complete: *mut c_int, total: *mut c_int) -> c_int;
 pub type IsMeasureEventCompleted = extern "C" fn(
     chan_id: c_int,
     pattern: *const c_char,
@@ -2,10 +2,70 @@ use std::default::Default;
 
 use serde::{Deserialize, Serialize};
 
+use crate::b1500::types::Channels;
+use crate::b1500::wgfmu::{driver::WarningLevel, fault::FaultRule, memristor::MemristorParams};
+
+/// WGFMU backend used by the application
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DriverKind {
+    /// The B1530A through the Keysight WGFMU library (wgfmu.dll)
+    Production,
+    /// The simulated WGFMU, with the memristor model in `memristor`
+    Simulator,
+    /// Serves the calls recorded in `driver.trace`
+    Replay,
+    /// A WGFMU served by another Xavier instance at `driver.remote`
+    Remote,
+}
+
+impl Default for DriverKind {
+    fn default() -> Self {
+        if cfg!(target_os = "windows") {
+            DriverKind::Production
+        } else {
+            DriverKind::Simulator
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct DriverConfig {
+    pub kind: DriverKind,
+    /// Trace file replayed by the replay driver
+    pub trace: Option<String>,
+    /// When set, every call to the driver is recorded to this trace file
+    pub record: Option<String>,
+    /// Address of the WGFMU server used by the remote driver (host:port)
+    pub remote: Option<String>,
+    /// When set, the driver is also served to remote clients at this address (host:port). The protocol is plain TCP
+    /// and gives full control of the instrument, addresses other than loopback are only served with a `token`.
+    pub serve: Option<String>,
+    /// Shared secret remote clients send before any call, used both when serving and by the remote driver
+    pub token: Option<String>,
+    /// Warnings reported by the WGFMU library, its own default when not set
+    pub warning_level: Option<WarningLevel>,
+    /// Warnings at this level or more severe fail the call that raised them
+    pub warnings_as_errors: Option<WarningLevel>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub database_url: String,
     pub port: usize,
+    /// WGFMU backend selection
+    #[serde(default)]
+    pub driver: DriverConfig,
+    /// Device model used by the WGFMU simulator
+    #[serde(default)]
+    pub memristor: MemristorParams,
+    /// Faults injected in the WGFMU calls
+    #[serde(default)]
+    pub faults: Vec<FaultRule>,
+    /// Channels wired to the device under test, used by the measurements that do not pick their own
+    #[serde(default)]
+    pub channels: Channels,
 }
 
 impl Default for Config {
@@ -13,6 +73,47 @@ impl Default for Config {
         Config {
             database_url: "sqlite:./xavier.db?mode=rwc".to_string(),
             port: 8000,
+            driver: DriverConfig::default(),
+            memristor: MemristorParams::default(),
+            faults: vec![],
+            channels: Channels::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Overrides the driver settings with the command line ones:
+    ///
+    /// `--driver <production|simulator|replay|remote>`, `--trace <file>`, `--record <file>`, `--remote <host:port>`,
+    /// `--serve <host:port>` and `--token <secret>`.
+    pub fn apply_args(&mut self, args: impl Iterator<Item = String>) -> Result<(), String> {
+        let mut args = args;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for argument {}", arg))
+            };
+
+            match arg.as_str() {
+                "--driver" => {
+                    self.driver.kind = match value()?.as_str() {
+                        "production" => DriverKind::Production,
+                        "simulator" => DriverKind::Simulator,
+                        "replay" => DriverKind::Replay,
+                        "remote" => DriverKind::Remote,
+                        kind => return Err(format!("Unknown WGFMU driver {}", kind)),
+                    }
+                }
+                "--trace" => self.driver.trace = Some(value()?),
+                "--record" => self.driver.record = Some(value()?),
+                "--remote" => self.driver.remote = Some(value()?),
+                "--serve" => self.driver.serve = Some(value()?),
+                "--token" => self.driver.token = Some(value()?),
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
         }
+
+        Ok(())
     }
 }
@@ -13,28 +13,33 @@ use actix_web::{dev::fn_service, middleware::Logger, web, App, HttpServer};
 #[cfg(debug_assertions)]
 use dotenv::dotenv;
 
+use b1500::instrument::InstrumentManager;
 use config::Config;
+use log::error;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct AppState {
-    pub measuring: bool,
     pub db: db::Database,
     pub cfg: config::Config,
+    pub instrument: Arc<InstrumentManager>,
 }
 
 #[actix_web::main]
-async fn web_server(cfg: Config) -> std::io::Result<()> {
+async fn web_server(cfg: Config, instrument: Arc<InstrumentManager>) -> std::io::Result<()> {
     #[cfg(debug_assertions)]
     dotenv().ok();
 
     let db = db::Database::new().await;
 
     let state = AppState {
-        measuring: false,
         db,
         cfg,
+        instrument,
     };
 
+    actix_web::rt::spawn(www::measurements::queue::worker(state.clone()));
+
     std::env::set_var("RUST_LOG", "actix_web=debug");
     std::env::set_var("RUST_LOG", "debug");
 
@@ -62,7 +67,30 @@ async fn web_server(cfg: Config) -> std::io::Result<()> {
 }
 
 fn main() {
-    let cfg: config::Config = confy::load("xavier", None).expect("Could not load config. SAD!");
+    let mut cfg: config::Config = confy::load("xavier", None).expect("Could not load config. SAD!");
+    if let Err(err) = cfg.apply_args(std::env::args().skip(1)) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+
+    let instrument = match b1500::init_wgfmu(&cfg) {
+        Ok(driver) => InstrumentManager::new(driver),
+        Err(err) => {
+            eprintln!("Could not initialize the {:?} WGFMU driver: {}", cfg.driver.kind, err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(address) = cfg.driver.serve.clone() {
+        let instrument = Arc::clone(&instrument);
+        let acquire = move |peer: &str| instrument.lease(format!("remote client {}", peer).as_str());
+        let token = cfg.driver.token.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = b1500::wgfmu::remote::serve(acquire, address.as_str(), token) {
+                error!("WGFMU server error: {}", err);
+            }
+        });
+    }
 
     std::env::set_var("RUST_LOG", "actix_web=debug");
     std::env::set_var("RUST_LOG", "debug");
@@ -71,7 +99,7 @@ fn main() {
     let join_handle = std::thread::spawn({
         let cfg = cfg.clone();
         move || {
-            web_server(cfg).unwrap();
+            web_server(cfg, instrument).unwrap();
         }
     });
 
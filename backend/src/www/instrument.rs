@@ -0,0 +1,75 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use serde_json::json;
+
+use crate::b1500;
+use crate::b1500::wgfmu::WgfmuDriver;
+use crate::www::utils;
+use crate::AppState;
+
+use super::measurements::types::ErrorJson;
+
+pub async fn status(app: web::Data<AppState>) -> impl Responder {
+    match app.instrument.status() {
+        Ok(status) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&status).unwrap()),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::json())
+            .body(
+                (ErrorJson {
+                    error: format!("Could not read the instrument status {:?}.", err),
+                })
+                .to_string(),
+            ),
+    }
+}
+
+/// Lists the WGFMU channels installed in the instrument, along with the ones measurements use by default
+pub async fn channels(app: web::Data<AppState>) -> impl Responder {
+    let mut wgfmu = match utils::lease_instrument(&app, "channel discovery") {
+        Ok(lease) => lease,
+        Err(err) => return err.response(),
+    };
+
+    let blocking = web::block(move || {
+        let result = b1500::utils::discover_channels(&mut wgfmu, Some("b1500gpib"));
+        let reported = if result.is_err() { wgfmu.reported() } else { String::new() };
+        (result, reported)
+    });
+    let (result, reported) = match blocking.await {
+        Ok(res) => res,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .content_type(ContentType::json())
+                .body(
+                    (ErrorJson {
+                        error: format!("Actix blocking error {}.", err),
+                    })
+                    .to_string(),
+                )
+        }
+    };
+
+    match result {
+        Ok(installed) => {
+            let res = json! {
+                {
+                    "installed": installed,
+                    "default": app.cfg.channels,
+                }
+            };
+
+            HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .body(res.to_string())
+        }
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::json())
+            .body(
+                (ErrorJson {
+                    error: utils::with_reported(format!("WGFMU channel discovery error {:?}.", err), &reported),
+                })
+                .to_string(),
+            ),
+    }
+}
@@ -1,4 +1,6 @@
 use crate::b1500;
+use crate::b1500::wgfmu::WgfmuDriver;
+use crate::www::utils;
 use crate::AppState;
 use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse, Responder};
@@ -8,20 +10,20 @@ use serde_json::json;
 use super::types::ErrorJson;
 
 pub async fn calibrate(app: web::Data<AppState>) -> impl Responder {
-    if app.measuring {
-        return HttpResponse::Conflict()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Another measurement is already in progress.".to_string(),
-                })
-                .to_string(),
-            );
-    }
+    let mut wgfmu = match utils::lease_instrument(&app, "calibration") {
+        Ok(lease) => lease,
+        Err(err) => return err.response(),
+    };
 
     info!("Calibrating!");
 
-    let result = match web::block(move || b1500::utils::calibrate(Some("b1500gpib"))).await {
+    let channels = app.cfg.channels;
+    let blocking = web::block(move || {
+        let result = b1500::utils::calibrate(&mut wgfmu, Some("b1500gpib"), channels);
+        let reported = if result.is_err() { wgfmu.reported() } else { String::new() };
+        (result, reported)
+    });
+    let (result, reported) = match blocking.await {
         Ok(res) => res,
         Err(err) => {
             return HttpResponse::InternalServerError()
@@ -51,7 +53,7 @@ pub async fn calibrate(app: web::Data<AppState>) -> impl Responder {
             .content_type(ContentType::json())
             .body(
                 (ErrorJson {
-                    error: format!("WGFMU calibration error {}.", err),
+                    error: utils::with_reported(format!("WGFMU calibration error {}.", err), &reported),
                 })
                 .to_string(),
             ),
@@ -0,0 +1,55 @@
+use crate::b1500::measure::{
+    self,
+    endurance::{measure_endurance_fastiv, Endurance, EnduranceMeasurement},
+};
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::WgfmuDriver;
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::Submission;
+use entity::measurement::Category;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EnduranceMeasurementParams {
+    #[serde(flatten)]
+    endurance: Endurance,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl EnduranceMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<EnduranceMeasurement, measure::Error> {
+        measure_endurance_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.endurance.clone(),
+        )
+    }
+}
+
+impl Submission for EnduranceMeasurementParams {
+    const CATEGORY: Category = Category::Endurance;
+
+    fn invalid(&self) -> Option<String> {
+        if self.endurance.is_valid() {
+            return None;
+        }
+        Some(
+            "The test needs a positive vSet, a negative vReset, amplitudes within 5 V, pulses of at least 10 ns and \
+             at least one cycle."
+                .to_string(),
+        )
+    }
+}
@@ -0,0 +1,55 @@
+use crate::b1500::measure::{
+    self,
+    epsc::{measure_epsc_fastiv, Epsc, EpscMeasurement},
+};
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::WgfmuDriver;
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::Submission;
+use entity::measurement::Category;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EpscMeasurementParams {
+    #[serde(flatten)]
+    epsc: Epsc,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl EpscMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<EpscMeasurement, measure::Error> {
+        measure_epsc_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.epsc.clone(),
+        )
+    }
+}
+
+impl Submission for EpscMeasurementParams {
+    const CATEGORY: Category = Category::Epsc;
+
+    fn invalid(&self) -> Option<String> {
+        if self.epsc.is_valid() {
+            return None;
+        }
+        Some(
+            "The measurement needs a spike and a read bias within 10 V, a spike of at least 10 ns and at least 2 \
+             points 10 ns or more apart."
+                .to_string(),
+        )
+    }
+}
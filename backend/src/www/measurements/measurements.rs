@@ -4,18 +4,25 @@ use std::io::Write;
 use crate::AppState;
 use actix_files::NamedFile;
 use actix_web::http::header::ContentType;
-use actix_web::{web, Error, HttpResponse};
+use actix_web::{web, Either, Error, HttpResponse};
 
 use chrono::SecondsFormat;
-use entity::{self, measurement::Category};
+use entity::{self, measurement::Category, measurement::Status};
+use log::info;
 use sea_orm::{prelude::DateTimeLocal, EntityTrait, QuerySelect};
 use sea_orm::{FromQueryResult, JsonValue};
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json;
 
 // use super::types::ErrorJson;
+use crate::b1500::instrument::Progress;
+use crate::b1500::measure::endurance::EnduranceCycle;
+use crate::b1500::measure::ppf::PpfPoint;
+use crate::b1500::measure::retention::RetentionRead;
 use crate::b1500::wgfmu::driver::Measurement;
 
+use super::queue::error_response;
+
 // use std::time::Instant;
 
 #[derive(FromQueryResult, Serialize)]
@@ -47,6 +54,15 @@ pub async fn list(app: web::Data<AppState>) -> Result<HttpResponse, Error> {
         .body(list_str))
 }
 
+#[derive(Serialize)]
+pub struct SingleMeasurement {
+    #[serde(flatten)]
+    pub measurement: entity::measurement::Model,
+    /// Only there while the measurement is running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Progress>,
+}
+
 pub async fn get_single(
     app: web::Data<AppState>,
     id: web::Path<i32>,
@@ -57,86 +73,161 @@ pub async fn get_single(
         .expect("Could not find measurement")
         .unwrap();
 
-    let measurement_str = serde_json::to_string(&measurement).unwrap();
+    let progress = app.instrument.progress(measurement.id).unwrap_or_default();
+    let measurement_str = serde_json::to_string(&SingleMeasurement {
+        measurement,
+        progress,
+    })
+    .unwrap();
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::json())
         .body(measurement_str))
 }
 
+/// Writes `rows` to a CSV file named after `prefix` and the measurement, returns the name of the file
+fn write_csv(
+    prefix: &str,
+    measurement: &entity::measurement::Model,
+    rows: impl IntoIterator<Item = String>,
+) -> std::io::Result<String> {
+    let file_name = prefix.to_string()
+        + i32::to_string(&measurement.id).as_str()
+        + "__"
+        + measurement
+            .date
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+            .replace(":", "_")
+            .as_str()
+        + ".csv";
+
+    info!("File name: {}", file_name);
+
+    let mut f = File::create(&file_name)?;
+    for row in rows {
+        writeln!(f, "{}", row)?;
+    }
+
+    Ok(file_name)
+}
+
+/// The `key` field of `data`, or `data` itself when there is no key
+fn field<T: DeserializeOwned>(data: &JsonValue, key: Option<&str>) -> Option<T> {
+    let value = match key {
+        Some(key) => data.get(key)?,
+        None => data,
+    };
+    serde_json::from_value(value.clone()).ok()
+}
+
+fn measurement_rows(data: Vec<Measurement>) -> Vec<String> {
+    data.into_iter()
+        .map(|point| match point.current {
+            Some(current) => format!("{},{},{}", point.voltage, current, point.time),
+            None => format!("{} {}", point.voltage, point.time),
+        })
+        .collect()
+}
+
+/// The file prefix and the CSV rows of the data of a measurement, None when the data does not have the shape its
+/// category stores
+fn csv_rows(category: &Category, data: &JsonValue) -> Option<(&'static str, Vec<String>)> {
+    match category {
+        Category::Pulse | Category::PulseCollection => Some(("Train_", measurement_rows(field(data, None)?))),
+        Category::IvSweep => Some(("IvSweep_", measurement_rows(field(data, None)?))),
+        Category::Stdp | Category::PulsedIv | Category::Epsc => {
+            let prefix = match category {
+                Category::PulsedIv => "PulsedIv_",
+                Category::Epsc => "Epsc_",
+                _ => "Train_",
+            };
+            // An aborted measurement holds the bare points measured until then
+            let data = field(data, Some("iv")).or_else(|| field(data, None))?;
+            Some((prefix, measurement_rows(data)))
+        }
+        Category::Endurance => {
+            // Resistances that could not be read are left empty
+            let cell = |resistance: Option<f64>| resistance.map(|r| r.to_string()).unwrap_or_default();
+            let cycles: Vec<EnduranceCycle> = field(data, Some("cycles"))?;
+            let rows = cycles
+                .into_iter()
+                .map(|cycle| format!("{},{},{}", cycle.cycle, cell(cycle.hrs), cell(cycle.lrs)))
+                .collect();
+            Some(("Endurance_", rows))
+        }
+        Category::Retention => {
+            let reads: Vec<RetentionRead> = field(data, Some("reads"))?;
+            let rows = reads
+                .into_iter()
+                .map(|read| format!("{},{}", read.time, read.conductance))
+                .collect();
+            Some(("Retention_", rows))
+        }
+        Category::Ppf => {
+            // The index is left empty when the first pulse drew no current
+            let curve: Vec<PpfPoint> = field(data, Some("curve"))?;
+            let rows = curve
+                .into_iter()
+                .map(|point| {
+                    let index = point.index.map(|index| index.to_string()).unwrap_or_default();
+                    format!("{},{},{},{}", point.interval, point.a1, point.a2, index)
+                })
+                .collect();
+            Some(("Ppf_", rows))
+        }
+        Category::StdpCollection => None,
+    }
+}
+
 pub async fn get_single_file(
     app: web::Data<AppState>,
     id: web::Path<i32>,
-) -> actix_web::Result<NamedFile> {
-    let measurement = entity::measurement::Entity::find_by_id(id.into_inner())
+) -> actix_web::Result<Either<NamedFile, HttpResponse>> {
+    let id = id.into_inner();
+    let measurement = match entity::measurement::Entity::find_by_id(id)
         .one(app.db.get_connection())
         .await
-        .expect("Could not find measurement")
-        .unwrap();
+    {
+        Ok(Some(measurement)) => measurement,
+        Ok(None) => {
+            return Ok(Either::Right(error_response(
+                HttpResponse::NotFound(),
+                format!("Measurement {} not found", id),
+            )))
+        }
+        Err(err) => {
+            return Ok(Either::Right(error_response(
+                HttpResponse::InternalServerError(),
+                format!("Database error {}", err),
+            )))
+        }
+    };
 
-    match measurement.category {
-        Category::Pulse => {
-            let data = measurement.data.unwrap().to_string();
-            let data = serde_json::from_str::<Vec<Measurement>>(data.as_str()).unwrap();
-
-            let file_name = "Train_".to_string()
-                + i32::to_string(&measurement.id).as_str()
-                + "__"
-                + measurement
-                    .date
-                    .to_rfc3339_opts(SecondsFormat::Secs, true)
-                    .replace(":", "_")
-                    .as_str()
-                + ".csv";
-
-            println!("File name: {}", file_name);
-
-            let mut f = File::create(file_name.to_owned()).expect("Could not open file");
-
-            for point in data {
-                match point.current {
-                    Some(current) => {
-                        writeln!(f, "{},{},{}", point.voltage, current, point.time)?;
-                    }
-                    None => {
-                        writeln!(f, "{} {}", point.voltage, point.time)?;
-                    }
-                }
-            }
-
-            Ok(NamedFile::open(file_name)?)
+    if measurement.status != Status::Done && measurement.status != Status::Aborted {
+        return Ok(Either::Right(error_response(
+            HttpResponse::Conflict(),
+            format!("Measurement {} is {:?}, it has no data to download", id, measurement.status),
+        )));
+    }
+
+    let data = match measurement.data.as_ref() {
+        Some(data) => data,
+        None => {
+            return Ok(Either::Right(error_response(
+                HttpResponse::NotFound(),
+                format!("Measurement {} has no data", id),
+            )))
         }
-        Category::Stdp => {
-            let data = measurement.data.unwrap().get("iv").unwrap().to_string();
-            let data = serde_json::from_str::<Vec<Measurement>>(data.as_str()).unwrap();
-
-            let file_name = "Train_".to_string()
-                + i32::to_string(&measurement.id).as_str()
-                + "__"
-                + measurement
-                    .date
-                    .to_rfc3339_opts(SecondsFormat::Secs, true)
-                    .replace(":", "_")
-                    .as_str()
-                + ".csv";
-
-            println!("File name: {}", file_name);
-
-            let mut f = File::create(file_name.to_owned()).expect("Could not open file");
-
-            for point in data {
-                match point.current {
-                    Some(current) => {
-                        writeln!(f, "{},{},{}", point.voltage, current, point.time)?;
-                    }
-                    None => {
-                        writeln!(f, "{} {}", point.voltage, point.time)?;
-                    }
-                }
-            }
-
-            Ok(NamedFile::open(file_name)?)
+    };
+
+    match csv_rows(&measurement.category, data) {
+        Some((prefix, rows)) => {
+            let file_name = write_csv(prefix, &measurement, rows)?;
+            Ok(Either::Left(NamedFile::open(file_name)?))
         }
-        _ => panic!(),
+        None => Ok(Either::Right(error_response(
+            HttpResponse::Conflict(),
+            format!("The data of measurement {} can not be downloaded as a file", id),
+        ))),
     }
 }
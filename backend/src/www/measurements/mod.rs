@@ -1,7 +1,15 @@
 pub mod calibrate;
+pub mod endurance;
+pub mod epsc;
 pub mod measurements;
+pub mod ppf;
 pub mod pulse;
+pub mod pulsed_iv;
+pub mod queue;
+pub mod retention;
 pub mod stdp;
+pub mod stream;
+pub mod sweep;
 pub mod types;
 pub mod urls;
 
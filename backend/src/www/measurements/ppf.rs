@@ -0,0 +1,55 @@
+use crate::b1500::measure::{
+    self,
+    ppf::{measure_ppf_fastiv, Ppf, PpfMeasurement},
+};
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::WgfmuDriver;
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::Submission;
+use entity::measurement::Category;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PpfMeasurementParams {
+    #[serde(flatten)]
+    ppf: Ppf,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl PpfMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<PpfMeasurement, measure::Error> {
+        measure_ppf_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.ppf.clone(),
+        )
+    }
+}
+
+impl Submission for PpfMeasurementParams {
+    const CATEGORY: Category = Category::Ppf;
+
+    fn invalid(&self) -> Option<String> {
+        if self.ppf.is_valid() {
+            return None;
+        }
+        Some(
+            "The measurement needs a nonzero amplitude within 10 V, at least one interval of 10 ns or more and \
+             pulses of at least 10 ns per point."
+                .to_string(),
+        )
+    }
+}
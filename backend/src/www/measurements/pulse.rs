@@ -1,25 +1,16 @@
-use log::error;
-use std::str::FromStr;
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::thread;
-
 use crate::b1500::measure::pulsed::{
     measure_pulse_collection_fastiv, measure_pulse_fastiv, PulseTrain, PulseTrainCollection,
 };
-use crate::b1500::{measure, wgfmu::driver::Measurement};
+use crate::b1500::{measure, types::{Channels, CurrentRanges, Ranges}, wgfmu::driver::Measurement, wgfmu::WgfmuDriver};
 use crate::AppState;
 use actix_web::body::BoxBody;
 use actix_web::http::header::ContentType;
-use actix_web::rt::spawn;
 use actix_web::{web, HttpResponse, Responder};
 
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-
-use entity::sea_orm::{ActiveModelTrait, Set};
 
-use super::types::{ErrorJson, MeasurementRef};
-use crate::www::utils;
+use super::queue;
+use super::types::ErrorJson;
 use entity::measurement;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -35,6 +26,15 @@ pub struct PulseMeasurementParams {
     n_points_low: usize,
     noise: bool,
     noise_std: f64,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+    /// Current ranges to switch between during the pulses and the low phases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current_ranges: Option<CurrentRanges>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,6 +46,62 @@ pub struct PulseCollectionMeasurementParams {
     n_points_low: usize,
     noise: bool,
     noise_std: f64,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl PulseMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<Vec<Measurement>, measure::Error> {
+        measure_pulse_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            PulseTrain {
+                n_pulses: self.n_pulses,
+                duty_cycle: self.duty_cycle,
+                cycle_time: self.cycle_time,
+                v_high: self.v_high,
+                v_low: self.v_low,
+                delay: 0.0,
+                current_ranges: self.current_ranges,
+            },
+            self.n_points_high,
+            self.n_points_low,
+            self.avg_time,
+            self.noise,
+            self.noise_std,
+        )
+    }
+}
+
+impl PulseCollectionMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<Vec<Measurement>, measure::Error> {
+        measure_pulse_collection_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.pulse_train_collection.clone(),
+            self.n_points_high,
+            self.n_points_low,
+            self.avg_time,
+            self.noise,
+            self.noise_std
+        )
+    }
 }
 
 impl Responder for PulseMeasurementParams {
@@ -64,119 +120,13 @@ pub async fn pulse_measurement(
     app: web::Data<AppState>,
     params: web::Json<PulseMeasurementParams>,
 ) -> impl Responder {
-    // let res_body = serde_json::to_string(&params).unwrap();
-
-    let guard = utils::measuring_guard(&app);
-    if guard.is_err() {
-        return guard.unwrap_err();
-    }
-
-    let params_str = serde_json::to_string(&params).unwrap();
-
-    let measurement = measurement::ActiveModel {
-        status: Set(measurement::Status::InProgress),
-        date: Set(chrono::Local::now()),
-        parameters: Set(Some(Value::from_str(params_str.as_str()).unwrap())),
-        category: Set(measurement::Category::Pulse),
-        ..Default::default()
-    };
-
-    let measurement = measurement.insert(app.db.get_connection()).await;
-    if measurement.is_err() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Could not insert measurement in database.".to_string(),
-                })
-                .to_string(),
-            );
-    }
-
-    let id = measurement.unwrap().id as usize;
-
-    let (tx, rx): (
-        Sender<Result<Vec<Measurement>, measure::Error>>,
-        Receiver<Result<Vec<Measurement>, measure::Error>>,
-    ) = mpsc::channel();
-
-    thread::spawn(move || {
-        let result = measure_pulse_fastiv(
-            Some("b1500gpib"),
-            PulseTrain {
-                n_pulses: params.n_pulses,
-                duty_cycle: params.duty_cycle,
-                cycle_time: params.cycle_time,
-                v_high: params.v_high,
-                v_low: params.v_low,
-                delay: 0.0
-            },
-            params.n_points_high,
-            params.n_points_low,
-            params.avg_time,
-            params.noise,
-            params.noise_std,
-        );
-
-        tx.send(result).unwrap();
-    });
-
-    spawn(async move {
-        let result = rx.recv().unwrap();
-
-        let measurement = measurement::Entity::find_by_id(id as i32)
-            .one(app.db.get_connection())
-            .await;
-        if measurement.is_err() {
-            return;
-        }
-        let measurement = measurement.unwrap();
-        if measurement.is_none() {
-            return;
-        }
-
-        let mut measurement: measurement::ActiveModel = measurement.unwrap().into();
-
-        match result {
-            Ok(data) => {
-                measurement.status = Set(measurement::Status::Done);
-
-                let data_str = serde_json::to_string(&data).unwrap();
-
-                measurement.data = Set(Some(Value::from_str(data_str.as_str()).unwrap()));
-
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-            Err(err) => {
-                error!("--------------------");
-                error!("--------------------");
-                error!("{:?}", err);
-                error!("--------------------");
-                error!("--------------------");
-
-                measurement.status = Set(measurement::Status::Error);
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-        };
-    });
-
-    let measurement_ref = MeasurementRef { id };
-    let res_body = serde_json::to_string(&measurement_ref).unwrap();
-
-    HttpResponse::Ok()
-        .content_type(ContentType::json())
-        .body(res_body)
+    queue::enqueue(&app, measurement::Category::Pulse, &params.into_inner()).await
 }
 
 pub async fn pulse_collection_measurement(
     app: web::Data<AppState>,
     params: web::Json<PulseCollectionMeasurementParams>,
 ) -> impl Responder {
-    let guard = utils::measuring_guard(&app);
-    if guard.is_err() {
-        return guard.unwrap_err();
-    }
-
     if params.pulse_train_collection.len() < 1 {
         
         return HttpResponse::BadRequest()
@@ -189,95 +139,5 @@ pub async fn pulse_collection_measurement(
             )
     }
 
-    let params_str = serde_json::to_string(&params).unwrap();
-
-    let measurement = measurement::ActiveModel {
-        status: Set(measurement::Status::InProgress),
-        date: Set(chrono::Local::now()),
-        parameters: Set(Some(Value::from_str(params_str.as_str()).unwrap())),
-        category: Set(measurement::Category::Pulse),
-        ..Default::default()
-    };
-
-    let measurement = measurement.insert(app.db.get_connection()).await;
-    if measurement.is_err() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Could not insert measurement in database.".to_string(),
-                })
-                .to_string(),
-            );
-    }
-
-    let id = measurement.unwrap().id as usize;
-
-    let (tx, rx): (
-        Sender<Result<Vec<Measurement>, measure::Error>>,
-        Receiver<Result<Vec<Measurement>, measure::Error>>,
-    ) = mpsc::channel();
-
-    thread::spawn(move || {
-
-        println!("{:#?}", params);
-
-        let result = measure_pulse_collection_fastiv(
-            Some("b1500gpib"),
-            params.pulse_train_collection.clone(),
-            params.n_points_high,
-            params.n_points_low,
-            params.avg_time,
-            params.noise,
-            params.noise_std
-        );
-
-        tx.send(result).unwrap();
-    });
-
-    spawn(async move {
-        let result = rx.recv().unwrap();
-
-        let measurement = measurement::Entity::find_by_id(id as i32)
-            .one(app.db.get_connection())
-            .await;
-        if measurement.is_err() {
-            return;
-        }
-        let measurement = measurement.unwrap();
-        if measurement.is_none() {
-            return;
-        }
-
-        let mut measurement: measurement::ActiveModel = measurement.unwrap().into();
-
-        match result {
-            Ok(data) => {
-                measurement.status = Set(measurement::Status::Done);
-
-                let data_str = serde_json::to_string(&data).unwrap();
-
-                measurement.data = Set(Some(Value::from_str(data_str.as_str()).unwrap()));
-
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-            Err(err) => {
-                error!("--------------------");
-                error!("--------------------");
-                error!("{:?}", err);
-                error!("--------------------");
-                error!("--------------------");
-
-                measurement.status = Set(measurement::Status::Error);
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-        };
-    });
-
-    let measurement_ref = MeasurementRef { id };
-    let res_body = serde_json::to_string(&measurement_ref).unwrap();
-
-    HttpResponse::Ok()
-        .content_type(ContentType::json())
-        .body(res_body)
+    queue::enqueue(&app, measurement::Category::PulseCollection, &params.into_inner()).await
 }
@@ -0,0 +1,56 @@
+use crate::b1500::measure::{
+    self,
+    pulsed_iv::{measure_pulsed_iv_fastiv, PulsedIvSweep, PulsedIvMeasurement},
+};
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::WgfmuDriver;
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::Submission;
+use entity::measurement::Category;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PulsedIvMeasurementParams {
+    #[serde(flatten)]
+    sweep: PulsedIvSweep,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl PulsedIvMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<PulsedIvMeasurement, measure::Error> {
+        measure_pulsed_iv_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.sweep.clone(),
+        )
+    }
+}
+
+impl Submission for PulsedIvMeasurementParams {
+    const CATEGORY: Category = Category::PulsedIv;
+
+    fn invalid(&self) -> Option<String> {
+        if self.sweep.is_valid(&self.ranges) {
+            return None;
+        }
+        Some(
+            "The sweep needs a positive vMax and a negative vMin within the force range, a positive step, at least \
+             one cycle, pulses of at least 10 ns per point, triangular pulses of at least 20 ns and a positive \
+             threshold or a ratio above 1 to detect the switching."
+                .to_string(),
+        )
+    }
+}
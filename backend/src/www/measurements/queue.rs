@@ -0,0 +1,773 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::header::ContentType;
+use actix_web::rt::time::sleep;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::join;
+use log::{error, info, warn};
+use sea_orm::prelude::DateTimeLocal;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DbErr, EntityTrait, JsonValue, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use entity::chunk;
+use entity::job;
+use entity::measurement::{self, Category, Status};
+
+use crate::b1500::events::MeasurementEvent;
+use crate::b1500::instrument::Lease;
+use crate::b1500::measure::{self, Continuation};
+use crate::b1500::types::Channels;
+use crate::b1500::wgfmu::WgfmuDriver;
+use crate::www::utils;
+use crate::AppState;
+
+use super::endurance::EnduranceMeasurementParams;
+use super::epsc::EpscMeasurementParams;
+use super::ppf::PpfMeasurementParams;
+use super::pulse::{PulseCollectionMeasurementParams, PulseMeasurementParams};
+use super::pulsed_iv::PulsedIvMeasurementParams;
+use super::retention::RetentionMeasurementParams;
+use super::stdp::{StdpCollectionMeasurementParams, StdpMeasurementParams};
+use super::sweep::IvSweepMeasurementParams;
+use super::types::{ErrorJson, MeasurementRef};
+
+// Measurement queue. New measurements are stored as Queued together with a job that gives their place in the queue,
+// a single worker takes the jobs one after another and runs them against the instrument. Both live in the database,
+// so the queue survives a restart of the server. Measurements that run in several sittings, like a retention test,
+// stay in progress between them with a job scheduled for their next sitting. Their data is kept in the chunks until
+// they finish, that way they carry on after a restart too.
+
+/// Time the worker waits before looking again at an empty queue
+const POLL_PERIOD: Duration = Duration::from_secs(1);
+
+/// Chunks of data waiting to be stored, a measurement that gets further ahead waits for the database
+const CHUNK_BACKLOG: usize = 16;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedMeasurement {
+    pub id: i32,
+    pub position: usize,
+    pub date: DateTimeLocal,
+    pub category: Category,
+    pub parameters: Option<JsonValue>,
+    /// When the next sitting of a measurement that is already in progress is due
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTimeLocal>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct JobPosition {
+    pub position: usize,
+}
+
+pub fn error_response(mut res: actix_web::HttpResponseBuilder, error: String) -> HttpResponse {
+    res.content_type(ContentType::json())
+        .body((ErrorJson { error }).to_string())
+}
+
+/// Stores a new measurement with `params` and puts it at the end of the queue.
+pub async fn enqueue<P: Serialize>(app: &AppState, category: Category, params: &P) -> HttpResponse {
+    let id = async {
+        let txn = app.db.get_connection().begin().await?;
+
+        let measurement = measurement::ActiveModel {
+            status: Set(Status::Queued),
+            date: Set(chrono::Local::now()),
+            parameters: Set(Some(serde_json::to_value(params).unwrap())),
+            category: Set(category),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        let last = job::Entity::find()
+            .order_by_desc(job::Column::Position)
+            .one(&txn)
+            .await?;
+
+        job::ActiveModel {
+            measurement: Set(measurement.id),
+            position: Set(last.map_or(0, |job| job.position + 1)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok::<i32, DbErr>(measurement.id)
+    }
+    .await;
+
+    match id {
+        Ok(id) => {
+            info!("Measurement {} queued", id);
+            let res_body = serde_json::to_string(&MeasurementRef { id: id as usize }).unwrap();
+
+            HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .body(res_body)
+        }
+        Err(err) => error_response(
+            HttpResponse::InternalServerError(),
+            format!("Could not queue the measurement {}.", err),
+        ),
+    }
+}
+
+/// Parameters of a measurement submitted to the API, they are checked before the measurement is queued
+pub trait Submission: Serialize + DeserializeOwned {
+    const CATEGORY: Category;
+
+    /// Why the measurement can not be run, None when it can
+    fn invalid(&self) -> Option<String>;
+}
+
+/// Queues the measurement described by `params`, it is rejected with Bad Request when they are not valid.
+pub async fn submit<P: Submission>(app: web::Data<AppState>, params: web::Json<P>) -> HttpResponse {
+    if let Some(error) = params.invalid() {
+        return error_response(HttpResponse::BadRequest(), error);
+    }
+
+    enqueue(&app, P::CATEGORY, &params.into_inner()).await
+}
+
+async fn queued(app: &AppState) -> Result<Vec<QueuedMeasurement>, DbErr> {
+    let jobs = job::Entity::find()
+        .order_by_asc(job::Column::Position)
+        .order_by_asc(job::Column::Id)
+        .all(app.db.get_connection())
+        .await?;
+
+    let measurements = measurement::Entity::find()
+        .filter(measurement::Column::Id.is_in(jobs.iter().map(|job| job.measurement)))
+        .all(app.db.get_connection())
+        .await?;
+
+    Ok(jobs
+        .iter()
+        .enumerate()
+        .filter_map(|(position, job)| {
+            let measurement = measurements.iter().find(|m| m.id == job.measurement)?;
+            Some(QueuedMeasurement {
+                id: measurement.id,
+                position,
+                date: measurement.date,
+                category: measurement.category.clone(),
+                parameters: measurement.parameters.clone(),
+                not_before: job.not_before,
+            })
+        })
+        .collect())
+}
+
+async fn queue_response(app: &AppState) -> HttpResponse {
+    match queued(app).await {
+        Ok(list) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&list).unwrap()),
+        Err(err) => error_response(
+            HttpResponse::InternalServerError(),
+            format!("Could not read the queue {}.", err),
+        ),
+    }
+}
+
+pub async fn list(app: web::Data<AppState>) -> impl Responder {
+    queue_response(&app).await
+}
+
+/// Moves a queued measurement to `position`, 0 being the next one to run.
+pub async fn move_job(
+    app: web::Data<AppState>,
+    id: web::Path<i32>,
+    body: web::Json<JobPosition>,
+) -> impl Responder {
+    let id = id.into_inner();
+
+    let moved = async {
+        let txn = app.db.get_connection().begin().await?;
+
+        let mut jobs = job::Entity::find()
+            .order_by_asc(job::Column::Position)
+            .order_by_asc(job::Column::Id)
+            .all(&txn)
+            .await?;
+
+        let idx = match jobs.iter().position(|job| job.measurement == id) {
+            Some(idx) => idx,
+            None => return Ok(false),
+        };
+        let job = jobs.remove(idx);
+        jobs.insert(usize::min(body.position, jobs.len()), job);
+
+        for (position, job) in jobs.into_iter().enumerate() {
+            if job.position != position as i32 {
+                let mut job: job::ActiveModel = job.into();
+                job.position = Set(position as i32);
+                job.update(&txn).await?;
+            }
+        }
+
+        txn.commit().await?;
+        Ok::<bool, DbErr>(true)
+    }
+    .await;
+
+    match moved {
+        Ok(true) => queue_response(&app).await,
+        Ok(false) => error_response(
+            HttpResponse::Conflict(),
+            format!("Measurement {} is not queued.", id),
+        ),
+        Err(err) => error_response(
+            HttpResponse::InternalServerError(),
+            format!("Could not reorder the queue {}.", err),
+        ),
+    }
+}
+
+/// Removes a measurement from the queue, it is kept as Cancelled.
+pub async fn cancel(app: web::Data<AppState>, id: web::Path<i32>) -> impl Responder {
+    let id = id.into_inner();
+
+    let cancelled = async {
+        let txn = app.db.get_connection().begin().await?;
+
+        // The worker takes jobs by deleting them, whoever deletes the job first owns the measurement. Measurements
+        // waiting for their next sitting already started, they are aborted instead.
+        let deleted = job::Entity::delete_many()
+            .filter(job::Column::Measurement.eq(id))
+            .filter(job::Column::NotBefore.is_null())
+            .exec(&txn)
+            .await?;
+        if deleted.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        if let Some(measurement) = measurement::Entity::find_by_id(id).one(&txn).await? {
+            let mut measurement: measurement::ActiveModel = measurement.into();
+            measurement.status = Set(Status::Cancelled);
+            measurement.update(&txn).await?;
+        }
+
+        txn.commit().await?;
+        Ok::<bool, DbErr>(true)
+    }
+    .await;
+
+    match cancelled {
+        Ok(true) => {
+            info!("Measurement {} cancelled", id);
+            app.instrument
+                .events()
+                .publish(id, MeasurementEvent::Status(Status::Cancelled));
+            HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .body(serde_json::to_string(&MeasurementRef { id: id as usize }).unwrap())
+        }
+        Ok(false) => error_response(
+            HttpResponse::Conflict(),
+            format!("Measurement {} is not queued.", id),
+        ),
+        Err(err) => error_response(
+            HttpResponse::InternalServerError(),
+            format!("Could not cancel the measurement {}.", err),
+        ),
+    }
+}
+
+/// Aborts the running measurement, it is stored as Aborted with the data measured until then.
+pub async fn abort(app: web::Data<AppState>, id: web::Path<i32>) -> impl Responder {
+    let id = id.into_inner();
+
+    let running = match app.instrument.abort(id) {
+        Ok(running) => running,
+        Err(err) => {
+            return error_response(
+                HttpResponse::InternalServerError(),
+                format!("Could not abort the measurement {:?}.", err),
+            )
+        }
+    };
+    if running {
+        return HttpResponse::Accepted()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&MeasurementRef { id: id as usize }).unwrap());
+    }
+
+    match abort_scheduled(&app, id).await {
+        Ok(true) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&MeasurementRef { id: id as usize }).unwrap()),
+        Ok(false) => error_response(
+            HttpResponse::Conflict(),
+            format!("Measurement {} is not running.", id),
+        ),
+        Err(err) => error_response(
+            HttpResponse::InternalServerError(),
+            format!("Could not abort the measurement {}.", err),
+        ),
+    }
+}
+
+/// Aborts a measurement waiting for its next sitting, it is stored as Aborted with the data measured until then.
+/// Returns false when it is not waiting.
+async fn abort_scheduled(app: &AppState, id: i32) -> Result<bool, DbErr> {
+    let deleted = job::Entity::delete_many()
+        .filter(job::Column::Measurement.eq(id))
+        .filter(job::Column::NotBefore.is_not_null())
+        .exec(app.db.get_connection())
+        .await?;
+    if deleted.rows_affected == 0 {
+        return Ok(false);
+    }
+
+    info!("Measurement {} aborted between sittings", id);
+    if let Some(measurement) = measurement::Entity::find_by_id(id).one(app.db.get_connection()).await? {
+        let mut measurement: measurement::ActiveModel = measurement.into();
+        measurement.status = Set(Status::Aborted);
+        measurement.data = Set(stored_chunks(app, id).await?);
+        measurement.update(app.db.get_connection()).await?;
+    }
+    chunk::Entity::delete_by_measurement(id)
+        .exec(app.db.get_connection())
+        .await?;
+    app.instrument
+        .events()
+        .publish(id, MeasurementEvent::Status(Status::Aborted));
+
+    Ok(true)
+}
+
+/// Puts the measurement back at the front of the queue, to go on once `not_before` is reached.
+async fn schedule(app: &AppState, id: i32, not_before: DateTimeLocal) -> Result<(), DbErr> {
+    let first = job::Entity::find()
+        .order_by_asc(job::Column::Position)
+        .one(app.db.get_connection())
+        .await?;
+
+    job::ActiveModel {
+        measurement: Set(id),
+        position: Set(first.map_or(0, |job| job.position - 1)),
+        not_before: Set(Some(not_before)),
+        ..Default::default()
+    }
+    .insert(app.db.get_connection())
+    .await?;
+
+    Ok(())
+}
+
+/// Takes the next job that is due out of the queue, its measurement is marked in progress in the same transaction so
+/// it is never left queued without a job.
+async fn next(app: &AppState) -> Result<Option<measurement::Model>, DbErr> {
+    let txn = app.db.get_connection().begin().await?;
+
+    let job = job::Entity::find()
+        .filter(
+            Condition::any()
+                .add(job::Column::NotBefore.is_null())
+                .add(job::Column::NotBefore.lte(chrono::Local::now())),
+        )
+        .order_by_asc(job::Column::Position)
+        .order_by_asc(job::Column::Id)
+        .one(&txn)
+        .await?;
+
+    let job = match job {
+        Some(job) => job,
+        None => return Ok(None),
+    };
+
+    let deleted = job::Entity::delete_by_id(job.id).exec(&txn).await?;
+    if deleted.rows_affected == 0 {
+        // Cancelled in the meantime
+        return Ok(None);
+    }
+
+    let measurement = measurement::Entity::find_by_id(job.measurement).one(&txn).await?;
+
+    // A scheduled job goes on with a measurement that is already in progress
+    let expected = match job.not_before {
+        Some(_) => Status::InProgress,
+        None => Status::Queued,
+    };
+    let measurement = match measurement.filter(|m| m.status == expected) {
+        Some(measurement) => {
+            let mut measurement: measurement::ActiveModel = measurement.into();
+            measurement.status = Set(Status::InProgress);
+            Some(measurement.update(&txn).await?)
+        }
+        None => None,
+    };
+
+    txn.commit().await?;
+    Ok(measurement)
+}
+
+fn holder(category: &Category) -> &'static str {
+    match category {
+        Category::Pulse => "pulse measurement",
+        Category::PulseCollection => "pulse collection measurement",
+        Category::Stdp => "STDP measurement",
+        Category::StdpCollection => "STDP collection measurement",
+        Category::IvSweep => "I-V sweep",
+        Category::PulsedIv => "pulsed I-V measurement",
+        Category::Endurance => "endurance test",
+        Category::Retention => "retention read",
+        Category::Ppf => "PPF measurement",
+        Category::Epsc => "EPSC measurement",
+    }
+}
+
+/// How a job ended
+enum Outcome {
+    Done(JsonValue),
+    /// Holds the data measured before the abort, null if there is none
+    Aborted(JsonValue),
+    Failed(String),
+    /// The measurement goes on at that time
+    Scheduled(DateTimeLocal),
+}
+
+fn run<P, T, F>(wgfmu: &mut Lease, parameters: JsonValue, channels: Channels, measure: F) -> Outcome
+where
+    P: DeserializeOwned,
+    T: Serialize,
+    F: FnOnce(&P, &mut Lease, Channels) -> Result<T, measure::Error>,
+{
+    let params: P = match serde_json::from_value(parameters) {
+        Ok(params) => params,
+        Err(err) => return Outcome::Failed(format!("Invalid measurement parameters {}", err)),
+    };
+
+    let result = measure(&params, wgfmu, channels).map(Continuation::Done);
+    outcome(wgfmu, result)
+}
+
+/// Same as `run` for the measurements that run in several sittings, `stored` holds the data reported in the previous
+/// ones
+fn resume<P, T, F>(
+    wgfmu: &mut Lease,
+    parameters: JsonValue,
+    channels: Channels,
+    stored: Vec<JsonValue>,
+    measure: F,
+) -> Outcome
+where
+    P: DeserializeOwned,
+    T: Serialize,
+    F: FnOnce(&P, &mut Lease, Channels, Vec<JsonValue>) -> Result<Continuation<T>, measure::Error>,
+{
+    let params: P = match serde_json::from_value(parameters) {
+        Ok(params) => params,
+        Err(err) => return Outcome::Failed(format!("Invalid measurement parameters {}", err)),
+    };
+
+    let result = measure(&params, wgfmu, channels, stored);
+    outcome(wgfmu, result)
+}
+
+fn outcome<T: Serialize>(wgfmu: &mut Lease, result: Result<Continuation<T>, measure::Error>) -> Outcome {
+    match result {
+        Ok(Continuation::Done(data)) => match serde_json::to_value(data) {
+            Ok(data) => Outcome::Done(data),
+            Err(err) => Outcome::Failed(err.to_string()),
+        },
+        Ok(Continuation::Later(not_before)) => Outcome::Scheduled(not_before),
+        Err(measure::Error::Aborted(data)) => Outcome::Aborted(data),
+        Err(err) => Outcome::Failed(describe(wgfmu, err)),
+    }
+}
+
+/// The error of a failed measurement, followed by the errors and warnings the WGFMU library reported
+fn describe(wgfmu: &mut Lease, err: measure::Error) -> String {
+    utils::with_reported(err.to_string(), &wgfmu.reported())
+}
+
+/// Runs the measurement, on `channels` unless its parameters pick other ones
+fn measure(
+    wgfmu: &mut Lease,
+    category: &Category,
+    parameters: JsonValue,
+    channels: Channels,
+    stored: Vec<JsonValue>,
+) -> Outcome {
+    match category {
+        Category::Pulse => run(wgfmu, parameters, channels, PulseMeasurementParams::measure),
+        Category::PulseCollection => run(wgfmu, parameters, channels, PulseCollectionMeasurementParams::measure),
+        Category::Stdp => run(wgfmu, parameters, channels, StdpMeasurementParams::measure),
+        Category::StdpCollection => run(wgfmu, parameters, channels, StdpCollectionMeasurementParams::measure),
+        Category::IvSweep => run(wgfmu, parameters, channels, IvSweepMeasurementParams::measure),
+        Category::PulsedIv => run(wgfmu, parameters, channels, PulsedIvMeasurementParams::measure),
+        Category::Endurance => run(wgfmu, parameters, channels, EnduranceMeasurementParams::measure),
+        Category::Retention => resume(wgfmu, parameters, channels, stored, RetentionMeasurementParams::measure),
+        Category::Ppf => run(wgfmu, parameters, channels, PpfMeasurementParams::measure),
+        Category::Epsc => run(wgfmu, parameters, channels, EpscMeasurementParams::measure),
+    }
+}
+
+async fn run_job(app: &AppState, measurement: measurement::Model) -> Result<(), DbErr> {
+    let id = measurement.id;
+    let category = measurement.category.clone();
+    let parameters = measurement.parameters.clone().unwrap_or(JsonValue::Null);
+
+    // Already marked in progress when it was taken out of the queue
+    app.instrument
+        .events()
+        .publish(id, MeasurementEvent::Status(Status::InProgress));
+
+    info!("Running measurement {}", id);
+
+    // Data stored by the previous sittings, the new chunks go after it
+    let stored = chunks_of(app, id).await?;
+    let first_sequence = stored.len() as i32;
+
+    let (sink, mut chunks) = mpsc::channel(CHUNK_BACKLOG);
+    let store = async {
+        let mut sequence = first_sequence;
+        while let Some(data) = chunks.recv().await {
+            let chunk = chunk::ActiveModel {
+                measurement: Set(id),
+                sequence: Set(sequence),
+                data: Set(data),
+                ..Default::default()
+            };
+            if let Err(err) = chunk.insert(app.db.get_connection()).await {
+                error!("Could not store data of measurement {} {}", id, err);
+            }
+            sequence += 1;
+        }
+    };
+
+    let instrument = Arc::clone(&app.instrument);
+    let channels = app.cfg.channels;
+    let measure = web::block(move || {
+        let mut wgfmu = match instrument.lease(holder(&category)) {
+            Ok(lease) => lease,
+            Err(err) => return Outcome::Failed(format!("Could not lease the instrument {:?}", err)),
+        };
+        wgfmu.set_measurement(id);
+        wgfmu.set_sink(sink);
+        measure(&mut wgfmu, &category, parameters, channels, stored)
+    });
+    // The sink is dropped together with the lease, which ends the storing
+    let (result, ()) = join!(measure, store);
+
+    let mut measurement: measurement::ActiveModel = measurement.into();
+    match result {
+        Ok(Outcome::Scheduled(not_before)) => {
+            // Still in progress, the data stays in the chunks until the last sitting
+            info!("Measurement {} goes on at {}", id, not_before);
+            return schedule(app, id, not_before).await;
+        }
+        Ok(Outcome::Done(data)) => {
+            measurement.status = Set(Status::Done);
+            measurement.data = Set(Some(data));
+        }
+        Ok(Outcome::Aborted(data)) => {
+            info!("Measurement {} aborted", id);
+            measurement.status = Set(Status::Aborted);
+            measurement.data = match data {
+                JsonValue::Null => Set(stored_chunks(app, id).await?),
+                data => Set(Some(data)),
+            };
+        }
+        Ok(Outcome::Failed(err)) => {
+            error!("Measurement {} failed: {}", id, err);
+            measurement.status = Set(Status::Error);
+            measurement.data = Set(stored_chunks(app, id).await?);
+            measurement.error = Set(Some(err));
+        }
+        Err(err) => {
+            error!("Measurement {} failed, actix blocking error {}", id, err);
+            measurement.status = Set(Status::Error);
+            measurement.data = Set(stored_chunks(app, id).await?);
+            measurement.error = Set(Some(format!("Actix blocking error {}", err)));
+        }
+    }
+    let measurement = measurement.update(app.db.get_connection()).await?;
+    chunk::Entity::delete_by_measurement(id)
+        .exec(app.db.get_connection())
+        .await?;
+    app.instrument
+        .events()
+        .publish(id, MeasurementEvent::Status(measurement.status));
+
+    Ok(())
+}
+
+/// The data a measurement stored while it was running, in the order it was acquired.
+async fn stored_chunks(app: &AppState, id: i32) -> Result<Option<JsonValue>, DbErr> {
+    let chunks = chunks_of(app, id).await?;
+
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(JsonValue::Array(chunks)))
+}
+
+async fn chunks_of(app: &AppState, id: i32) -> Result<Vec<JsonValue>, DbErr> {
+    let chunks = chunk::Entity::find()
+        .filter(chunk::Column::Measurement.eq(id))
+        .order_by_asc(chunk::Column::Sequence)
+        .all(app.db.get_connection())
+        .await?;
+
+    Ok(chunks.into_iter().map(|chunk| chunk.data).collect())
+}
+
+/// Measurements left in progress were interrupted by a restart, they can not be resumed. The ones waiting for their
+/// next sitting were not running, they go on when it is due.
+async fn recover(app: &AppState) -> Result<(), DbErr> {
+    let scheduled = job::Entity::find()
+        .filter(job::Column::NotBefore.is_not_null())
+        .all(app.db.get_connection())
+        .await?;
+
+    let interrupted = measurement::Entity::find()
+        .filter(measurement::Column::Status.eq(Status::InProgress))
+        .filter(measurement::Column::Id.is_not_in(scheduled.iter().map(|job| job.measurement)))
+        .all(app.db.get_connection())
+        .await?;
+
+    for measurement in interrupted {
+        let id = measurement.id;
+        warn!("Measurement {} was interrupted", id);
+        let mut measurement: measurement::ActiveModel = measurement.into();
+        measurement.status = Set(Status::Error);
+        measurement.error = Set(Some("Interrupted by a restart of the server".to_string()));
+        // Keeping what was acquired before the interruption
+        measurement.data = Set(stored_chunks(app, id).await?);
+        measurement.update(app.db.get_connection()).await?;
+        chunk::Entity::delete_by_measurement(id)
+            .exec(app.db.get_connection())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Runs the queued measurements one after another, forever.
+pub async fn worker(app: AppState) {
+    if let Err(err) = recover(&app).await {
+        error!("Could not recover interrupted measurements {}", err);
+    }
+
+    loop {
+        match next(&app).await {
+            Ok(Some(measurement)) => {
+                if let Err(err) = run_job(&app, measurement).await {
+                    error!("Could not store the measurement result {}", err);
+                }
+            }
+            Ok(None) => sleep(POLL_PERIOD).await,
+            Err(err) => {
+                error!("Could not read the queue {}", err);
+                sleep(POLL_PERIOD).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ConnectionTrait, DbBackend, Schema};
+
+    use super::*;
+    use crate::b1500::instrument::InstrumentManager;
+    use crate::b1500::wgfmu::fault::{Call, Fault, FaultRule, FaultyWgfmu};
+    use crate::b1500::wgfmu::sim::TestWgfmu;
+    use crate::b1500::wgfmu::Error;
+    use crate::config::Config;
+    use crate::db::Database;
+
+    /// Application state on an in-memory database, with a simulated instrument that fails as `rules` say
+    async fn app(rules: Vec<FaultRule>) -> AppState {
+        let connection = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DbBackend::Sqlite);
+        let tables = [
+            schema.create_table_from_entity(measurement::Entity),
+            schema.create_table_from_entity(job::Entity),
+            schema.create_table_from_entity(chunk::Entity),
+        ];
+        for table in tables.iter() {
+            connection.execute(DbBackend::Sqlite.build(table)).await.unwrap();
+        }
+
+        let cfg = Config::default();
+        let driver = FaultyWgfmu::new(TestWgfmu::new(cfg.memristor.clone()).unwrap(), rules);
+        AppState {
+            db: Database { connection },
+            cfg,
+            instrument: InstrumentManager::new(Box::new(driver)),
+        }
+    }
+
+    #[actix_web::test]
+    async fn taken_job_is_in_progress() {
+        let app = app(vec![]).await;
+        assert!(enqueue(&app, Category::Pulse, &serde_json::json!({})).await.status().is_success());
+
+        let taken = next(&app).await.unwrap().unwrap();
+        let stored = measurement::Entity::find_by_id(taken.id)
+            .one(app.db.get_connection())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.status, Status::InProgress);
+        assert!(job::Entity::find().all(app.db.get_connection()).await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn not_queued_conflicts() {
+        let app = app(vec![]).await;
+        assert!(enqueue(&app, Category::Pulse, &serde_json::json!({})).await.status().is_success());
+        let id = next(&app).await.unwrap().unwrap().id;
+
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        let data = web::Data::new(app.clone());
+        let cancelled = cancel(data.clone(), web::Path::from(id)).await.respond_to(&req);
+        let moved = move_job(data, web::Path::from(id), web::Json(JobPosition { position: 0 }))
+            .await
+            .respond_to(&req);
+
+        assert_eq!(cancelled.status(), actix_web::http::StatusCode::CONFLICT);
+        assert_eq!(moved.status(), actix_web::http::StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn failed_measurement_stores_the_error() {
+        let app = app(vec![FaultRule {
+            call: Call::Execute,
+            nth: Some(1),
+            fault: Fault::Error(Error::CommunicationError),
+        }])
+        .await;
+
+        let params = serde_json::json!({
+            "avgTime": 1e-7, "vHigh": 1.0, "vLow": 0.0, "cycleTime": 1e-4, "nPulses": 3, "dutyCycle": 0.5,
+            "nPointsHigh": 5, "nPointsLow": 5, "noise": false, "noiseStd": 0.0
+        });
+        assert!(enqueue(&app, Category::Pulse, &params).await.status().is_success());
+
+        let queued = next(&app).await.unwrap().unwrap();
+        let id = queued.id;
+        run_job(&app, queued).await.unwrap();
+
+        let stored = measurement::Entity::find_by_id(id)
+            .one(app.db.get_connection())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.status, Status::Error);
+        assert!(stored.error.unwrap().contains(&Error::CommunicationError.to_string()));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::b1500::measure::{
+    self,
+    retention::{measure_retention_fastiv, Retention, RetentionMeasurement, RetentionRead},
+    Continuation,
+};
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::WgfmuDriver;
+
+use sea_orm::JsonValue;
+use serde::{Deserialize, Serialize};
+
+use super::queue::Submission;
+use entity::measurement::Category;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionMeasurementParams {
+    #[serde(flatten)]
+    retention: Retention,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl RetentionMeasurementParams {
+    /// Goes on with the test, `stored` holds the reads taken in the previous sittings
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+        stored: Vec<JsonValue>,
+    ) -> Result<Continuation<RetentionMeasurement>, measure::Error> {
+        let reads = stored
+            .into_iter()
+            .filter_map(|read| serde_json::from_value::<RetentionRead>(read).ok())
+            .collect::<Vec<RetentionRead>>();
+
+        measure_retention_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            &self.retention,
+            &reads,
+        )
+    }
+}
+
+impl Submission for RetentionMeasurementParams {
+    const CATEGORY: Category = Category::Retention;
+
+    fn invalid(&self) -> Option<String> {
+        if self.retention.is_valid() {
+            return None;
+        }
+        Some(
+            "The test needs a positive firstRead, a lastRead after it, from 1 to 100 reads per decade and programming \
+             pulses of at least 10 ns within 10 V."
+                .to_string(),
+        )
+    }
+}
@@ -1,7 +1,4 @@
-use log::{error, info};
-use std::str::FromStr;
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::thread;
+use log::info;
 
 use crate::b1500::measure::{
     self,
@@ -11,18 +8,18 @@ use crate::b1500::measure::{
     },
     utils::measure_conductance_fastiv,
 };
+use crate::b1500::types::{Channels, CurrentRanges, Ranges};
+use crate::b1500::wgfmu::WgfmuDriver;
 use crate::AppState;
+use crate::www::utils;
 use actix_web::body::BoxBody;
 use actix_web::http::header::ContentType;
-use actix_web::rt::spawn;
 use actix_web::{web, HttpResponse, Responder};
 
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 
-use entity::sea_orm::{ActiveModelTrait, Set};
-
-use super::types::{ErrorJson, MeasurementRef};
+use super::queue;
+use super::types::ErrorJson;
 use entity::measurement;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -37,6 +34,41 @@ pub struct StdpMeasurementParams {
     avg_time: f64,
     noise: bool,
     noise_std: f64,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+    /// Current ranges to switch between during the pulses and the rest of the measurement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current_ranges: Option<CurrentRanges>,
+}
+
+impl StdpMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<StdpMeasurement, measure::Error> {
+        measure_stdp_fastiv(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.current_ranges,
+            self.delay,
+            self.amplitude,
+            self.pulse_duration,
+            self.wait_time,
+            self.n_points,
+            self.avg_time,
+            self.stdp_type,
+            self.noise,
+            self.noise_std,
+            true,
+        )
+    }
 }
 
 impl Responder for StdpMeasurementParams {
@@ -55,110 +87,7 @@ pub async fn stdp_measurement(
     app: web::Data<AppState>,
     params: web::Json<StdpMeasurementParams>,
 ) -> impl Responder {
-    // let res_body = serde_json::to_string(&params).unwrap();
-
-    if app.measuring {
-        return HttpResponse::Conflict()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Another measurement is already in progress.".to_string(),
-                })
-                .to_string(),
-            );
-    }
-
-    let params_str = serde_json::to_string(&params).unwrap();
-
-    let measurement = measurement::ActiveModel {
-        status: Set(measurement::Status::InProgress),
-        date: Set(chrono::Local::now()),
-        parameters: Set(Some(Value::from_str(params_str.as_str()).unwrap())),
-        category: Set(measurement::Category::Stdp),
-        ..Default::default()
-    };
-
-    let measurement = measurement.insert(app.db.get_connection()).await;
-    if measurement.is_err() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Could not insert measurement in database.".to_string(),
-                })
-                .to_string(),
-            );
-    }
-
-    let id = measurement.unwrap().id as usize;
-
-    let (tx, rx): (
-        Sender<Result<StdpMeasurement, measure::Error>>,
-        Receiver<Result<StdpMeasurement, measure::Error>>,
-    ) = mpsc::channel();
-
-    thread::spawn(move || {
-        let result = measure_stdp_fastiv(
-            Some("b1500gpib"),
-            params.delay,
-            params.amplitude,
-            params.pulse_duration,
-            params.wait_time,
-            params.n_points,
-            params.avg_time,
-            params.stdp_type,
-            params.noise,
-            params.noise_std,
-        );
-
-        tx.send(result).unwrap();
-    });
-
-    spawn(async move {
-        let result = rx.recv().unwrap();
-
-        let measurement = measurement::Entity::find_by_id(id as i32)
-            .one(app.db.get_connection())
-            .await;
-        if measurement.is_err() {
-            return;
-        }
-        let measurement = measurement.unwrap();
-        if measurement.is_none() {
-            return;
-        }
-
-        let mut measurement: measurement::ActiveModel = measurement.unwrap().into();
-
-        match result {
-            Ok(data) => {
-                measurement.status = Set(measurement::Status::Done);
-
-                let data_str = serde_json::to_string(&data).unwrap();
-
-                measurement.data = Set(Some(Value::from_str(data_str.as_str()).unwrap()));
-
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-            Err(err) => {
-                error!("--------------------");
-                error!("--------------------");
-                error!("{:?}", err);
-                error!("--------------------");
-                error!("--------------------");
-
-                measurement.status = Set(measurement::Status::Error);
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-        };
-    });
-
-    let measurement_ref = MeasurementRef { id };
-    let res_body = serde_json::to_string(&measurement_ref).unwrap();
-
-    HttpResponse::Ok()
-        .content_type(ContentType::json())
-        .body(res_body)
+    queue::enqueue(&app, measurement::Category::Stdp, &params.into_inner()).await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -173,6 +102,41 @@ pub struct StdpCollectionMeasurementParams {
     avg_time: f64,
     noise: bool,
     noise_std: f64,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+    /// Current ranges to switch between during the pulses and the rest of the measurement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current_ranges: Option<CurrentRanges>,
+}
+
+impl StdpCollectionMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<StdpCollectionMeasurement, measure::Error> {
+        measure_stdp_collection_fastiv(
+            wgfmu,
+            "b1500gpib",
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.current_ranges,
+            self.delay_points,
+            self.amplitude,
+            self.wait_time,
+            self.pulse_duration,
+            self.stdp_type,
+            self.n_points,
+            self.avg_time,
+            self.noise,
+            self.noise_std,
+            StdpCollectionMeasMode::ForceConductanceMeasurement,
+        )
+    }
 }
 
 impl Responder for StdpCollectionMeasurementParams {
@@ -191,111 +155,7 @@ pub async fn stdp_collection_measurement(
     app: web::Data<AppState>,
     params: web::Json<StdpCollectionMeasurementParams>,
 ) -> impl Responder {
-    // let res_body = serde_json::to_string(&params).unwrap();
-
-    if app.measuring {
-        return HttpResponse::Conflict()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Another measurement is already in progress.".to_string(),
-                })
-                .to_string(),
-            );
-    }
-
-    let params_str = serde_json::to_string(&params).unwrap();
-
-    let measurement = measurement::ActiveModel {
-        status: Set(measurement::Status::InProgress),
-        date: Set(chrono::Local::now()),
-        parameters: Set(Some(Value::from_str(params_str.as_str()).unwrap())),
-        category: Set(measurement::Category::StdpCollection),
-        ..Default::default()
-    };
-
-    let measurement = measurement.insert(app.db.get_connection()).await;
-    if measurement.is_err() {
-        return HttpResponse::InternalServerError()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Could not insert measurement in database.".to_string(),
-                })
-                .to_string(),
-            );
-    }
-
-    let id = measurement.unwrap().id as usize;
-
-    let (tx, rx): (
-        Sender<Result<StdpCollectionMeasurement, measure::Error>>,
-        Receiver<Result<StdpCollectionMeasurement, measure::Error>>,
-    ) = mpsc::channel();
-
-    thread::spawn(move || {
-        let result = measure_stdp_collection_fastiv(
-            "b1500gpib",
-            params.delay_points,
-            params.amplitude,
-            params.wait_time,
-            params.pulse_duration,
-            params.stdp_type,
-            params.n_points,
-            params.avg_time,
-            params.noise,
-            params.noise_std,
-            StdpCollectionMeasMode::ForceConductanceMeasurement,
-        );
-
-        tx.send(result).unwrap();
-    });
-
-    spawn(async move {
-        let result = rx.recv().unwrap();
-
-        let measurement = measurement::Entity::find_by_id(id as i32)
-            .one(app.db.get_connection())
-            .await;
-        if measurement.is_err() {
-            return;
-        }
-        let measurement = measurement.unwrap();
-        if measurement.is_none() {
-            return;
-        }
-
-        let mut measurement: measurement::ActiveModel = measurement.unwrap().into();
-
-        match result {
-            Ok(data) => {
-                measurement.status = Set(measurement::Status::Done);
-
-                let data_str = serde_json::to_string(&data).unwrap();
-
-                measurement.data = Set(Some(Value::from_str(data_str.as_str()).unwrap()));
-
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-            Err(err) => {
-                error!("--------------------");
-                error!("--------------------");
-                error!("{:?}", err);
-                error!("--------------------");
-                error!("--------------------");
-
-                measurement.status = Set(measurement::Status::Error);
-                measurement.update(app.db.get_connection()).await.unwrap();
-            }
-        };
-    });
-
-    let measurement_ref = MeasurementRef { id };
-    let res_body = serde_json::to_string(&measurement_ref).unwrap();
-
-    HttpResponse::Ok()
-        .content_type(ContentType::json())
-        .body(res_body)
+    queue::enqueue(&app, measurement::Category::StdpCollection, &params.into_inner()).await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -303,21 +163,17 @@ pub async fn stdp_collection_measurement(
 pub struct Conductance {
     conductance: f64,
 }
-pub async fn conductance_measurement(app: web::Data<AppState>) -> impl Responder {
+pub async fn conductance_measurement(app: web::Data<AppState>, ranges: web::Query<Ranges>) -> impl Responder {
     // let res_body = serde_json::to_string(&params).unwrap();
 
-    if app.measuring {
-        return HttpResponse::Conflict()
-            .content_type(ContentType::json())
-            .body(
-                (ErrorJson {
-                    error: "Another measurement is already in progress.".to_string(),
-                })
-                .to_string(),
-            );
-    }
+    let mut wgfmu = match utils::lease_instrument(&app, "conductance measurement") {
+        Ok(lease) => lease,
+        Err(err) => return err.response(),
+    };
 
-    let result = match web::block(move || measure_conductance_fastiv(Some("b1500gpib"))).await {
+    let channels = app.cfg.channels;
+    let ranges = ranges.into_inner();
+    let result = match web::block(move || measure_conductance_fastiv(&mut wgfmu, Some("b1500gpib"), channels, ranges)).await {
         Ok(res) => res,
         Err(err) => {
             return HttpResponse::InternalServerError()
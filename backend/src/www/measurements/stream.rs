@@ -0,0 +1,77 @@
+use std::convert::Infallible;
+
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::stream::{self, StreamExt};
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+
+use entity::measurement;
+
+use crate::b1500::events::MeasurementEvent;
+use crate::AppState;
+
+use super::queue::error_response;
+
+/// Streams the status, progress and partial data of a measurement as server-sent events, until it finishes.
+pub async fn stream(app: web::Data<AppState>, id: web::Path<i32>) -> impl Responder {
+    let id = id.into_inner();
+
+    // Subscribing before reading the row, so nothing published in between gets lost
+    let events = app.instrument.events().subscribe();
+
+    let measurement = match measurement::Entity::find_by_id(id)
+        .one(app.db.get_connection())
+        .await
+    {
+        Ok(Some(measurement)) => measurement,
+        Ok(None) => {
+            return error_response(
+                HttpResponse::NotFound(),
+                format!("Measurement {} does not exist.", id),
+            )
+        }
+        Err(err) => {
+            return error_response(
+                HttpResponse::InternalServerError(),
+                format!("Could not read the measurement {}.", err),
+            )
+        }
+    };
+
+    let mut current = vec![MeasurementEvent::Status(measurement.status)];
+    if let Ok(Some(progress)) = app.instrument.progress(id) {
+        current.push(MeasurementEvent::Progress(progress));
+    }
+    let finished = current[0].is_final();
+
+    let live = stream::unfold((events, finished), move |(mut events, finished)| async move {
+        if finished {
+            return None;
+        }
+
+        loop {
+            match events.recv().await {
+                Ok((measurement, event)) if measurement == id => {
+                    let finished = event.is_final();
+                    return Some((event, (events, finished)));
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Stream of measurement {} fell behind, {} events skipped", id, skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let body = stream::iter(current)
+        .chain(live)
+        .map(|event| Ok::<Bytes, Infallible>(Bytes::from(event.to_sse())));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(body)
+}
@@ -0,0 +1,51 @@
+use crate::b1500::measure::{
+    self,
+    sweep::{measure_iv_sweep, IvSweep},
+};
+use crate::b1500::types::{Channels, Ranges};
+use crate::b1500::wgfmu::{driver::Measurement, WgfmuDriver};
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::Submission;
+use entity::measurement::Category;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IvSweepMeasurementParams {
+    #[serde(flatten)]
+    sweep: IvSweep,
+    /// Channels to use instead of the configured ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<Channels>,
+    /// Ranges and delays to set, the instrument defaults otherwise
+    #[serde(default)]
+    ranges: Ranges,
+}
+
+impl IvSweepMeasurementParams {
+    pub fn measure<D: WgfmuDriver + ?Sized>(
+        &self,
+        wgfmu: &mut D,
+        default_channels: Channels,
+    ) -> Result<Vec<Measurement>, measure::Error> {
+        measure_iv_sweep(
+            wgfmu,
+            Some("b1500gpib"),
+            self.channels.unwrap_or(default_channels),
+            self.ranges,
+            self.sweep.clone(),
+        )
+    }
+}
+
+impl Submission for IvSweepMeasurementParams {
+    const CATEGORY: Category = Category::IvSweep;
+
+    fn invalid(&self) -> Option<String> {
+        if self.sweep.voltages().is_some() {
+            return None;
+        }
+        Some("The sweep needs a positive step, finite voltages and at least one cycle.".to_string())
+    }
+}
@@ -10,6 +10,24 @@ pub fn register_urls(cfg: &mut web::ServiceConfig) {
         web::resource("/stdp-collection")
             .route(web::post().to(super::stdp::stdp_collection_measurement)),
     );
+    cfg.service(
+        web::resource("/iv-sweep").route(web::post().to(super::queue::submit::<super::sweep::IvSweepMeasurementParams>)),
+    );
+    cfg.service(
+        web::resource("/pulsed-iv").route(web::post().to(super::queue::submit::<super::pulsed_iv::PulsedIvMeasurementParams>)),
+    );
+    cfg.service(
+        web::resource("/endurance").route(web::post().to(super::queue::submit::<super::endurance::EnduranceMeasurementParams>)),
+    );
+    cfg.service(
+        web::resource("/retention").route(web::post().to(super::queue::submit::<super::retention::RetentionMeasurementParams>)),
+    );
+    cfg.service(
+        web::resource("/ppf").route(web::post().to(super::queue::submit::<super::ppf::PpfMeasurementParams>)),
+    );
+    cfg.service(
+        web::resource("/epsc").route(web::post().to(super::queue::submit::<super::epsc::EpscMeasurementParams>)),
+    );
     cfg.service(
         web::resource("/conductance").route(web::post().to(super::stdp::conductance_measurement)),
     );
@@ -17,6 +35,12 @@ pub fn register_urls(cfg: &mut web::ServiceConfig) {
     // Main
     cfg.service(web::resource("/").route(web::get().to(super::measurements::list)));
 
+    // Abort
+    cfg.service(web::resource("/{id}/abort").route(web::post().to(super::queue::abort)));
+
+    // Live events
+    cfg.service(web::resource("/{id}/stream").route(web::get().to(super::stream::stream)));
+
     // Retrieve
     cfg.service(web::resource("/{id}").route(web::get().to(super::measurements::get_single)));
     cfg.service(
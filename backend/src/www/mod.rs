@@ -1,3 +1,4 @@
+pub mod instrument;
 pub mod measurements;
 
 pub mod urls;
@@ -1,11 +1,21 @@
 use crate::AppState;
 
-use super::measurements::calibrate;
+use super::instrument;
+use super::measurements::{calibrate, queue};
 use actix_web::{web, Responder, HttpResponse, http::header::ContentType};
 
 pub fn register_urls(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/measurements").configure(super::measurements::register_urls));
 
+    // Queue
+    cfg.service(web::resource("/queue").route(web::get().to(queue::list)));
+    cfg.service(web::resource("/queue/{id}").route(web::delete().to(queue::cancel)));
+    cfg.service(web::resource("/queue/{id}/position").route(web::put().to(queue::move_job)));
+
+    // Instrument
+    cfg.service(web::resource("/instrument/status").route(web::get().to(instrument::status)));
+    cfg.service(web::resource("/instrument/channels").route(web::get().to(instrument::channels)));
+
     // Other
     cfg.service(web::resource("/calibrate").route(web::post().to(calibrate::calibrate)));
     cfg.service(web::resource("/ping").route(web::get().to(pong)));
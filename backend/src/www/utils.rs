@@ -1,21 +1,56 @@
 use actix_web::{http::header::ContentType, web, HttpResponse};
 
+use crate::b1500::instrument::Lease;
 use crate::AppState;
 
 use super::measurements::types::ErrorJson;
 
-pub fn measuring_guard(app: &web::Data<AppState>) -> Result<(), HttpResponse> {
-    if app.measuring {
-        return Result::Err(
-            HttpResponse::Conflict()
-                .content_type(ContentType::json())
-                .body(
-                    (ErrorJson {
-                        error: "Another measurement is already in progress.".to_string(),
-                    })
-                    .to_string(),
-                ),
-        );
+/// Why the instrument could not be leased
+#[derive(Debug)]
+pub enum LeaseError {
+    /// Someone else is using it, the holder when known
+    Busy(Option<String>),
+    Failed(String),
+}
+
+impl LeaseError {
+    /// Conflict when the instrument is busy, Internal Server Error otherwise
+    pub fn response(&self) -> HttpResponse {
+        let (mut res, error) = match self {
+            LeaseError::Busy(Some(holder)) => (
+                HttpResponse::Conflict(),
+                format!("The instrument is busy with {}.", holder),
+            ),
+            LeaseError::Busy(None) => (HttpResponse::Conflict(), "The instrument is busy.".to_string()),
+            LeaseError::Failed(err) => (
+                HttpResponse::InternalServerError(),
+                format!("Could not lease the instrument {}.", err),
+            ),
+        };
+
+        res.content_type(ContentType::json())
+            .body((ErrorJson { error }).to_string())
+    }
+}
+
+/// Leases the instrument to `holder`, it fails with `LeaseError::Busy` when someone else is using it.
+pub fn lease_instrument(app: &web::Data<AppState>, holder: &str) -> Result<Lease, LeaseError> {
+    match app.instrument.try_lease(holder) {
+        Ok(Some(lease)) => Ok(lease),
+        Ok(None) => {
+            let holder = app.instrument.status().ok().and_then(|status| status.holder);
+            Result::Err(LeaseError::Busy(holder.map(|holder| holder.name)))
+        }
+        Err(err) => Result::Err(LeaseError::Failed(format!("{:?}", err))),
     }
-    Ok(())
+}
+
+/// `error` followed by the errors and warnings the WGFMU library reported, one per line
+pub fn with_reported(mut error: String, reported: &str) -> String {
+    if !reported.is_empty() {
+        error.push('\n');
+        error.push_str(reported);
+    }
+
+    error
 }